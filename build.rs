@@ -0,0 +1,165 @@
+// Compiles every shader in shaders/ to SPIR-V and embeds it, so the binary doesn't depend on the working directory.
+// GLSL goes through glslc, as it keeps specialization constants where naga would bake them in, and WGSL through naga-cli.
+// Without those installed the SPIR-V checked in under shaders/out is embedded instead, as long as the hash stored
+// next to it still matches the source. `just compile-shaders` rebuilds both.
+// The sources are embedded too, so shader variants can be compiled at runtime with the naga feature
+use std::{env, fmt::Write, fs, io, path::{Path, PathBuf}, process::{self, Command}};
+
+const SOURCE_EXTENSIONS: [&str; 5] = ["vert", "frag", "comp", "wgsl", "glsl"];
+
+// Set by `just compile-shaders` to copy what was compiled into shaders/out
+const UPDATE_VAR: &str = "VULKRUST_UPDATE_SHADERS";
+
+fn main() {
+    println!("cargo::rerun-if-changed=shaders");
+    for var in ["GLSLC", "NAGA", UPDATE_VAR] {
+        println!("cargo::rerun-if-env-changed={var}");
+    }
+
+    let root = Path::new("shaders");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let update = env::var_os(UPDATE_VAR).is_some();
+
+    let mut names = vec![];
+    find_sources(root, root, &mut names);
+    names.sort();
+
+    let mut sources = String::from("// Generated by build.rs\npub static EMBEDDED_SOURCES: &[(&str, &str)] = &[\n");
+    let mut table = String::from("pub static EMBEDDED: &[(&str, &[u8])] = &[\n");
+
+    for name in &names {
+        let path = fs::canonicalize(root.join(name)).unwrap();
+        writeln!(sources, "    ({name:?}, include_str!({:?})),", path.display().to_string()).unwrap();
+
+        // Only there to be included by the others
+        if name.ends_with(".glsl") {
+            continue;
+        }
+
+        let hash = format!("{:016x}\n", source_hash(root, name));
+        let checked_in = root.join("out").join(format!("{name}.spv"));
+        let hash_file = root.join("out").join(format!("{name}.spv.hash"));
+        let compiled = out_dir.join("spv").join(format!("{name}.spv"));
+
+        let spv = match compile(root, name, &compiled) {
+            Ok(true) => {
+                if update {
+                    write_if_changed(&checked_in, &fs::read(&compiled).unwrap());
+                    write_if_changed(&hash_file, hash.as_bytes());
+                }
+                compiled
+            },
+            Ok(false) if update => fail(&format!("Cannot compile {name}, {} is not installed", compiler(name).0)),
+            Ok(false) => {
+                if !checked_in.exists() {
+                    fail(&format!("{} is missing and {} is not installed to build it", checked_in.display(), compiler(name).0));
+                }
+                if fs::read_to_string(&hash_file).ok().as_deref() != Some(hash.as_str()) {
+                    fail(&format!(
+                        "{} is out of date with {name}. Install {} and run `just compile-shaders`",
+                        checked_in.display(),
+                        compiler(name).0
+                    ));
+                }
+                checked_in
+            },
+            Err(e) => fail(&format!("Cannot compile {name}: {e}")),
+        };
+
+        let spv = fs::canonicalize(spv).unwrap();
+        writeln!(table, "    ({name:?}, include_bytes!({:?})),", spv.display().to_string()).unwrap();
+    }
+
+    sources.push_str("];\n");
+    table.push_str("];\n");
+    fs::write(out_dir.join("shaders.rs"), sources + &table).unwrap();
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
+
+// The program that compiles a shader, with the environment variable that overrides where it is
+fn compiler(name: &str) -> (&'static str, &'static str) {
+    if name.ends_with(".wgsl") { ("naga", "NAGA") } else { ("glslc", "GLSLC") }
+}
+
+// False when the compiler isn't installed
+fn compile(root: &Path, name: &str, output: &Path) -> Result<bool, String> {
+    let (program, var) = compiler(name);
+    let program = env::var_os(var).unwrap_or_else(|| program.into());
+    fs::create_dir_all(output.parent().unwrap()).unwrap();
+
+    let mut command = Command::new(program);
+    if name.ends_with(".wgsl") {
+        command.arg(root.join(name)).arg(output);
+    } else {
+        // Includes are named relative to shaders/, as the runtime preprocessor expects
+        command.arg("-I").arg(root).arg(root.join(name)).arg("-o").arg(output);
+    }
+
+    match command.output() {
+        Ok(result) if result.status.success() => Ok(true),
+        Ok(result) => Err(String::from_utf8_lossy(&result.stderr).into_owned()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// FNV-1a over a shader and everything it includes, in the order they are first included.
+// Only needs to notice edits, and is simple enough to reproduce without this script
+fn source_hash(root: &Path, name: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut seen = vec![];
+    let mut pending = vec![name.to_string()];
+
+    while let Some(name) = pending.pop() {
+        if seen.contains(&name) {
+            continue;
+        }
+        let source = fs::read(root.join(&name)).unwrap_or_else(|e| fail(&format!("Cannot read {name}: {e}")));
+
+        for &byte in name.as_bytes().iter().chain(&[0]).chain(&source) {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+
+        let includes = String::from_utf8_lossy(&source).lines()
+            .filter_map(|line| line.trim().strip_prefix("#include"))
+            .filter_map(|target| target.trim().strip_prefix('"')?.strip_suffix('"').map(str::to_string))
+            .collect::<Vec<_>>();
+        pending.extend(includes.into_iter().rev());
+        seen.push(name);
+    }
+
+    hash
+}
+
+// Leaves the file alone when it already matches, so cargo doesn't see shaders/ change and rerun this every build
+fn write_if_changed(path: &Path, contents: &[u8]) {
+    if fs::read(path).ok().as_deref() != Some(contents) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+// Names are relative to shaders/ with forward slashes, the compiled modules in out/ are skipped
+fn find_sources(root: &Path, dir: &Path, names: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).expect("Cannot read the shaders directory") {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            if path != root.join("out") {
+                find_sources(root, &path, names);
+            }
+            continue;
+        }
+
+        let is_source = path.extension().and_then(|e| e.to_str()).is_some_and(|e| SOURCE_EXTENSIONS.contains(&e));
+        if is_source {
+            let relative = path.strip_prefix(root).unwrap();
+            let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+            names.push(parts.join("/"));
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// The same as shader.vert and shader.frag, with both stages in one module
+
+struct Push {
+    mvp: mat4x4<f32>,
+    baseColour: vec4<f32>,
+}
+
+var<immediate> push: Push;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) colour: vec3<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = push.mvp * vec4<f32>(in.position, 1.0);
+    // Tint the normals until there is lighting
+    out.colour = push.baseColour.rgb * (in.normal * 0.5 + 0.5);
+    return out;
+}
+
+@fragment
+fn fs_main(@location(0) colour: vec3<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(colour, 1.0);
+}
@@ -1,71 +1,84 @@
-use std::num::NonZero;
-
 use anyhow::Result;
-use vulkrust_play::engine::VulkanEngine;
+use vulkrust_play::{camera::Camera, engine::VulkanEngine, gltf_loader::GltfScene, mesh::MeshData, obj_loader::ObjModel};
 use winit::{event_loop::{ActiveEventLoop, ControlFlow, EventLoop}, window::{Window, WindowAttributes}};
 
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
 use winit::window::{WindowId};
-use softbuffer::{Context, Surface};
 
 #[derive(Default)]
 struct App {
+    model_path: Option<String>,
+    pipeline_path: Option<String>,
     engine: Option<VulkanEngine>,
     window: Option<Window>,
 }
 
+impl App {
+    fn load_meshes(&self, engine: &mut VulkanEngine) -> Result<()> {
+        match &self.model_path {
+            None => engine.add_mesh(&MeshData::triangle())?,
+            Some(path) if path.ends_with(".gltf") || path.ends_with(".glb") => {
+                engine.add_gltf_scene(&GltfScene::load(path)?)?;
+            },
+            Some(path) => {
+                let model = ObjModel::load(path)?;
+                for mesh in &model.meshes {
+                    engine.add_mesh(&mesh.data)?;
+                }
+            },
+        }
+
+        if let Some(path) = &self.pipeline_path {
+            let pipeline = engine.load_pipeline(path)?;
+            engine.use_pipeline(pipeline);
+        }
+
+        if let Some(bounds) = engine.scene_bounds() {
+            engine.set_camera(Camera::framing(&bounds));
+        }
+
+        Ok(())
+    }
+}
+
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let w = event_loop.create_window(
             WindowAttributes::default().with_title("WSLg - first frame")
         ).unwrap();
 
-        let engine = VulkanEngine::new("Window App", true, &w).expect("Cannot create engine");
-
+        let mut engine = VulkanEngine::new("Window App", true, &w).expect("Cannot create engine");
+        self.load_meshes(&mut engine).expect("Cannot load model");
 
-        {
-            let ctx  = Context::new(&w).unwrap();
-            let mut surf = Surface::new(&ctx, &w).unwrap();
-            let size = w.inner_size();
-            surf.resize(NonZero::new(size.width).unwrap(), NonZero::new(size.height).unwrap()).ok();
-            let mut buf = surf.buffer_mut().unwrap();
-            for px in buf.iter_mut() { *px = 0xFF_202040; }
-            buf.present().unwrap();
-        } 
+        w.request_redraw();
 
         self.window = Some(w);
         self.engine = Some(engine);
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
-        println!("{event:?}");
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
                 event_loop.exit();
             },
-            // WindowEvent::Resized(new_size) => {
-            // //     if let Some(s) = &mut self.sb_surf {
-            // //         // s.resize(new_size.width, new_size.height).ok();
-            // //         // optional: redraw to fill new size
-            // //         if let Ok(mut buf) = s.buffer_mut() {
-            // //             for px in buf.iter_mut() { *px = 0xFF_202040; }
-            // //             let _ = buf.present();
-            // //         }
-            // //     }
-            // },
-            // WindowEvent::RedrawRequested => {
-            //      let window = self.window.as_ref().expect("redraw request without a window");
-
-            //     // Notify that you're about to draw.
-            //     window.pre_present_notify();
-
-            //     // Draw.
-            //     // fill::fill_window(window.as_ref());
-
-            //     window.request_redraw();
-            // }
+            WindowEvent::Resized(new_size) => {
+                if let Some(engine) = &mut self.engine {
+                    engine.resize(new_size.width, new_size.height);
+                }
+            },
+            WindowEvent::RedrawRequested => {
+                let window = self.window.as_ref().expect("redraw request without a window");
+                let engine = self.engine.as_mut().expect("redraw request without an engine");
+
+                // Notify that you're about to draw.
+                window.pre_present_notify();
+
+                engine.draw_frame().expect("Cannot draw frame");
+
+                window.request_redraw();
+            }
             _ => (),
         }
     }
@@ -75,7 +88,13 @@ fn main() -> Result<()>{
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = App::default();
+    // Optionally show an OBJ or glTF model instead of the default triangle,
+    // drawn with a pipeline description from pipelines/
+    let mut app = App {
+        model_path: std::env::args().nth(1),
+        pipeline_path: std::env::args().nth(2),
+        ..Default::default()
+    };
     event_loop.run_app(&mut app)?;
 
     Ok(())
@@ -0,0 +1,249 @@
+use ash::vk::{self, DescriptorBindingFlags, DescriptorSetLayoutBinding, DescriptorType, ImageLayout, ShaderStageFlags};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, buffer::Buffer, descriptors::DescriptorSetLayout, image_view::ImageView, sampler::Sampler, texture::Texture};
+
+pub const IMAGES_BINDING: u32 = 0;
+pub const SAMPLERS_BINDING: u32 = 1;
+pub const STORAGE_BUFFERS_BINDING: u32 = 2;
+
+// Upper bounds, lowered to whatever the device allows for update-after-bind descriptors
+const MAX_IMAGES: u32 = 16384;
+const MAX_SAMPLERS: u32 = 1024;
+const MAX_STORAGE_BUFFERS: u32 = 16384;
+
+// How many of each resource the bindless set holds on this device, or None when it can't have one
+fn capacities(logical_device: &LogicalDevice) -> Option<[u32; 3]> {
+    let limits = logical_device.descriptor_indexing()?;
+
+    let samplers = MAX_SAMPLERS
+        .min(limits.max_descriptor_set_update_after_bind_samplers)
+        .min(limits.max_per_stage_descriptor_update_after_bind_samplers);
+    let storage_buffers = MAX_STORAGE_BUFFERS
+        .min(limits.max_descriptor_set_update_after_bind_storage_buffers)
+        .min(limits.max_per_stage_descriptor_update_after_bind_storage_buffers);
+    let images = MAX_IMAGES
+        .min(limits.max_descriptor_set_update_after_bind_sampled_images)
+        .min(limits.max_per_stage_descriptor_update_after_bind_sampled_images)
+        // Samplers don't count as resources here
+        .min(limits.max_per_stage_update_after_bind_resources.saturating_sub(storage_buffers));
+
+    Some([images, samplers, storage_buffers])
+}
+
+// The layout of the bindless set. Pipelines given it with GraphicsPipelineBuilder::bindless make their own
+// from this, and as the definitions are identical Vulkan treats them as compatible
+pub fn bindless_layout(logical_device: &LogicalDevice) -> Result<DescriptorSetLayout> {
+    let [images, samplers, storage_buffers] = capacities(logical_device)
+        .ok_or_else(|| Error::msg("Bindless descriptors need descriptor indexing, which this device doesn't support"))?;
+
+    let binding = |binding: u32, descriptor_type: DescriptorType, count: u32| DescriptorSetLayoutBinding::default()
+        .binding(binding)
+        .descriptor_type(descriptor_type)
+        .descriptor_count(count)
+        .stage_flags(ShaderStageFlags::ALL_GRAPHICS);
+    let bindings = [
+        binding(IMAGES_BINDING, DescriptorType::SAMPLED_IMAGE, images),
+        binding(SAMPLERS_BINDING, DescriptorType::SAMPLER, samplers),
+        binding(STORAGE_BUFFERS_BINDING, DescriptorType::STORAGE_BUFFER, storage_buffers),
+    ];
+
+    // Slots are filled in while the set is bound, and most are never filled at all.
+    // Only the last binding can have a variable count
+    let flags = DescriptorBindingFlags::PARTIALLY_BOUND | DescriptorBindingFlags::UPDATE_AFTER_BIND;
+    let binding_flags = [flags, flags, flags | DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT];
+
+    DescriptorSetLayout::with_flags(logical_device, &bindings, vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL, &binding_flags)
+}
+
+// Hands out array slots, reusing freed ones so indices stay small
+#[derive(Default)]
+struct Slots {
+    next: u32,
+    free: Vec<u32>,
+}
+
+impl Slots {
+    fn take(&mut self, capacity: u32, what: &str) -> Result<u32> {
+        if let Some(index) = self.free.pop() {
+            return Ok(index);
+        }
+        if self.next == capacity {
+            return Err(Error::msg(format!("The bindless set is full, it has room for {capacity} {what}")));
+        }
+
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+
+    fn release(&mut self, index: u32) {
+        debug_assert!(index < self.next && !self.free.contains(&index), "Releasing bindless index {index} twice");
+        self.free.push(index);
+    }
+}
+
+// Indices of a texture's parts in the bindless set, for shaders to find it with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BindlessTexture {
+    pub image: u32,
+    pub sampler: u32,
+}
+
+// One descriptor set holding every registered image, sampler and storage buffer, bound once per frame.
+// Each resource keeps its index until it's removed, so shaders can be given indices through push constants
+// instead of binding a set per material. The set only refers to the resources, which the caller keeps alive
+pub struct BindlessSet {
+    set: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    layout: DescriptorSetLayout,
+    capacities: [u32; 3],
+    images: Slots,
+    samplers: Slots,
+    storage_buffers: Slots,
+    device: ash::Device
+}
+
+impl BindlessSet {
+    // None on devices without descriptor indexing, which keep drawing the bound-descriptor way
+    pub fn new(logical_device: &LogicalDevice) -> Result<Option<Self>> {
+        let Some(capacities) = capacities(logical_device) else { return Ok(None) };
+        let [images, samplers, storage_buffers] = capacities;
+        let layout = bindless_layout(logical_device)?;
+        let device = logical_device.raw();
+
+        // VkDescriptorPoolCreateInfo
+        let pool_sizes = [
+            vk::DescriptorPoolSize { ty: DescriptorType::SAMPLED_IMAGE, descriptor_count: images },
+            vk::DescriptorPoolSize { ty: DescriptorType::SAMPLER, descriptor_count: samplers },
+            vk::DescriptorPoolSize { ty: DescriptorType::STORAGE_BUFFER, descriptor_count: storage_buffers },
+        ];
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { device.create_descriptor_pool(&create_info, None)? };
+
+        // VkDescriptorSetVariableDescriptorCountAllocateInfo
+        let variable_counts = [storage_buffers];
+        let mut variable_count_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::default().descriptor_counts(&variable_counts);
+
+        // VkDescriptorSetAllocateInfo
+        let set_layouts = [*layout.raw()];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts)
+            .push_next(&mut variable_count_info);
+        let set = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets[0],
+            Err(e) => {
+                unsafe { device.destroy_descriptor_pool(pool, None) };
+                return Err(e.into());
+            },
+        };
+
+        println!("Bindless descriptors: {images} images, {samplers} samplers, {storage_buffers} storage buffers");
+
+        Ok(Some(Self {
+            set,
+            pool,
+            layout,
+            capacities,
+            images: Slots::default(),
+            samplers: Slots::default(),
+            storage_buffers: Slots::default(),
+            device: device.clone()
+        }))
+    }
+
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    // Expects the image in SHADER_READ_ONLY_OPTIMAL whenever it's sampled
+    pub fn add_image(&mut self, view: &ImageView) -> Result<u32> {
+        let index = self.images.take(self.capacities[0], "images")?;
+        self.write(IMAGES_BINDING, index, DescriptorType::SAMPLED_IMAGE, &[vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: *view.raw(),
+            image_layout: ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }], &[]);
+
+        Ok(index)
+    }
+
+    pub fn add_sampler(&mut self, sampler: &Sampler) -> Result<u32> {
+        let index = self.samplers.take(self.capacities[1], "samplers")?;
+        self.write(SAMPLERS_BINDING, index, DescriptorType::SAMPLER, &[vk::DescriptorImageInfo {
+            sampler: *sampler.raw(),
+            ..Default::default()
+        }], &[]);
+
+        Ok(index)
+    }
+
+    pub fn add_texture(&mut self, texture: &Texture) -> Result<BindlessTexture> {
+        let image = self.add_image(texture.view())?;
+        let sampler = match self.add_sampler(texture.sampler()) {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                self.images.release(image);
+                return Err(e);
+            },
+        };
+
+        Ok(BindlessTexture { image, sampler })
+    }
+
+    pub fn add_storage_buffer(&mut self, buffer: &Buffer) -> Result<u32> {
+        let index = self.storage_buffers.take(self.capacities[2], "storage buffers")?;
+        self.write(STORAGE_BUFFERS_BINDING, index, DescriptorType::STORAGE_BUFFER, &[], &[vk::DescriptorBufferInfo {
+            buffer: *buffer.raw(),
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }]);
+
+        Ok(index)
+    }
+
+    // The index can be handed out again straight away, so only remove what no frame in flight still uses
+    pub fn remove_image(&mut self, index: u32) {
+        self.images.release(index);
+    }
+
+    pub fn remove_sampler(&mut self, index: u32) {
+        self.samplers.release(index);
+    }
+
+    pub fn remove_texture(&mut self, texture: BindlessTexture) {
+        self.images.release(texture.image);
+        self.samplers.release(texture.sampler);
+    }
+
+    pub fn remove_storage_buffer(&mut self, index: u32) {
+        self.storage_buffers.release(index);
+    }
+
+    // Update-after-bind lets this happen while command buffers using the set are recorded or running,
+    // as long as those draws don't read this slot
+    fn write(&self, binding: u32, index: u32, descriptor_type: DescriptorType, image_info: &[vk::DescriptorImageInfo], buffer_info: &[vk::DescriptorBufferInfo]) {
+        // VkWriteDescriptorSet
+        let mut write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .dst_array_element(index)
+            .descriptor_type(descriptor_type);
+        write = if image_info.is_empty() { write.buffer_info(buffer_info) } else { write.image_info(image_info) };
+
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+}
+
+impl Drop for BindlessSet {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_descriptor_pool(self.pool, None) };
+    }
+}
@@ -0,0 +1,117 @@
+use ash::vk::{self, BufferUsageFlags, DeviceMemory, MemoryPropertyFlags, SharingMode};
+use anyhow::Result;
+
+use crate::{LogicalDevice, command_pool::CommandPool};
+
+pub struct Buffer {
+    raw: vk::Buffer,
+    memory: DeviceMemory,
+    size: vk::DeviceSize,
+    device: ash::Device
+}
+
+impl Buffer {
+    pub fn new(logical_device: &LogicalDevice, size: vk::DeviceSize, usage: BufferUsageFlags, properties: MemoryPropertyFlags) -> Result<Self> {
+        let device = logical_device.raw();
+
+        // VkBufferCreateInfo
+        let buffer_create_info = vk::BufferCreateInfo {
+            size,
+            usage,
+            sharing_mode: SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+
+        let buffer = unsafe { device.create_buffer(&buffer_create_info, None)? };
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+        // VkMemoryAllocateInfo
+        // One allocation per buffer is fine for now, but there is a low limit on the number of allocations
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index: logical_device.find_memory_type(requirements.memory_type_bits, properties)?,
+            ..Default::default()
+        };
+
+        let memory = unsafe { device.allocate_memory(&allocate_info, None)? };
+        unsafe { device.bind_buffer_memory(buffer, memory, 0)? };
+
+        Ok(Self {
+            raw: buffer,
+            memory,
+            size,
+            device: device.clone()
+        })
+    }
+
+    // Creates a device local buffer and fills it by going through a host visible staging buffer
+    pub fn new_device_local(logical_device: &LogicalDevice, command_pool: &CommandPool, data: &[u8], usage: BufferUsageFlags) -> Result<Self> {
+        let size = data.len() as vk::DeviceSize;
+
+        let staging = Self::new(
+            logical_device,
+            size,
+            BufferUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT
+        )?;
+        staging.write(data)?;
+
+        let buffer = Self::new(logical_device, size, usage | BufferUsageFlags::TRANSFER_DST, MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+        command_pool.submit_single_time(logical_device, |device, command_buffer| {
+            let region = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size
+            };
+
+            unsafe { device.cmd_copy_buffer(command_buffer, staging.raw, buffer.raw, &[region]) };
+        })?;
+
+        Ok(buffer)
+    }
+
+    // Only valid on host visible, host coherent memory
+    pub fn write(&self, data: &[u8]) -> Result<()> {
+        self.write_at(0, data)
+    }
+
+    pub fn write_at(&self, offset: vk::DeviceSize, data: &[u8]) -> Result<()> {
+        assert!(offset + data.len() as vk::DeviceSize <= self.size, "Write past the end of the buffer");
+
+        unsafe {
+            let mapped = self.device.map_memory(self.memory, offset, data.len() as vk::DeviceSize, vk::MemoryMapFlags::empty())?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, data.len());
+            self.device.unmap_memory(self.memory);
+        }
+
+        Ok(())
+    }
+
+    // Maps the whole buffer until unmap, for buffers written every frame. write and write_at can't be used meanwhile
+    pub fn map(&self) -> Result<*mut u8> {
+        let mapped = unsafe { self.device.map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())? };
+        Ok(mapped as *mut u8)
+    }
+
+    pub fn unmap(&self) {
+        unsafe { self.device.unmap_memory(self.memory) };
+    }
+
+    pub fn raw(&self) -> &vk::Buffer {
+        &self.raw
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.raw, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
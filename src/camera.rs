@@ -0,0 +1,53 @@
+use glam::{Mat4, Vec3};
+
+use crate::mesh::Bounds;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.0, 2.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 45.0f32.to_radians(),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    // Backs off along +Z until the whole of the bounds fits in view
+    pub fn framing(bounds: &Bounds) -> Self {
+        let camera = Self::default();
+        let radius = ((bounds.max - bounds.min).length() * 0.5).max(0.001);
+        let distance = radius / (camera.fov_y * 0.5).sin();
+
+        Self {
+            eye: bounds.centre() + Vec3::Z * distance,
+            target: bounds.centre(),
+            near: (distance - radius).max(distance * 0.01),
+            far: distance + radius * 2.0,
+            ..camera
+        }
+    }
+
+    pub fn view_projection(&self, aspect: f32) -> Mat4 {
+        let view = Mat4::look_at_rh(self.eye, self.target, self.up);
+        let mut projection = Mat4::perspective_rh(self.fov_y, aspect, self.near, self.far);
+
+        // Vulkan clip space has Y pointing down
+        projection.y_axis.y *= -1.0;
+
+        projection * view
+    }
+}
@@ -0,0 +1,90 @@
+use ash::vk::{self, CommandBufferLevel, CommandBufferUsageFlags, CommandPoolCreateFlags};
+use anyhow::Result;
+
+use crate::LogicalDevice;
+
+pub struct CommandPool {
+    raw: vk::CommandPool,
+    device: ash::Device
+}
+
+impl CommandPool {
+    pub fn new(logical_device: &LogicalDevice) -> Result<Self> {
+        // VkCommandPoolCreateInfo
+        // Command buffers get re-recorded every frame, so let them be reset individually
+        let create_info = vk::CommandPoolCreateInfo {
+            flags: CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: logical_device.queue_family_indices().graphics_family.unwrap(),
+            ..Default::default()
+        };
+
+        let command_pool = unsafe { logical_device.raw().create_command_pool(&create_info, None)? };
+
+        Ok(Self {
+            raw: command_pool,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn allocate_command_buffers(&self, count: u32) -> Result<Vec<vk::CommandBuffer>> {
+        // VkCommandBufferAllocateInfo
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            command_pool: self.raw,
+            level: CommandBufferLevel::PRIMARY,
+            command_buffer_count: count,
+            ..Default::default()
+        };
+
+        Ok(unsafe { self.device.allocate_command_buffers(&allocate_info)? })
+    }
+
+    // Records and submits a throwaway command buffer on the graphics queue, then waits for it to finish.
+    // Fine for uploads at load time, but shouldn't be used during a frame
+    pub fn submit_single_time<F>(&self, logical_device: &LogicalDevice, record: F) -> Result<()>
+    where
+        F: FnOnce(&ash::Device, vk::CommandBuffer)
+    {
+        let command_buffer = self.allocate_command_buffers(1)?[0];
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        let result = unsafe {
+            self.device.begin_command_buffer(command_buffer, &begin_info)
+                .and_then(|_| {
+                    record(&self.device, command_buffer);
+                    self.device.end_command_buffer(command_buffer)
+                })
+                .and_then(|_| {
+                    let submit_info = vk::SubmitInfo {
+                        command_buffer_count: 1,
+                        p_command_buffers: &command_buffer,
+                        ..Default::default()
+                    };
+
+                    self.device.queue_submit(logical_device.graphics_queue(), &[submit_info], vk::Fence::null())
+                })
+                .and_then(|_| self.device.queue_wait_idle(logical_device.graphics_queue()))
+        };
+
+        unsafe { self.device.free_command_buffers(self.raw, &[command_buffer]) };
+
+        Ok(result?)
+    }
+
+    pub fn raw(&self) -> &vk::CommandPool {
+        &self.raw
+    }
+}
+
+impl Drop for CommandPool {
+    fn drop(&mut self) {
+        unsafe {
+            println!("Dropping CommandPool");
+
+            self.device.destroy_command_pool(self.raw, None);
+        }
+    }
+}
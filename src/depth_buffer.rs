@@ -0,0 +1,74 @@
+use ash::vk::{self, Extent2D, Format, FormatFeatureFlags, ImageAspectFlags, ImageUsageFlags, SampleCountFlags};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, image::{Image, ImageDescription}, image_view::ImageView};
+
+// Most precise first. D32 has no stencil, so it loses to the packed formats when stencil is wanted
+pub const DEPTH_FORMATS: [Format; 3] = [Format::D32_SFLOAT, Format::D32_SFLOAT_S8_UINT, Format::D24_UNORM_S8_UINT];
+pub const DEPTH_STENCIL_FORMATS: [Format; 3] = [Format::D32_SFLOAT_S8_UINT, Format::D24_UNORM_S8_UINT, Format::D16_UNORM_S8_UINT];
+
+// The first format the device can use as a depth attachment with optimal tiling.
+// Only D16_UNORM and one of D32_SFLOAT or X8_D24_UNORM_PACK32 are guaranteed, so it pays to have fallbacks
+pub fn find_depth_format(logical_device: &LogicalDevice, candidates: &[Format]) -> Result<Format> {
+    candidates.iter().copied()
+        .find(|&format| logical_device.format_properties(format).optimal_tiling_features.contains(FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT))
+        .ok_or_else(|| Error::msg(format!("None of {candidates:?} can be a depth attachment on this device")))
+}
+
+pub fn has_stencil(format: Format) -> bool {
+    matches!(format, Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT | Format::S8_UINT)
+}
+
+// Which aspects a view of an image in this format covers
+pub fn aspect_flags(format: Format) -> ImageAspectFlags {
+    match format {
+        Format::D16_UNORM | Format::X8_D24_UNORM_PACK32 | Format::D32_SFLOAT => ImageAspectFlags::DEPTH,
+        Format::S8_UINT => ImageAspectFlags::STENCIL,
+        format if has_stencil(format) => ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL,
+        _ => ImageAspectFlags::COLOR,
+    }
+}
+
+// Sized to the swap chain, so it's recreated along with it
+pub struct DepthBuffer {
+    view: ImageView,
+    image: Image,
+}
+
+impl DepthBuffer {
+    // Depth is never read back, so a multisampled one is transient like the multisampled colour target
+    pub fn new(logical_device: &LogicalDevice, extent: &Extent2D, format: Format, samples: SampleCountFlags) -> Result<Self> {
+        let mut usage = ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+        if samples != SampleCountFlags::TYPE_1 {
+            usage |= ImageUsageFlags::TRANSIENT_ATTACHMENT;
+        }
+        let description = ImageDescription {
+            samples,
+            ..ImageDescription::new_2d(extent.width, extent.height, format, usage)
+        };
+        let image = Image::new(logical_device, &description)?;
+        let view = ImageView::new(logical_device, image.raw(), &format)?;
+
+        Ok(Self {
+            view,
+            image,
+        })
+    }
+
+    pub fn view(&self) -> &ImageView {
+        &self.view
+    }
+
+    // For the layout transitions dynamic rendering leaves to the caller
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn format(&self) -> vk::Format {
+        *self.image.format()
+    }
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.image.samples()
+    }
+}
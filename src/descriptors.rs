@@ -0,0 +1,390 @@
+use ash::vk::{self, DescriptorPoolSize, DescriptorSetLayoutBinding, DescriptorType, ImageLayout};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, buffer::Buffer, image_view::ImageView, sampler::Sampler, texture::Texture};
+
+pub struct DescriptorSetLayout {
+    raw: vk::DescriptorSetLayout,
+    bindings: Vec<DescriptorSetLayoutBinding<'static>>,
+    device: ash::Device
+}
+
+impl DescriptorSetLayout {
+    pub fn new(logical_device: &LogicalDevice, bindings: &[DescriptorSetLayoutBinding<'static>]) -> Result<Self> {
+        Self::with_flags(logical_device, bindings, vk::DescriptorSetLayoutCreateFlags::empty(), &[])
+    }
+
+    // binding_flags is empty, or one entry per binding for descriptor indexing
+    pub fn with_flags(logical_device: &LogicalDevice, bindings: &[DescriptorSetLayoutBinding<'static>], flags: vk::DescriptorSetLayoutCreateFlags, binding_flags: &[vk::DescriptorBindingFlags]) -> Result<Self> {
+        // VkDescriptorSetLayoutBindingFlagsCreateInfo
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(binding_flags);
+
+        // VkDescriptorSetLayoutCreateInfo
+        let mut create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings).flags(flags);
+        if !binding_flags.is_empty() {
+            create_info = create_info.push_next(&mut binding_flags_info);
+        }
+        let raw = unsafe { logical_device.raw().create_descriptor_set_layout(&create_info, None)? };
+
+        Ok(Self {
+            raw,
+            bindings: bindings.to_vec(),
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn raw(&self) -> &vk::DescriptorSetLayout {
+        &self.raw
+    }
+
+    pub fn bindings(&self) -> &[DescriptorSetLayoutBinding<'static>] {
+        &self.bindings
+    }
+
+    pub fn binding(&self, binding: u32) -> Option<&DescriptorSetLayoutBinding<'static>> {
+        self.bindings.iter().find(|b| b.binding == binding)
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_descriptor_set_layout(self.raw, None);
+        }
+    }
+}
+
+// Descriptors of each type a pool is given for every set it can hold, roughly what a material needs
+const DEFAULT_RATIOS: [(DescriptorType, f32); 6] = [
+    (DescriptorType::UNIFORM_BUFFER, 2.0),
+    (DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (DescriptorType::STORAGE_BUFFER, 1.0),
+    (DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (DescriptorType::STORAGE_IMAGE, 1.0),
+    (DescriptorType::SAMPLED_IMAGE, 1.0),
+];
+
+// Each new pool holds half as many sets again as the last, up to this
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+// Hands out descriptor sets from as many pools as it takes. A pool that runs out is set aside
+// and a new, bigger one made, so callers never see OUT_OF_POOL_MEMORY or FRAGMENTED_POOL.
+// Sets aren't freed one at a time, everything goes at once with reset
+pub struct DescriptorAllocator {
+    ratios: Vec<(DescriptorType, f32)>,
+    sets_per_pool: u32,
+    ready: Vec<vk::DescriptorPool>,
+    full: Vec<vk::DescriptorPool>,
+    device: ash::Device
+}
+
+impl DescriptorAllocator {
+    pub fn new(logical_device: &LogicalDevice, initial_sets: u32) -> Self {
+        Self::with_ratios(logical_device, initial_sets, &DEFAULT_RATIOS)
+    }
+
+    pub fn with_ratios(logical_device: &LogicalDevice, initial_sets: u32, ratios: &[(DescriptorType, f32)]) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            sets_per_pool: initial_sets.clamp(1, MAX_SETS_PER_POOL),
+            ready: vec![],
+            full: vec![],
+            device: logical_device.raw().clone()
+        }
+    }
+
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout) -> Result<vk::DescriptorSet> {
+        let pool = self.take_pool(layout)?;
+        let set = match self.allocate_from(pool, layout) {
+            Ok(set) => set,
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                self.full.push(pool);
+
+                // A fresh pool always has room for at least one set of this layout
+                let pool = self.create_pool(layout)?;
+                let set = self.allocate_from(pool, layout);
+                self.ready.push(pool);
+                return Ok(set?);
+            },
+            Err(e) => {
+                self.ready.push(pool);
+                return Err(e.into());
+            },
+        };
+        self.ready.push(pool);
+
+        Ok(set)
+    }
+
+    // Frees every set allocated so far. Only once the GPU has finished with all of them,
+    // e.g. after waiting on the fence of the frame they were used in
+    pub fn reset(&mut self) -> Result<()> {
+        self.ready.append(&mut self.full);
+        for pool in &self.ready {
+            unsafe { self.device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())? };
+        }
+
+        Ok(())
+    }
+
+    fn allocate_from(&self, pool: vk::DescriptorPool, layout: &DescriptorSetLayout) -> Result<vk::DescriptorSet, vk::Result> {
+        // VkDescriptorSetAllocateInfo
+        let set_layouts = [*layout.raw()];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+
+        unsafe { self.device.allocate_descriptor_sets(&allocate_info) }.map(|sets| sets[0])
+    }
+
+    fn take_pool(&mut self, layout: &DescriptorSetLayout) -> Result<vk::DescriptorPool> {
+        match self.ready.pop() {
+            Some(pool) => Ok(pool),
+            None => self.create_pool(layout),
+        }
+    }
+
+    fn create_pool(&mut self, layout: &DescriptorSetLayout) -> Result<vk::DescriptorPool> {
+        let sets = self.sets_per_pool;
+        self.sets_per_pool = (sets + sets / 2).min(MAX_SETS_PER_POOL);
+
+        let pool_sizes = pool_sizes(&self.ratios, sets, layout.bindings())?;
+
+        // VkDescriptorPoolCreateInfo
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(sets)
+            .pool_sizes(&pool_sizes);
+
+        Ok(unsafe { self.device.create_descriptor_pool(&create_info, None)? })
+    }
+}
+
+// The ratios scaled up to `sets`, with room for at least one set of the layout with these bindings
+fn pool_sizes(ratios: &[(DescriptorType, f32)], sets: u32, bindings: &[DescriptorSetLayoutBinding]) -> Result<Vec<DescriptorPoolSize>> {
+    let mut pool_sizes: Vec<DescriptorPoolSize> = ratios.iter().map(|&(ty, ratio)| DescriptorPoolSize {
+        ty,
+        descriptor_count: (ratio * sets as f32).ceil() as u32,
+    }).collect();
+
+    // What one set of this layout takes, adding up every binding of the same type
+    let mut needed: Vec<DescriptorPoolSize> = vec![];
+    for binding in bindings {
+        match needed.iter_mut().find(|size| size.ty == binding.descriptor_type) {
+            Some(size) => {
+                size.descriptor_count = size.descriptor_count.checked_add(binding.descriptor_count)
+                    .ok_or_else(|| Error::msg(format!("The descriptor set layout needs more than {} {:?} descriptors", u32::MAX, binding.descriptor_type)))?;
+            },
+            None => needed.push(DescriptorPoolSize {
+                ty: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+            }),
+        }
+    }
+
+    // Types the ratios leave out, or sets bigger than they allow for
+    for set in needed {
+        match pool_sizes.iter_mut().find(|size| size.ty == set.ty) {
+            Some(size) => size.descriptor_count = size.descriptor_count.max(set.descriptor_count),
+            None => pool_sizes.push(set),
+        }
+    }
+    pool_sizes.retain(|size| size.descriptor_count > 0);
+
+    Ok(pool_sizes)
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        for pool in self.ready.iter().chain(&self.full) {
+            unsafe { self.device.destroy_descriptor_pool(*pool, None) };
+        }
+    }
+}
+
+enum DescriptorInfo {
+    // And the size of the whole buffer, to check the range against
+    Buffer(vk::DescriptorBufferInfo, vk::DeviceSize),
+    Image(vk::DescriptorImageInfo),
+}
+
+struct DescriptorWrite {
+    binding: u32,
+    descriptor_type: DescriptorType,
+    info: DescriptorInfo,
+}
+
+// Collects what goes in a set, then writes it all with one vkUpdateDescriptorSets.
+// Each binding is checked against the set layout first, so a wrong binding number or type
+// is an error here rather than a validation layer message or a GPU fault later
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<DescriptorWrite>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // range can be vk::WHOLE_SIZE for the rest of the buffer
+    pub fn uniform_buffer(self, binding: u32, buffer: &Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Self {
+        self.buffer(binding, DescriptorType::UNIFORM_BUFFER, buffer, offset, range)
+    }
+
+    // Offset into the buffer at bind time, the range is how much each draw can see
+    pub fn dynamic_uniform_buffer(self, binding: u32, buffer: &Buffer, range: vk::DeviceSize) -> Self {
+        self.buffer(binding, DescriptorType::UNIFORM_BUFFER_DYNAMIC, buffer, 0, range)
+    }
+
+    pub fn storage_buffer(self, binding: u32, buffer: &Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Self {
+        self.buffer(binding, DescriptorType::STORAGE_BUFFER, buffer, offset, range)
+    }
+
+    pub fn combined_image_sampler(self, binding: u32, view: &ImageView, sampler: &Sampler, layout: ImageLayout) -> Self {
+        self.image(binding, DescriptorType::COMBINED_IMAGE_SAMPLER, vk::DescriptorImageInfo {
+            sampler: *sampler.raw(),
+            image_view: *view.raw(),
+            image_layout: layout,
+        })
+    }
+
+    // Textures are left in SHADER_READ_ONLY_OPTIMAL once uploaded
+    pub fn texture(self, binding: u32, texture: &Texture) -> Self {
+        self.combined_image_sampler(binding, texture.view(), texture.sampler(), ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    // Storage images have to be in GENERAL layout while shaders write them
+    pub fn storage_image(self, binding: u32, view: &ImageView) -> Self {
+        self.image(binding, DescriptorType::STORAGE_IMAGE, vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: *view.raw(),
+            image_layout: ImageLayout::GENERAL,
+        })
+    }
+
+    fn buffer(mut self, binding: u32, descriptor_type: DescriptorType, buffer: &Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Self {
+        self.writes.push(DescriptorWrite {
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+                buffer: *buffer.raw(),
+                offset,
+                range,
+            }, buffer.size()),
+        });
+        self
+    }
+
+    fn image(mut self, binding: u32, descriptor_type: DescriptorType, info: vk::DescriptorImageInfo) -> Self {
+        self.writes.push(DescriptorWrite {
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Image(info),
+        });
+        self
+    }
+
+    pub fn update(&self, logical_device: &LogicalDevice, layout: &DescriptorSetLayout, set: vk::DescriptorSet) -> Result<()> {
+        let limits = &logical_device.properties().limits;
+
+        for write in &self.writes {
+            let expected = layout.binding(write.binding).ok_or_else(|| Error::msg(format!(
+                "The descriptor set layout has no binding {}, it has {:?}",
+                write.binding, layout.bindings().iter().map(|b| b.binding).collect::<Vec<_>>()
+            )))?;
+
+            if expected.descriptor_type != write.descriptor_type {
+                return Err(Error::msg(format!(
+                    "Binding {} is {:?} in the descriptor set layout, but is being written as {:?}",
+                    write.binding, expected.descriptor_type, write.descriptor_type
+                )));
+            }
+
+            if let DescriptorInfo::Buffer(info, buffer_size) = &write.info {
+                let range = if info.range == vk::WHOLE_SIZE { buffer_size.saturating_sub(info.offset) } else { info.range };
+                let end = info.offset.checked_add(range);
+                if info.offset >= *buffer_size || end.is_none_or(|end| end > *buffer_size) {
+                    return Err(Error::msg(format!(
+                        "Binding {} covers {range} bytes from byte {} of a {buffer_size} byte buffer",
+                        write.binding, info.offset
+                    )));
+                }
+
+                let max_range = match write.descriptor_type {
+                    DescriptorType::UNIFORM_BUFFER | DescriptorType::UNIFORM_BUFFER_DYNAMIC => limits.max_uniform_buffer_range,
+                    _ => limits.max_storage_buffer_range,
+                };
+                if range > max_range as vk::DeviceSize {
+                    return Err(Error::msg(format!(
+                        "Binding {} covers {range} bytes, more than this device's limit of {max_range} for {:?}",
+                        write.binding, write.descriptor_type
+                    )));
+                }
+            }
+        }
+
+        // VkWriteDescriptorSet, pointing into self.writes which doesn't move until this returns
+        let writes: Vec<vk::WriteDescriptorSet> = self.writes.iter().map(|write| {
+            let raw = vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(write.binding)
+                .descriptor_type(write.descriptor_type);
+
+            match &write.info {
+                DescriptorInfo::Buffer(info, _) => raw.buffer_info(std::slice::from_ref(info)),
+                DescriptorInfo::Image(info) => raw.image_info(std::slice::from_ref(info)),
+            }
+        }).collect();
+
+        unsafe { logical_device.raw().update_descriptor_sets(&writes, &[]) };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(binding: u32, descriptor_type: DescriptorType, descriptor_count: u32) -> DescriptorSetLayoutBinding<'static> {
+        DescriptorSetLayoutBinding { binding, descriptor_type, descriptor_count, ..Default::default() }
+    }
+
+    fn count(sizes: &[DescriptorPoolSize], ty: DescriptorType) -> Option<u32> {
+        sizes.iter().find(|size| size.ty == ty).map(|size| size.descriptor_count)
+    }
+
+    #[test]
+    fn adds_up_bindings_of_the_same_type() {
+        let bindings = [
+            binding(0, DescriptorType::COMBINED_IMAGE_SAMPLER, 3),
+            binding(1, DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+            binding(2, DescriptorType::STORAGE_IMAGE, 2),
+        ];
+
+        let sizes = pool_sizes(&[(DescriptorType::COMBINED_IMAGE_SAMPLER, 1.0)], 4, &bindings).unwrap();
+
+        assert_eq!(count(&sizes, DescriptorType::COMBINED_IMAGE_SAMPLER), Some(7));
+        assert_eq!(count(&sizes, DescriptorType::STORAGE_IMAGE), Some(2));
+    }
+
+    #[test]
+    fn keeps_ratios_bigger_than_one_set() {
+        let bindings = [binding(0, DescriptorType::UNIFORM_BUFFER, 1)];
+
+        let sizes = pool_sizes(&[(DescriptorType::UNIFORM_BUFFER, 2.0), (DescriptorType::SAMPLER, 0.0)], 10, &bindings).unwrap();
+
+        assert_eq!(count(&sizes, DescriptorType::UNIFORM_BUFFER), Some(20));
+        assert_eq!(count(&sizes, DescriptorType::SAMPLER), None);
+    }
+
+    #[test]
+    fn rejects_overflowing_layouts() {
+        let bindings = [
+            binding(0, DescriptorType::STORAGE_BUFFER, u32::MAX),
+            binding(1, DescriptorType::STORAGE_BUFFER, 1),
+        ];
+
+        assert!(pool_sizes(&[], 1, &bindings).is_err());
+    }
+}
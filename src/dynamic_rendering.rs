@@ -0,0 +1,142 @@
+use ash::vk::{self, AttachmentLoadOp, AttachmentStoreOp, ClearValue, Format, ImageLayout, Rect2D, ResolveModeFlags, SampleCountFlags};
+
+use crate::{image_view::ImageView, render_pass::SubpassInfo};
+
+// What a pipeline drawing without a render pass is told about its attachments, through
+// VkPipelineRenderingCreateInfo. Stands in for a single subpass
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderingFormats {
+    pub colour: Vec<Format>,
+    pub depth: Option<Format>,
+    pub samples: SampleCountFlags,
+}
+
+impl RenderingFormats {
+    // Matches RenderPassBuilder::swap_chain, the resolve target not being part of the pipeline's formats
+    pub fn swap_chain(image_format: Format, depth_format: Option<Format>, samples: SampleCountFlags) -> Self {
+        Self {
+            colour: vec![image_format],
+            depth: depth_format,
+            samples,
+        }
+    }
+
+    pub fn info(&self) -> SubpassInfo {
+        SubpassInfo {
+            colour_attachments: self.colour.len() as u32,
+            samples: self.samples,
+            depth_format: self.depth,
+        }
+    }
+}
+
+// One attachment of a dynamic rendering pass. Colour attachments are expected in COLOR_ATTACHMENT_OPTIMAL
+// and depth or stencil ones in DEPTH_STENCIL_ATTACHMENT_OPTIMAL, resolve targets included
+#[derive(Clone, Copy)]
+pub struct RenderingAttachment<'a> {
+    pub view: &'a ImageView,
+    pub load_op: AttachmentLoadOp,
+    pub store_op: AttachmentStoreOp,
+    pub clear_value: ClearValue,
+    // Averaged into at the end of the pass
+    pub resolve: Option<&'a ImageView>,
+}
+
+impl<'a> RenderingAttachment<'a> {
+    // Cleared and kept
+    pub fn cleared(view: &'a ImageView, clear_value: ClearValue) -> Self {
+        Self {
+            view,
+            load_op: AttachmentLoadOp::CLEAR,
+            store_op: AttachmentStoreOp::STORE,
+            clear_value,
+            resolve: None,
+        }
+    }
+
+    // Cleared and thrown away at the end of the pass, like a depth buffer nothing reads afterwards
+    pub fn transient(view: &'a ImageView, clear_value: ClearValue) -> Self {
+        Self {
+            store_op: AttachmentStoreOp::DONT_CARE,
+            ..Self::cleared(view, clear_value)
+        }
+    }
+
+    // Cleared, then resolved into another view and thrown away
+    pub fn resolved(view: &'a ImageView, resolve: &'a ImageView, clear_value: ClearValue) -> Self {
+        Self {
+            resolve: Some(resolve),
+            ..Self::transient(view, clear_value)
+        }
+    }
+
+    // VkRenderingAttachmentInfo
+    fn info(&self, layout: ImageLayout) -> vk::RenderingAttachmentInfo<'static> {
+        let mut info = vk::RenderingAttachmentInfo::default()
+            .image_view(*self.view.raw())
+            .image_layout(layout)
+            .load_op(self.load_op)
+            .store_op(self.store_op)
+            .clear_value(self.clear_value);
+        if let Some(resolve) = self.resolve {
+            info = info
+                .resolve_mode(ResolveModeFlags::AVERAGE)
+                .resolve_image_view(*resolve.raw())
+                .resolve_image_layout(layout);
+        }
+        info
+    }
+}
+
+// vkCmdBeginRendering and vkCmdEndRendering, which are core from Vulkan 1.3 and come from
+// VK_KHR_dynamic_rendering before that
+pub struct DynamicRendering {
+    extension: Option<ash::khr::dynamic_rendering::Device>,
+    device: ash::Device
+}
+
+impl DynamicRendering {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, from_extension: bool) -> Self {
+        Self {
+            extension: from_extension.then(|| ash::khr::dynamic_rendering::Device::new(instance, device)),
+            device: device.clone()
+        }
+    }
+
+    // The stencil attachment is the depth one again when the depth format has stencil
+    pub fn begin(&self, command_buffer: vk::CommandBuffer, render_area: Rect2D, colour: &[RenderingAttachment], depth: Option<&RenderingAttachment>, stencil: Option<&RenderingAttachment>) {
+        let colour_attachments: Vec<vk::RenderingAttachmentInfo> = colour.iter()
+            .map(|attachment| attachment.info(ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .collect();
+        let depth_attachment = depth.map(|attachment| attachment.info(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL));
+        let stencil_attachment = stencil.map(|attachment| attachment.info(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL));
+
+        // VkRenderingInfo
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(&colour_attachments);
+        if let Some(depth_attachment) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+        }
+        if let Some(stencil_attachment) = &stencil_attachment {
+            rendering_info = rendering_info.stencil_attachment(stencil_attachment);
+        }
+
+        unsafe {
+            match &self.extension {
+                Some(extension) => extension.cmd_begin_rendering(command_buffer, &rendering_info),
+                None => self.device.cmd_begin_rendering(command_buffer, &rendering_info),
+            }
+        }
+    }
+
+    pub fn end(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            match &self.extension {
+                Some(extension) => extension.cmd_end_rendering(command_buffer),
+                None => self.device.cmd_end_rendering(command_buffer),
+            }
+        }
+    }
+}
@@ -1,20 +1,86 @@
-use std::fs::{self};
+use std::sync::Arc;
 
-use ash::vk::{PhysicalDevice, PhysicalDeviceType, SurfaceKHR};
+use bytemuck::Pod;
+use glam::Mat4;
+use ash::vk::{self, AccessFlags, ClearColorValue, ClearDepthStencilValue, ClearValue, CommandBufferBeginInfo, ImageLayout, Offset2D, PhysicalDevice, PhysicalDeviceType, PipelineBindPoint, PipelineStageFlags, Rect2D, RenderPassBeginInfo, SampleCountFlags, SubmitInfo, SubpassContents, SurfaceKHR, Viewport};
 use ash_window::enumerate_required_extensions;
 use raw_window_handle::{HasDisplayHandle};
 use winit::window::Window;
-use crate::{Instance, LogicalDevice, Surface, graphics_pipeline::{self, GraphicsPipeline}, image_view::ImageView, logical_device::find_queue_families, render_pass::{self, RenderPass}, swap_chain::SwapChain, utils::vk_str_to_string};
+use crate::{Instance, LogicalDevice, Surface, bindless::BindlessSet, command_pool::CommandPool, depth_buffer::{self, DEPTH_FORMATS, DEPTH_STENCIL_FORMATS, DepthBuffer, find_depth_format}, descriptors::DescriptorAllocator, dynamic_rendering::{RenderingAttachment, RenderingFormats}, framebuffer::Framebuffer, graphics_pipeline::{GraphicsPipelineBuilder, PipelineShaders, RenderTarget}, image_view::ImageView, logical_device::find_queue_families, pipeline_cache::PipelineCache, pipeline_compiler::{PipelineCompiler, PipelineHandle, PipelineStatus}, pipeline_description::{PipelineDescription, PipelineFiles}, camera::Camera, gltf_loader::GltfScene, mesh::{Bounds, MeshData}, multisample::{MultisampleTarget, clamp_samples}, render_pass::RenderPassBuilder, sampler::SamplerOptions, scene::Scene, shader_watcher::ShaderWatcher, shaders, specialization::PipelineSpecialization, texture::{Texture, TextureUsage}, swap_chain::SwapChain, sync::{Fence, Semaphore}, uniform_ring::{UniformAllocation, UniformRing}, utils::vk_str_to_string};
 use anyhow::{Error, Result};
 
+// How many frames the CPU may record ahead of the GPU
+const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+pub struct EngineOptions {
+    // Lowered to the most the device can do for both colour and depth
+    pub samples: SampleCountFlags,
+    // For the pipeline the engine draws with until told otherwise, see GraphicsPipelineBuilder::sample_shading
+    pub sample_shading: Option<f32>,
+    // Picks a depth format with a stencil aspect, for pipelines with stencil tests
+    pub stencil: bool,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            samples: SampleCountFlags::TYPE_4,
+            sample_shading: None,
+            stencil: false,
+        }
+    }
+}
+
+struct Frame {
+    image_available: Semaphore,
+    in_flight: Fence,
+    // Sets used by this frame only, freed once its fence says the GPU is done with them
+    descriptors: DescriptorAllocator,
+    uniforms: UniformRing,
+}
+
 pub struct VulkanEngine {
-    logical_device: LogicalDevice,
+    scene: Scene,
+    camera: Camera,
+    // Only when shaders are loaded from disk, the embedded ones can't change
+    shader_watcher: Option<ShaderWatcher>,
+    pipeline_files: PipelineFiles,
+    frames: Vec<Frame>,
+    // One per swap chain image, as presentation may still be reading it after the frame's fence signals
+    render_finished: Vec<Semaphore>,
+    command_buffers: Vec<vk::CommandBuffer>,
+    command_pool: CommandPool,
+    // Shares the device, render target and pipeline cache with its worker threads, and joins them when dropped
+    pipeline_compiler: PipelineCompiler,
+    // None on devices without descriptor indexing
+    bindless: Option<BindlessSet>,
+    // Built before the first frame, and drawn with while the pipeline in use is still compiling
+    fallback_pipeline: PipelineHandle,
+    active_pipeline: PipelineHandle,
+    // Empty with dynamic rendering, which needs none
+    framebuffers: Vec<Framebuffer>,
+    depth_buffer: DepthBuffer,
+    // Only when multisampling, resolved into the swap chain image at the end of the frame's rendering
+    colour_target: Option<MultisampleTarget>,
+    // Dynamic rendering where the device has it, a render pass otherwise
+    render_target: RenderTarget,
+    image_views: Vec<ImageView>,
+    swap_chain: SwapChain,
+    physical_device: PhysicalDevice,
+    current_frame: usize,
+    window_size: (u32, u32),
+    swap_chain_dirty: bool,
+    logical_device: Arc<LogicalDevice>,
     surface: Surface,
     instance: Instance, // Must be last
 }
 
 impl VulkanEngine {
     pub fn new(app_name: &str, enable_validation: bool, window: &Window) -> Result<Self> {
+        Self::with_options(app_name, enable_validation, window, &EngineOptions::default())
+    }
+
+    pub fn with_options(app_name: &str, enable_validation: bool, window: &Window, options: &EngineOptions) -> Result<Self> {
         let wsi_exts  = enumerate_required_extensions(window.display_handle()?.into())?;
         let window_dims = window.inner_size();
 
@@ -22,23 +88,435 @@ impl VulkanEngine {
         let surface = Surface::new(&instance, window)?;
         assert_ne!(*surface.raw(), SurfaceKHR::null());
         let physical_device = Self::pick_suitable_device(&instance, &surface)?;
-        let logical_device = LogicalDevice::new(&instance, &physical_device,  &surface, &Self::required_device_prop_names())?;
-        let swap_chain = SwapChain::new(&instance, &physical_device, &logical_device, &surface, window_dims.width, window_dims.height)?;
-        let image_views = swap_chain.images().iter().map(|i| ImageView::new(
-            &logical_device, i, swap_chain.image_format()
-        )).collect::<Result<Vec<_>, _>>()?;
-        let render_pass = RenderPass::new(&logical_device, &swap_chain)?;
-        let graphics_pipeline = GraphicsPipeline::new(&logical_device, &swap_chain)?;
+        let logical_device = Arc::new(LogicalDevice::new(&instance, &physical_device,  &surface, &Self::required_device_prop_names())?);
+        // Saved to disk once the pipeline compiler's workers, the only ones using it, have stopped
+        let pipeline_cache = Arc::new(PipelineCache::new(&logical_device)?);
+        let swap_chain = SwapChain::new(&instance, &physical_device, &logical_device, &surface, window_dims.width, window_dims.height, None)?;
+        let image_views = Self::create_image_views(&logical_device, &swap_chain)?;
+        let depth_formats = if options.stencil { &DEPTH_STENCIL_FORMATS } else { &DEPTH_FORMATS };
+        let depth_format = find_depth_format(&logical_device, depth_formats)?;
+        let samples = clamp_samples(&logical_device, options.samples);
+        if samples != options.samples {
+            println!("{:?} samples aren't supported, using {samples:?}", options.samples);
+        }
+        let depth_buffer = DepthBuffer::new(&logical_device, swap_chain.extent(), depth_format, samples)?;
+        let colour_target = Self::create_colour_target(&logical_device, &swap_chain, samples)?;
+        let render_target = match logical_device.dynamic_rendering() {
+            Some(_) => RenderTarget::Dynamic(RenderingFormats::swap_chain(*swap_chain.image_format(), Some(depth_format), samples)),
+            None => RenderTarget::RenderPass(Arc::new(RenderPassBuilder::swap_chain(*swap_chain.image_format(), Some(depth_format), samples).build(&logical_device)?)),
+        };
+        let framebuffers = Self::create_framebuffers(&logical_device, &render_target, &swap_chain, &image_views, &depth_buffer, colour_target.as_ref())?;
+        let mut pipeline_compiler = PipelineCompiler::new(&logical_device, &pipeline_cache, &render_target)?;
+        let fallback_pipeline = pipeline_compiler.request(&GraphicsPipelineBuilder::default().sample_shading(options.sample_shading));
+        pipeline_compiler.wait(fallback_pipeline)?;
+
+        let bindless = BindlessSet::new(&logical_device)?;
+
+        let command_pool = CommandPool::new(&logical_device)?;
+        let command_buffers = command_pool.allocate_command_buffers(MAX_FRAMES_IN_FLIGHT as u32)?;
+
+        let frames = (0..MAX_FRAMES_IN_FLIGHT).map(|_| Ok(Frame {
+            image_available: Semaphore::new(&logical_device)?,
+            in_flight: Fence::new(&logical_device, true)?,
+            descriptors: DescriptorAllocator::new(&logical_device, 64),
+            uniforms: UniformRing::new(&logical_device)?,
+        })).collect::<Result<Vec<_>>>()?;
+        let render_finished = swap_chain.images().iter()
+            .map(|_| Semaphore::new(&logical_device))
+            .collect::<Result<Vec<_>>>()?;
 
-        // create_graphics_pipeline(&logical_device, &swap_chain)?;
+        let shader_watcher = match shaders::shader_dir() {
+            Some(dir) => Some(ShaderWatcher::new(&dir)?),
+            None => None,
+        };
 
         Ok(VulkanEngine { 
+            scene: Scene::default(),
+            camera: Camera::default(),
+            shader_watcher,
+            pipeline_files: PipelineFiles::new()?,
+            frames,
+            render_finished,
+            command_buffers,
+            command_pool,
+            pipeline_compiler,
+            bindless,
+            fallback_pipeline,
+            active_pipeline: fallback_pipeline,
+            framebuffers,
+            depth_buffer,
+            colour_target,
+            render_target,
+            image_views,
+            swap_chain,
+            physical_device,
+            current_frame: 0,
+            window_size: (window_dims.width, window_dims.height),
+            swap_chain_dirty: false,
             surface,
             instance, 
             logical_device
         })
     }
 
+    // Uploads the geometry and draws it every frame from now on
+    pub fn add_mesh(&mut self, data: &MeshData) -> Result<()> {
+        self.scene.add_mesh(&self.logical_device, &self.command_pool, data, Mat4::IDENTITY)
+    }
+
+    // Uploads all meshes and images, then draws every mesh instance from now on
+    pub fn add_gltf_scene(&mut self, gltf: &GltfScene) -> Result<()> {
+        self.scene.add_gltf(&self.logical_device, &self.command_pool, gltf)
+    }
+
+    // Decodes a PNG or JPEG and uploads it to device local memory
+    pub fn load_texture<P: AsRef<std::path::Path>>(&self, path: P, usage: TextureUsage, sampler_options: &SamplerOptions) -> Result<Texture> {
+        Texture::load(&self.logical_device, &self.command_pool, path, usage, sampler_options)
+    }
+
+    // Loads a pre-built KTX2 or DDS texture
+    pub fn load_texture_container<P: AsRef<std::path::Path>>(&self, path: P, sampler_options: &SamplerOptions) -> Result<Texture> {
+        Texture::load_container(&self.logical_device, &self.command_pool, path, sampler_options)
+    }
+
+    pub fn scene_bounds(&self) -> Option<Bounds> {
+        self.scene.bounds()
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    // Starts building a pipeline in the background, e.g. to have a material ready before it's needed
+    pub fn request_pipeline(&mut self, builder: &GraphicsPipelineBuilder) -> PipelineHandle {
+        self.pipeline_compiler.request(builder)
+    }
+
+    pub fn pipeline_status(&self, handle: PipelineHandle) -> PipelineStatus<'_> {
+        self.pipeline_compiler.status(handle)
+    }
+
+    // Draws with the fallback pipeline until this one is built, or if it fails to build
+    pub fn use_pipeline(&mut self, handle: PipelineHandle) {
+        self.active_pipeline = handle;
+    }
+
+    // Switches the specialization constants the scene is drawn with, keeping the rest of the current pipeline
+    pub fn set_specialization(&mut self, specialization: PipelineSpecialization) -> PipelineHandle {
+        let builder = self.pipeline_compiler.builder(self.active_pipeline).clone().specialization(specialization);
+        let handle = self.request_pipeline(&builder);
+        self.use_pipeline(handle);
+
+        handle
+    }
+
+    // e.g. PipelineShaders::combined("shader.wgsl", "vs_main", "fs_main")
+    pub fn set_shaders(&mut self, shaders: PipelineShaders) -> PipelineHandle {
+        let builder = self.pipeline_compiler.builder(self.active_pipeline).clone().shaders(shaders);
+        let handle = self.request_pipeline(&builder);
+        self.use_pipeline(handle);
+
+        handle
+    }
+
+    // A pipeline from a .ron or .toml description, rebuilt whenever the file is saved
+    pub fn load_pipeline<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<PipelineHandle> {
+        let builder = PipelineDescription::load(path.as_ref())?.builder()?;
+        let handle = self.pipeline_compiler.request_unique(&builder);
+        self.pipeline_files.watch(path.as_ref(), handle)?;
+
+        Ok(handle)
+    }
+
+    // Registers textures and buffers for pipelines built with GraphicsPipelineBuilder::bindless.
+    // None when the device can't do bindless, and those pipelines would fail to build
+    pub fn bindless(&mut self) -> Option<&mut BindlessSet> {
+        self.bindless.as_mut()
+    }
+
+    // Uniform data for the frame being recorded, for pipelines built with GraphicsPipelineBuilder::uniform_ring
+    pub fn push_uniform<T: Pod>(&mut self, value: &T) -> Result<UniformAllocation> {
+        self.frames[self.current_frame].uniforms.push(value)
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.window_size = (width, height);
+        self.swap_chain_dirty = true;
+    }
+
+    pub fn draw_frame(&mut self) -> Result<()> {
+        // Nothing to draw into while minimised
+        if self.window_size.0 == 0 || self.window_size.1 == 0 {
+            return Ok(());
+        }
+
+        if self.swap_chain_dirty {
+            self.recreate_swap_chain()?;
+        }
+
+        if self.shader_watcher.as_ref().is_some_and(|watcher| watcher.poll()) {
+            self.pipeline_compiler.rebuild_all();
+        }
+
+        for (path, handle) in self.pipeline_files.poll() {
+            match PipelineDescription::load(&path).and_then(|description| description.builder()) {
+                Ok(builder) => self.pipeline_compiler.update(handle, &builder),
+                Err(e) => eprintln!("Cannot reload pipeline {}, keeping the previous one: {e}", path.display()),
+            }
+        }
+
+        let replaced = self.pipeline_compiler.poll();
+        if !replaced.is_empty() {
+            // Frames in flight may still be using the old pipelines
+            unsafe { self.logical_device.raw().device_wait_idle()? };
+            drop(replaced);
+        }
+
+        self.frames[self.current_frame].in_flight.wait()?;
+        self.frames[self.current_frame].descriptors.reset()?;
+        self.frames[self.current_frame].uniforms.reset()?;
+        let frame = &self.frames[self.current_frame];
+
+        let image_index = match self.swap_chain.acquire_next_image(*frame.image_available.raw()) {
+            Ok((image_index, _)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swap_chain_dirty = true;
+                return Ok(());
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        // Only reset once we know work will be submitted, otherwise the next wait deadlocks
+        frame.in_flight.reset()?;
+
+        let command_buffer = self.command_buffers[self.current_frame];
+        self.record_command_buffer(command_buffer, image_index)?;
+
+        let render_finished = *self.render_finished[image_index as usize].raw();
+        let wait_stage = PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+        let submit_info = SubmitInfo {
+            wait_semaphore_count: 1,
+            p_wait_semaphores: frame.image_available.raw(),
+            p_wait_dst_stage_mask: &wait_stage,
+            command_buffer_count: 1,
+            p_command_buffers: &command_buffer,
+            signal_semaphore_count: 1,
+            p_signal_semaphores: &render_finished,
+            ..Default::default()
+        };
+
+        unsafe { self.logical_device.raw().queue_submit(self.logical_device.graphics_queue(), &[submit_info], *frame.in_flight.raw())? };
+
+        match self.swap_chain.present(self.logical_device.present_queue(), image_index, render_finished) {
+            Ok(false) => {},
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.swap_chain_dirty = true,
+            Err(e) => return Err(e.into()),
+        }
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+        Ok(())
+    }
+
+    fn record_command_buffer(&self, command_buffer: vk::CommandBuffer, image_index: u32) -> Result<()> {
+        let device = self.logical_device.raw();
+        let extent = *self.swap_chain.extent();
+        let render_area = Rect2D { offset: Offset2D { x: 0, y: 0 }, extent };
+        let pipeline = self.pipeline_compiler.get(self.active_pipeline)
+            .or_else(|| self.pipeline_compiler.get(self.fallback_pipeline))
+            .ok_or_else(|| Error::msg("The fallback pipeline is missing"))?;
+
+        // In attachment order, colour then depth
+        let clear_values = [
+            ClearValue { color: ClearColorValue { float32: [0.125, 0.125, 0.25, 1.0] } },
+            ClearValue { depth_stencil: ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+        ];
+
+        let viewport = Viewport {
+            x: 0.0f32,
+            y: 0.0f32,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0f32,
+            max_depth: 1.0f32
+        };
+
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(command_buffer, &CommandBufferBeginInfo::default())?;
+
+            match &self.render_target {
+                RenderTarget::RenderPass(render_pass) => {
+                    // VkRenderPassBeginInfo
+                    let render_pass_begin_info = RenderPassBeginInfo {
+                        render_pass: *render_pass.raw(),
+                        framebuffer: *self.framebuffers[image_index as usize].raw(),
+                        render_area,
+                        clear_value_count: clear_values.len() as u32,
+                        p_clear_values: clear_values.as_ptr(),
+                        ..Default::default()
+                    };
+                    device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, SubpassContents::INLINE);
+                },
+                RenderTarget::Dynamic(_) => self.begin_rendering(command_buffer, image_index, render_area, &clear_values)?,
+            }
+
+            device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::GRAPHICS, *pipeline.raw());
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+            if let (Some(set), Some(bindless)) = (pipeline.layout().bindless_set(), &self.bindless) {
+                device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::GRAPHICS, *pipeline.layout().raw(), set, &[bindless.set()], &[]);
+            }
+
+            let aspect = extent.width as f32 / extent.height as f32;
+            self.scene.record(device, command_buffer, pipeline.layout(), self.camera.view_projection(aspect));
+
+            match &self.render_target {
+                RenderTarget::RenderPass(_) => device.cmd_end_render_pass(command_buffer),
+                RenderTarget::Dynamic(_) => self.end_rendering(command_buffer, image_index)?,
+            }
+            device.end_command_buffer(command_buffer)?;
+        }
+
+        Ok(())
+    }
+
+    // Does by hand what the render pass's layouts and dependency do: the swap chain image moves out of UNDEFINED
+    // once the acquire semaphore is waited on at COLOR_ATTACHMENT_OUTPUT, and the shared depth buffer
+    // and multisampled target wait for the previous frame to finish with them
+    fn begin_rendering(&self, command_buffer: vk::CommandBuffer, image_index: u32, render_area: Rect2D, clear_values: &[ClearValue; 2]) -> Result<()> {
+        let device = self.logical_device.raw();
+        let dynamic_rendering = self.logical_device.dynamic_rendering()
+            .ok_or_else(|| Error::msg("Drawing with dynamic rendering, which this device doesn't support"))?;
+
+        let swap_chain_image = self.swap_chain.images()[image_index as usize];
+        let mut barriers = vec![swap_chain_image_barrier(
+            swap_chain_image,
+            ImageLayout::UNDEFINED,
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            AccessFlags::empty(),
+            AccessFlags::COLOR_ATTACHMENT_WRITE
+        )];
+        let depth_image = self.depth_buffer.image();
+        barriers.push(depth_image.barrier(
+            depth_image.full_range(),
+            ImageLayout::UNDEFINED,
+            ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+        ));
+        if let Some(colour_target) = &self.colour_target {
+            let image = colour_target.image();
+            barriers.push(image.barrier(
+                image.full_range(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                AccessFlags::COLOR_ATTACHMENT_WRITE,
+                AccessFlags::COLOR_ATTACHMENT_WRITE
+            ));
+        }
+
+        let stages = PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        unsafe { device.cmd_pipeline_barrier(command_buffer, stages, stages, vk::DependencyFlags::empty(), &[], &[], &barriers) };
+
+        let swap_chain_view = &self.image_views[image_index as usize];
+        let colour = match &self.colour_target {
+            Some(colour_target) => RenderingAttachment::resolved(colour_target.view(), swap_chain_view, clear_values[0]),
+            None => RenderingAttachment::cleared(swap_chain_view, clear_values[0]),
+        };
+        let depth = RenderingAttachment::transient(self.depth_buffer.view(), clear_values[1]);
+        let stencil = depth_buffer::has_stencil(self.depth_buffer.format()).then_some(&depth);
+        dynamic_rendering.begin(command_buffer, render_area, &[colour], Some(&depth), stencil);
+
+        Ok(())
+    }
+
+    // Moves the swap chain image to PRESENT_SRC once drawing to it is done, which the render pass
+    // does with its final layout
+    fn end_rendering(&self, command_buffer: vk::CommandBuffer, image_index: u32) -> Result<()> {
+        let dynamic_rendering = self.logical_device.dynamic_rendering()
+            .ok_or_else(|| Error::msg("Drawing with dynamic rendering, which this device doesn't support"))?;
+        dynamic_rendering.end(command_buffer);
+
+        let barrier = swap_chain_image_barrier(
+            self.swap_chain.images()[image_index as usize],
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageLayout::PRESENT_SRC_KHR,
+            AccessFlags::COLOR_ATTACHMENT_WRITE,
+            AccessFlags::empty()
+        );
+        unsafe {
+            self.logical_device.raw().cmd_pipeline_barrier(
+                command_buffer,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+
+        Ok(())
+    }
+
+    fn recreate_swap_chain(&mut self) -> Result<()> {
+        unsafe { self.logical_device.raw().device_wait_idle()? };
+
+        // Everything that points at the old swap chain images has to go first
+        self.framebuffers.clear();
+        self.image_views.clear();
+
+        let (width, height) = self.window_size;
+        let swap_chain = SwapChain::new(&self.instance, &self.physical_device, &self.logical_device, &self.surface, width, height, Some(&self.swap_chain))?;
+        self.swap_chain = swap_chain;
+
+        self.image_views = Self::create_image_views(&self.logical_device, &self.swap_chain)?;
+        let samples = self.depth_buffer.samples();
+        self.depth_buffer = DepthBuffer::new(&self.logical_device, self.swap_chain.extent(), self.depth_buffer.format(), samples)?;
+        self.colour_target = Self::create_colour_target(&self.logical_device, &self.swap_chain, samples)?;
+        self.framebuffers = Self::create_framebuffers(&self.logical_device, &self.render_target, &self.swap_chain, &self.image_views, &self.depth_buffer, self.colour_target.as_ref())?;
+
+        if self.render_finished.len() != self.swap_chain.images().len() {
+            self.render_finished = self.swap_chain.images().iter()
+                .map(|_| Semaphore::new(&self.logical_device))
+                .collect::<Result<Vec<_>>>()?;
+        }
+
+        self.swap_chain_dirty = false;
+
+        Ok(())
+    }
+
+    fn create_image_views(logical_device: &LogicalDevice, swap_chain: &SwapChain) -> Result<Vec<ImageView>> {
+        swap_chain.images().iter().map(|i| ImageView::new(
+            logical_device, i, swap_chain.image_format()
+        )).collect()
+    }
+
+    fn create_colour_target(logical_device: &LogicalDevice, swap_chain: &SwapChain, samples: SampleCountFlags) -> Result<Option<MultisampleTarget>> {
+        if samples == SampleCountFlags::TYPE_1 {
+            return Ok(None);
+        }
+
+        Ok(Some(MultisampleTarget::new(logical_device, swap_chain.extent(), *swap_chain.image_format(), samples)?))
+    }
+
+    // Every framebuffer shares the one depth buffer and multisampled target, which the render pass keeps frames
+    // from using at once. The attachments are in the render pass's order, with the swap chain image resolved into last
+    fn create_framebuffers(logical_device: &LogicalDevice, render_target: &RenderTarget, swap_chain: &SwapChain, image_views: &[ImageView], depth_buffer: &DepthBuffer, colour_target: Option<&MultisampleTarget>) -> Result<Vec<Framebuffer>> {
+        let RenderTarget::RenderPass(render_pass) = render_target else { return Ok(vec![]) };
+
+        image_views.iter().map(|view| {
+            let attachments = match colour_target {
+                Some(target) => vec![target.view(), depth_buffer.view(), view],
+                None => vec![view, depth_buffer.view()],
+            };
+            Framebuffer::new(logical_device, render_pass, &attachments, swap_chain.extent())
+        }).collect()
+    }
+
     fn required_device_prop_names() -> Vec<String> {
         let swapchain = ash::khr::swapchain::NAME;
         let swapchain = swapchain.to_str().unwrap().to_string();
@@ -125,3 +603,30 @@ fn check_physical_device_extension_support(instance: &Instance, physical_device:
     Ok(required_props_names.is_empty())
 }
 
+// VkImageMemoryBarrier for the one mip level and layer of a swap chain image, which isn't an Image
+fn swap_chain_image_barrier(image: vk::Image, old_layout: ImageLayout, new_layout: ImageLayout, src_access_mask: AccessFlags, dst_access_mask: AccessFlags) -> vk::ImageMemoryBarrier<'static> {
+    vk::ImageMemoryBarrier {
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        image,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1
+        },
+        src_access_mask,
+        dst_access_mask,
+        ..Default::default()
+    }
+}
+
+impl Drop for VulkanEngine {
+    fn drop(&mut self) {
+        // The GPU may still be using resources owned by the fields below
+        unsafe { self.logical_device.raw().device_wait_idle().ok() };
+    }
+}
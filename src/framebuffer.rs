@@ -0,0 +1,48 @@
+use ash::vk::{self, Extent2D};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, image_view::ImageView, render_pass::RenderPass};
+
+pub struct Framebuffer {
+    raw: vk::Framebuffer,
+    device: ash::Device
+}
+
+impl Framebuffer {
+    pub fn new(logical_device: &LogicalDevice, render_pass: &RenderPass, attachments: &[&ImageView], extent: &Extent2D) -> Result<Self> {
+        if attachments.len() as u32 != render_pass.attachment_count() {
+            return Err(Error::msg(format!("The render pass has {} attachments, but the framebuffer was given {}", render_pass.attachment_count(), attachments.len())));
+        }
+        let attachments: Vec<vk::ImageView> = attachments.iter().map(|a| *a.raw()).collect();
+
+        // VkFramebufferCreateInfo
+        let create_info = vk::FramebufferCreateInfo {
+            render_pass: *render_pass.raw(),
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+            ..Default::default()
+        };
+
+        let framebuffer = unsafe { logical_device.raw().create_framebuffer(&create_info, None)? };
+
+        Ok(Self {
+            raw: framebuffer,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn raw(&self) -> &vk::Framebuffer {
+        &self.raw
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_framebuffer(self.raw, None);
+        }
+    }
+}
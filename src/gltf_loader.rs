@@ -0,0 +1,462 @@
+use std::path::Path;
+
+use anyhow::{Error, Result};
+use glam::Mat4;
+use gltf::{image::Format, mesh::Mode};
+
+use crate::{mesh::{Bounds, MeshData, Vertex}, texture::TextureUsage};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask { cutoff: f32 },
+    Blend,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRef {
+    // Index into `GltfScene::images`
+    pub image: usize,
+    pub tex_coord: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_colour_factor: [f32; 4],
+    pub base_colour_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for PbrMaterial {
+    // The spec's default material
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_colour_factor: [1.0; 4],
+            base_colour_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+// Decoded texels, always expanded to RGBA8
+#[derive(Clone, Debug)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub usage: TextureUsage,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfPrimitive {
+    pub data: MeshData,
+    pub material: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+// A mesh placed in the world, with the node hierarchy already flattened
+#[derive(Clone, Debug)]
+pub struct GltfInstance {
+    pub name: Option<String>,
+    pub mesh: usize,
+    pub transform: Mat4,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub instances: Vec<GltfInstance>,
+    pub materials: Vec<PbrMaterial>,
+    pub images: Vec<ImageData>,
+}
+
+impl GltfScene {
+    // Handles both .gltf and .glb. Buffers and images may be embedded or sit next to the file,
+    // anything that would need fetching from the network is rejected
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let (document, buffers, images) = gltf::import(path)
+            .map_err(|e| Error::msg(format!("{}: {e}", path.display())))?;
+
+        // None of the extensions are implemented, so using any of them would lose data
+        let required: Vec<&str> = document.extensions_required().collect();
+        let unsupported: Vec<String> = document.extensions_used()
+            .map(|e| if required.contains(&e) { format!("{e} (required)") } else { e.to_string() })
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(Error::msg(format!("{}: unsupported glTF extensions: {}", path.display(), unsupported.join(", "))));
+        }
+
+        let mut scene = GltfScene {
+            materials: document.materials().map(load_material).collect(),
+            ..Default::default()
+        };
+
+        let colour_images = colour_images(&scene.materials);
+        scene.images = images.into_iter().enumerate()
+            .map(|(ix, image)| {
+                let usage = if colour_images.contains(&ix) { TextureUsage::Colour } else { TextureUsage::Data };
+                convert_image(image, usage).map_err(|e| Error::msg(format!("{}: image {ix}: {e}", path.display())))
+            })
+            .collect::<Result<_>>()?;
+
+        for ignored in ignored_data(&document) {
+            eprintln!("{}: {ignored}", path.display());
+        }
+
+        for mesh in document.meshes() {
+            let mut primitives = vec![];
+
+            for primitive in mesh.primitives() {
+                let data = load_primitive(&primitive, &buffers)
+                    .map_err(|e| Error::msg(format!("{}: mesh {} primitive {}: {e}", path.display(), mesh.index(), primitive.index())))?;
+
+                primitives.push(GltfPrimitive {
+                    data,
+                    material: primitive.material().index(),
+                });
+            }
+
+            scene.meshes.push(GltfMesh {
+                name: mesh.name().map(str::to_string),
+                primitives,
+            });
+        }
+
+        match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(gltf_scene) => {
+                for node in gltf_scene.nodes() {
+                    collect_instances(&node, Mat4::IDENTITY, &mut scene.instances);
+                }
+            },
+            // Files without scenes are libraries of meshes, show them all at the origin
+            None => {
+                scene.instances = (0..scene.meshes.len()).map(|mesh| GltfInstance {
+                    name: None,
+                    mesh,
+                    transform: Mat4::IDENTITY,
+                }).collect();
+            },
+        }
+
+        Ok(scene)
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.instances.iter()
+            .flat_map(|instance| self.meshes[instance.mesh].primitives.iter()
+                .filter_map(|p| p.data.bounds())
+                .map(|b| b.transformed(&instance.transform)))
+            .reduce(Bounds::union)
+    }
+}
+
+// What the file has that the loader drops, one line each so they can be reported rather than lost silently.
+// Vertex only has a position, normal and one set of UVs, and nothing is animated
+fn ignored_data(document: &gltf::Document) -> Vec<String> {
+    let mut ignored = vec![];
+
+    let mut attributes: Vec<String> = document.meshes()
+        .flat_map(|mesh| mesh.primitives())
+        .flat_map(|primitive| primitive.attributes())
+        .map(|(semantic, _)| semantic)
+        .filter(|semantic| !matches!(semantic, gltf::Semantic::Positions | gltf::Semantic::Normals | gltf::Semantic::TexCoords(0)))
+        .map(|semantic| semantic.to_string())
+        .collect();
+    attributes.sort();
+    attributes.dedup();
+    if !attributes.is_empty() {
+        ignored.push(format!("ignoring the {} vertex attributes", attributes.join(", ")));
+    }
+
+    let morphed = document.meshes().flat_map(|mesh| mesh.primitives()).any(|primitive| primitive.morph_targets().next().is_some());
+    if morphed {
+        ignored.push("ignoring morph targets".to_string());
+    }
+
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let tex_coords = [
+            pbr.base_color_texture().map(|t| t.tex_coord()),
+            pbr.metallic_roughness_texture().map(|t| t.tex_coord()),
+            material.normal_texture().map(|t| t.tex_coord()),
+            material.occlusion_texture().map(|t| t.tex_coord()),
+            material.emissive_texture().map(|t| t.tex_coord()),
+        ];
+        if let Some(tex_coord) = tex_coords.into_iter().flatten().find(|&tex_coord| tex_coord != 0) {
+            let name = material.index().map_or("the default material".to_string(), |ix| format!("material {ix}"));
+            ignored.push(format!("{name} samples TEXCOORD_{tex_coord}, but only TEXCOORD_0 is loaded"));
+        }
+    }
+
+    let skins = document.skins().count();
+    if skins > 0 {
+        ignored.push(format!("ignoring {skins} skins, meshes are drawn in their bind pose"));
+    }
+
+    let animations = document.animations().count();
+    if animations > 0 {
+        ignored.push(format!("ignoring {animations} animations"));
+    }
+
+    ignored
+}
+
+fn collect_instances(node: &gltf::Node, parent_transform: Mat4, instances: &mut Vec<GltfInstance>) {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        instances.push(GltfInstance {
+            name: node.name().map(str::to_string),
+            mesh: mesh.index(),
+            transform,
+        });
+    }
+
+    for child in node.children() {
+        collect_instances(&child, transform, instances);
+    }
+}
+
+fn texture_ref(info: Option<gltf::texture::Info>) -> Option<TextureRef> {
+    info.map(|i| TextureRef {
+        image: i.texture().source().index(),
+        tex_coord: i.tex_coord(),
+    })
+}
+
+fn load_material(material: gltf::Material) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    PbrMaterial {
+        name: material.name().map(str::to_string),
+        base_colour_factor: pbr.base_color_factor(),
+        base_colour_texture: texture_ref(pbr.base_color_texture()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: texture_ref(pbr.metallic_roughness_texture()),
+        normal_texture: normal.as_ref().map(|n| TextureRef { image: n.texture().source().index(), tex_coord: n.tex_coord() }),
+        normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+        occlusion_texture: occlusion.as_ref().map(|o| TextureRef { image: o.texture().source().index(), tex_coord: o.tex_coord() }),
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: texture_ref(material.emissive_texture()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask { cutoff: material.alpha_cutoff().unwrap_or(0.5) },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn colour_images(materials: &[PbrMaterial]) -> Vec<usize> {
+    materials.iter()
+        .flat_map(|m| [m.base_colour_texture, m.emissive_texture])
+        .flatten()
+        .map(|t| t.image)
+        .collect()
+}
+
+fn convert_image(image: gltf::image::Data, usage: TextureUsage) -> Result<ImageData> {
+    let byte_count = (image.width as usize).checked_mul(image.height as usize).and_then(|texels| texels.checked_mul(4))
+        .ok_or_else(|| Error::msg(format!("a {}x{} image is too large", image.width, image.height)))?;
+    let mut pixels = Vec::with_capacity(byte_count);
+
+    // 16 bit images keep their most significant byte
+    let narrow = |bytes: &[u8]| (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8;
+
+    match image.format {
+        Format::R8 => image.pixels.iter().for_each(|&r| pixels.extend([r, r, r, 255])),
+        Format::R8G8 => image.pixels.chunks_exact(2).for_each(|p| pixels.extend([p[0], p[1], 0, 255])),
+        Format::R8G8B8 => image.pixels.chunks_exact(3).for_each(|p| pixels.extend([p[0], p[1], p[2], 255])),
+        Format::R8G8B8A8 => pixels = image.pixels,
+        Format::R16 => image.pixels.chunks_exact(2).for_each(|p| {
+            let r = narrow(p);
+            pixels.extend([r, r, r, 255]);
+        }),
+        Format::R16G16 => image.pixels.chunks_exact(4).for_each(|p| pixels.extend([narrow(&p[0..]), narrow(&p[2..]), 0, 255])),
+        Format::R16G16B16 => image.pixels.chunks_exact(6).for_each(|p| pixels.extend([narrow(&p[0..]), narrow(&p[2..]), narrow(&p[4..]), 255])),
+        Format::R16G16B16A16 => image.pixels.chunks_exact(8).for_each(|p| pixels.extend([narrow(&p[0..]), narrow(&p[2..]), narrow(&p[4..]), narrow(&p[6..])])),
+        other => return Err(Error::msg(format!("unsupported pixel format {:?}", other))),
+    }
+
+    Ok(ImageData {
+        width: image.width,
+        height: image.height,
+        pixels,
+        usage,
+    })
+}
+
+fn load_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Result<MeshData> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = reader.read_positions()
+        .ok_or_else(|| Error::msg("missing POSITION attribute"))?
+        .collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+    let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+
+    // Every attribute has to cover every vertex
+    for (name, count) in [("NORMAL", normals.as_ref().map(Vec::len)), ("TEXCOORD_0", uvs.as_ref().map(Vec::len))] {
+        if let Some(count) = count && count != positions.len() {
+            return Err(Error::msg(format!("{name} has {count} elements, but POSITION has {}", positions.len())));
+        }
+    }
+
+    let vertices = positions.iter().enumerate().map(|(ix, &position)| Vertex {
+        position,
+        normal: normals.as_ref().map_or([0.0; 3], |n| n[ix]),
+        uv: uvs.as_ref().map_or([0.0; 2], |t| t[ix]),
+    }).collect();
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if let Some(&index) = indices.iter().find(|&&index| index as usize >= positions.len()) {
+        return Err(Error::msg(format!("index {index} is out of range, there are {} vertices", positions.len())));
+    }
+
+    let indices = match primitive.mode() {
+        Mode::Triangles => indices,
+        Mode::TriangleStrip => (2..indices.len())
+            // Every other triangle has its winding flipped
+            .flat_map(|i| if i % 2 == 0 {
+                [indices[i - 2], indices[i - 1], indices[i]]
+            } else {
+                [indices[i - 1], indices[i - 2], indices[i]]
+            })
+            .collect(),
+        Mode::TriangleFan => (2..indices.len())
+            .flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+            .collect(),
+        other => return Err(Error::msg(format!("unsupported primitive mode {:?}", other))),
+    };
+
+    let mut data = MeshData { vertices, indices };
+    // The spec asks for flat shading when normals are left out
+    if normals.is_none() {
+        data.generate_flat_normals();
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A triangle whose buffer holds three positions, then the normals and indices given
+    fn load_triangle(name: &str, normal_count: usize, indices: [u16; 3]) -> Result<GltfScene> {
+        let dir = std::env::temp_dir().join(format!("vulkrust-gltf-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let mut bin: Vec<u8> = bytemuck::cast_slice(&positions).to_vec();
+        bin.extend(std::iter::repeat_n(bytemuck::bytes_of(&[0.0f32, 0.0, 1.0]).to_vec(), normal_count).flatten());
+        let indices_offset = bin.len();
+        bin.extend(bytemuck::cast_slice(&indices));
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        std::fs::write(dir.join("triangle.bin"), &bin).unwrap();
+
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "uri": "triangle.bin", "byteLength": {} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": {} }},
+                {{ "buffer": 0, "byteOffset": {indices_offset}, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": {normal_count}, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2 }}] }}]
+        }}"#, bin.len(), normal_count * 12);
+        std::fs::write(dir.join("triangle.gltf"), gltf).unwrap();
+
+        let scene = GltfScene::load(dir.join("triangle.gltf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        scene
+    }
+
+    #[test]
+    fn loads_a_triangle() {
+        let scene = load_triangle("valid", 3, [0, 1, 2]).unwrap();
+
+        assert_eq!(scene.meshes[0].primitives[0].data.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_short_normals() {
+        let error = load_triangle("short", 2, [0, 1, 2]).unwrap_err();
+
+        assert!(error.to_string().contains("NORMAL has 2 elements, but POSITION has 3"), "{error}");
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let error = load_triangle("indices", 3, [0, 1, 7]).unwrap_err();
+
+        assert!(error.to_string().contains("index 7 is out of range"), "{error}");
+    }
+
+    #[test]
+    fn reports_ignored_data() {
+        let gltf = br#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 72 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 72 }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC2" },
+                { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC4" }
+            ],
+            "images": [{ "uri": "lightmap.png" }],
+            "textures": [{ "source": 0 }],
+            "materials": [{ "occlusionTexture": { "index": 0, "texCoord": 1 } }],
+            "meshes": [{ "primitives": [{
+                "attributes": { "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 1, "COLOR_0": 2, "WEIGHTS_0": 2 },
+                "material": 0
+            }] }]
+        }"#;
+        let document = gltf::Gltf::from_slice(gltf).unwrap().document;
+
+        assert_eq!(ignored_data(&document), [
+            "ignoring the COLOR_0, TEXCOORD_1, WEIGHTS_0 vertex attributes",
+            "material 0 samples TEXCOORD_1, but only TEXCOORD_0 is loaded",
+        ]);
+    }
+}
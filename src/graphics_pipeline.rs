@@ -1,142 +1,864 @@
-use crate::{LogicalDevice, shader_module::ShaderModule, swap_chain::SwapChain, utils::read_file};
-use anyhow::Result;
-use ash::vk::{CullModeFlags, FrontFace, Offset2D, PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo, PipelineInputAssemblyStateCreateInfo, PipelineLayout, PipelineLayoutCreateInfo, PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode, PrimitiveTopology, ShaderStageFlags, Viewport};
+use std::{ffi::CString, hash::{Hash, Hasher}, sync::Arc};
+
+use crate::{LogicalDevice, depth_buffer, dynamic_rendering::RenderingFormats, mesh::{Vertex, VertexInputDescription}, pipeline_cache::PipelineCache, pipeline_layout::PipelineLayout, push_constants::{PushConstants, PushConstantsLayout}, reflection::{self, EntryPoint, IoType, ScalarType, ShaderReflection}, render_pass::{RenderPass, SubpassInfo}, scene::MeshPushConstants, shader_module::ShaderModule, shaders::load_shader, specialization::{PipelineSpecialization, Specialization}};
+use anyhow::{Error, Result};
+use serde::Deserialize;
+use ash::vk::{self, BlendFactor, BlendOp, ColorComponentFlags, CompareOp, CullModeFlags, DynamicState, FrontFace, GraphicsPipelineCreateInfo, LogicOp, Pipeline, PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo, PipelineDepthStencilStateCreateInfo, PipelineInputAssemblyStateCreateInfo, PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode, PrimitiveTopology, SampleCountFlags, ShaderStageFlags};
 
 
 pub struct GraphicsPipeline {
-    pipeline_layout: PipelineLayout,
+    raw: Pipeline,
+    layout: PipelineLayout,
     device: ash::Device
 }
 
 impl GraphicsPipeline {
-    pub fn new(logical_device: &LogicalDevice, swap_chain: &SwapChain) -> Result<Self> {
-        let vertex_shader = read_file("shaders/out/vert.spv")?;
-        let fragment_shader = read_file("shaders/out/frag.spv")?;
+    pub fn raw(&self) -> &Pipeline {
+        &self.raw
+    }
+
+    pub fn layout(&self) -> &PipelineLayout {
+        &self.layout
+    }
+
+    pub fn push<T: PushConstants>(&self, command_buffer: vk::CommandBuffer, value: &T) {
+        self.layout.push(command_buffer, value);
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        unsafe {
+            println!("Dropping GraphicsPipeline");
+
+            self.device.destroy_pipeline(self.raw, None);
+        }
+    }
+}
+
+// What pipelines are built to draw into: a subpass of a render pass, or attachments
+// of these formats begun with DynamicRendering::begin
+#[derive(Clone)]
+pub enum RenderTarget {
+    RenderPass(Arc<RenderPass>),
+    Dynamic(RenderingFormats),
+}
+
+impl RenderTarget {
+    // Dynamic rendering has no subpasses beyond the one
+    pub fn subpass(&self, subpass: u32) -> Option<SubpassInfo> {
+        match self {
+            RenderTarget::RenderPass(render_pass) => render_pass.subpass(subpass).copied(),
+            RenderTarget::Dynamic(formats) => (subpass == 0).then(|| formats.info()),
+        }
+    }
+}
+
+// A shader and the entry point to use from it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderEntry {
+    pub shader: String,
+    pub entry_point: String,
+}
+
+impl ShaderEntry {
+    pub fn new(shader: &str, entry_point: &str) -> Self {
+        Self {
+            shader: shader.to_string(),
+            entry_point: entry_point.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineShaders {
+    pub vertex: ShaderEntry,
+    pub fragment: ShaderEntry,
+}
+
+impl PipelineShaders {
+    // Both stages from one module, like WGSL, Slang and HLSL toolchains produce
+    pub fn combined(shader: &str, vertex_entry_point: &str, fragment_entry_point: &str) -> Self {
+        Self {
+            vertex: ShaderEntry::new(shader, vertex_entry_point),
+            fragment: ShaderEntry::new(shader, fragment_entry_point),
+        }
+    }
+}
+
+impl Default for PipelineShaders {
+    fn default() -> Self {
+        Self {
+            vertex: ShaderEntry::new("shader.vert", "main"),
+            fragment: ShaderEntry::new("shader.frag", "main"),
+        }
+    }
+}
+
+// How one colour attachment is blended: src * src_factor (op) dst * dst_factor, for colour and alpha separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlendState {
+    pub enable: bool,
+    pub src_colour_factor: BlendFactor,
+    pub dst_colour_factor: BlendFactor,
+    pub colour_op: BlendOp,
+    pub src_alpha_factor: BlendFactor,
+    pub dst_alpha_factor: BlendFactor,
+    pub alpha_op: BlendOp,
+    pub write_mask: ColorComponentFlags,
+}
+
+impl BlendState {
+    pub const OPAQUE: Self = Self {
+        enable: false,
+        src_colour_factor: BlendFactor::ONE,
+        dst_colour_factor: BlendFactor::ZERO,
+        colour_op: BlendOp::ADD,
+        src_alpha_factor: BlendFactor::ONE,
+        dst_alpha_factor: BlendFactor::ZERO,
+        alpha_op: BlendOp::ADD,
+        write_mask: ColorComponentFlags::RGBA,
+    };
+
+    // Straight alpha, for textures whose colour hasn't been multiplied by alpha
+    pub const ALPHA_BLENDED: Self = Self {
+        enable: true,
+        src_colour_factor: BlendFactor::SRC_ALPHA,
+        dst_colour_factor: BlendFactor::ONE_MINUS_SRC_ALPHA,
+        src_alpha_factor: BlendFactor::ONE,
+        dst_alpha_factor: BlendFactor::ONE_MINUS_SRC_ALPHA,
+        ..Self::OPAQUE
+    };
+
+    // Light adds up, for particles, glows and the like. Order doesn't matter
+    pub const ADDITIVE: Self = Self {
+        enable: true,
+        src_colour_factor: BlendFactor::SRC_ALPHA,
+        dst_colour_factor: BlendFactor::ONE,
+        src_alpha_factor: BlendFactor::ONE,
+        dst_alpha_factor: BlendFactor::ONE,
+        ..Self::OPAQUE
+    };
+
+    // For colour already multiplied by alpha, which also filters and mips without dark fringes
+    pub const PREMULTIPLIED: Self = Self {
+        enable: true,
+        src_colour_factor: BlendFactor::ONE,
+        dst_colour_factor: BlendFactor::ONE_MINUS_SRC_ALPHA,
+        src_alpha_factor: BlendFactor::ONE,
+        dst_alpha_factor: BlendFactor::ONE_MINUS_SRC_ALPHA,
+        ..Self::OPAQUE
+    };
+
+    fn raw(&self) -> PipelineColorBlendAttachmentState {
+        PipelineColorBlendAttachmentState {
+            blend_enable: self.enable.into(),
+            src_color_blend_factor: self.src_colour_factor,
+            dst_color_blend_factor: self.dst_colour_factor,
+            color_blend_op: self.colour_op,
+            src_alpha_blend_factor: self.src_alpha_factor,
+            dst_alpha_blend_factor: self.dst_alpha_factor,
+            alpha_blend_op: self.alpha_op,
+            color_write_mask: self.write_mask,
+        }
+    }
+}
+
+// Depth written is offset by constant_factor * r + slope_factor * slope, limited to clamp if that isn't 0.
+// Mostly for shadow maps
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+impl DepthBias {
+    // f32 isn't Eq or Hash, and pipelines are keyed on their builder, so compare the bits
+    fn bits(&self) -> [u32; 3] {
+        [self.constant_factor.to_bits(), self.clamp.to_bits(), self.slope_factor.to_bits()]
+    }
+}
+
+impl PartialEq for DepthBias {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits() == other.bits()
+    }
+}
+
+impl Eq for DepthBias {}
+
+impl Hash for DepthBias {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits().hash(state);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    // Which fragments pass, comparing theirs against what's in the depth buffer
+    pub compare_op: CompareOp,
+}
+
+impl DepthState {
+    // For render passes without a depth attachment, or drawing over everything
+    pub const OFF: Self = Self { test: false, write: false, compare_op: CompareOp::ALWAYS };
+    // The depth buffer is cleared to 1.0, so nearer is less
+    pub const READ_WRITE: Self = Self { test: true, write: true, compare_op: CompareOp::LESS };
+    // Transparent surfaces are hidden by opaque ones, but mustn't hide what's behind them
+    pub const READ_ONLY: Self = Self { test: true, write: false, compare_op: CompareOp::LESS };
+}
+
+// Everything a graphics pipeline is made from. It is also what the pipeline compiler keys pipelines on,
+// so asking for the same description twice gives the same pipeline
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GraphicsPipelineBuilder {
+    shaders: PipelineShaders,
+    specialization: PipelineSpecialization,
+    vertex_input: VertexInputDescription,
+    topology: PrimitiveTopology,
+    primitive_restart: bool,
+    polygon_mode: PolygonMode,
+    cull_mode: CullModeFlags,
+    front_face: FrontFace,
+    // The bits of an f32, for the same reason as DepthBias
+    line_width: u32,
+    depth_clamp: bool,
+    depth_bias: Option<DepthBias>,
+    depth: DepthState,
+    // One per colour attachment in the subpass
+    blend: Vec<BlendState>,
+    // Replaces blending on every attachment when set
+    logic_op: Option<LogicOp>,
+    // None takes whatever the render pass has
+    samples: Option<SampleCountFlags>,
+    // The bits of the minimum fraction of samples shaded separately
+    sample_shading: Option<u32>,
+    dynamic_states: Vec<DynamicState>,
+    // Of the render pass it's built for
+    subpass: u32,
+    uniform_ring_set: Option<u32>,
+    bindless_set: Option<u32>,
+    push_constants: Option<PushConstantsLayout>,
+}
+
+impl Default for GraphicsPipelineBuilder {
+    fn default() -> Self {
+        Self::opaque(PipelineShaders::default())
+    }
+}
+
+impl GraphicsPipelineBuilder {
+    pub fn opaque(shaders: PipelineShaders) -> Self {
+        Self {
+            shaders,
+            specialization: PipelineSpecialization::default(),
+            vertex_input: Vertex::input_description(),
+            topology: PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: PolygonMode::FILL,
+            cull_mode: CullModeFlags::BACK,
+            // Models are counter clockwise, and the projection flips Y so that still holds on screen
+            front_face: FrontFace::COUNTER_CLOCKWISE,
+            line_width: 1.0f32.to_bits(),
+            depth_clamp: false,
+            depth_bias: None,
+            depth: DepthState::READ_WRITE,
+            blend: vec![BlendState::OPAQUE],
+            logic_op: None,
+            samples: None,
+            sample_shading: None,
+            // Viewport and scissor get set when recording, so they survive a window resize
+            dynamic_states: vec![DynamicState::VIEWPORT, DynamicState::SCISSOR],
+            subpass: 0,
+            uniform_ring_set: None,
+            bindless_set: None,
+            push_constants: Some(PushConstantsLayout::of::<MeshPushConstants>()),
+        }
+    }
+
+    // The blended presets test depth without writing it, so draw them after everything opaque
+    pub fn alpha_blended(shaders: PipelineShaders) -> Self {
+        Self::opaque(shaders).blend(BlendState::ALPHA_BLENDED).depth(DepthState::READ_ONLY)
+    }
+
+    pub fn additive(shaders: PipelineShaders) -> Self {
+        Self::opaque(shaders).blend(BlendState::ADDITIVE).depth(DepthState::READ_ONLY)
+    }
+
+    pub fn premultiplied(shaders: PipelineShaders) -> Self {
+        Self::opaque(shaders).blend(BlendState::PREMULTIPLIED).depth(DepthState::READ_ONLY)
+    }
+
+    pub fn shaders(mut self, shaders: PipelineShaders) -> Self {
+        self.shaders = shaders;
+        self
+    }
+
+    pub fn specialization(mut self, specialization: PipelineSpecialization) -> Self {
+        self.specialization = specialization;
+        self
+    }
+
+    // Defaults to the layout of mesh::Vertex
+    pub fn vertex_input(mut self, vertex_input: VertexInputDescription) -> Self {
+        self.vertex_input = vertex_input;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    // Only for strips and fans, lists would need VK_EXT_primitive_topology_list_restart
+    pub fn primitive_restart(mut self, enable: bool) -> Self {
+        self.primitive_restart = enable;
+        self
+    }
+
+    // Anything but FILL needs fillModeNonSolid
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    // Anything but 1.0 needs wideLines
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width.to_bits();
+        self
+    }
+
+    // Clamps depth instead of clipping against the near and far planes. Needs depthClamp
+    pub fn depth_clamp(mut self, enable: bool) -> Self {
+        self.depth_clamp = enable;
+        self
+    }
+
+    // A non-zero clamp needs depthBiasClamp
+    pub fn depth_bias(mut self, depth_bias: Option<DepthBias>) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    // The same blending on every colour attachment
+    pub fn blend(mut self, blend: BlendState) -> Self {
+        self.blend.iter_mut().for_each(|attachment| *attachment = blend);
+        self
+    }
+
+    // Attachments past the end are added as opaque. Different blending per attachment needs independentBlend
+    pub fn attachment_blend(mut self, attachment: usize, blend: BlendState) -> Self {
+        if self.blend.len() <= attachment {
+            self.blend.resize(attachment + 1, BlendState::OPAQUE);
+        }
+        self.blend[attachment] = blend;
+        self
+    }
+
+    // Needs logicOp
+    pub fn logic_op(mut self, logic_op: Option<LogicOp>) -> Self {
+        self.logic_op = logic_op;
+        self
+    }
+
+    // Has to match the render pass attachments. Unset, the pipeline follows the render pass it's built for
+    pub fn samples(mut self, samples: Option<SampleCountFlags>) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    // Runs the fragment shader for at least this fraction of the samples instead of once per pixel,
+    // which also smooths aliasing inside triangles. Needs sampleRateShading
+    pub fn sample_shading(mut self, min_fraction: Option<f32>) -> Self {
+        self.sample_shading = min_fraction.map(f32::to_bits);
+        self
+    }
+
+    // Must keep VIEWPORT and SCISSOR, as the pipeline has no viewport of its own
+    pub fn dynamic_states(mut self, dynamic_states: &[DynamicState]) -> Self {
+        self.dynamic_states = dynamic_states.to_vec();
+        self
+    }
+
+    // The subpass the pipeline draws in, the first unless changed
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    // Binds this set to a UniformRing, so the uniform buffer at its binding 0 takes a dynamic offset
+    pub fn uniform_ring(mut self, set: u32) -> Self {
+        self.uniform_ring_set = Some(set);
+        self
+    }
+
+    // Gives this set to BindlessSet. Fails to build on devices without descriptor indexing,
+    // so check LogicalDevice::descriptor_indexing and fall back to bound descriptors there
+    pub fn bindless(mut self, set: u32) -> Self {
+        self.bindless_set = Some(set);
+        self
+    }
+
+    // The struct pushed to these shaders, MeshPushConstants unless changed
+    pub fn push_constants<T: PushConstants>(mut self) -> Self {
+        self.push_constants = Some(PushConstantsLayout::of::<T>());
+        self
+    }
+
+    pub fn no_push_constants(mut self) -> Self {
+        self.push_constants = None;
+        self
+    }
+
+    // For log messages
+    pub fn describe(&self) -> String {
+        format!("{} + {}", self.shaders.vertex.shader, self.shaders.fragment.shader)
+    }
+
+    // Catches state the device can't do, which the driver is free to crash on rather than report.
+    // LogicalDevice enables every optional feature the GPU has, so the feature checks only fail on GPUs without them
+    fn validate(&self, logical_device: &LogicalDevice) -> Result<()> {
+        let features = logical_device.enabled_features();
+        let limits = &logical_device.properties().limits;
+        let line_width = f32::from_bits(self.line_width);
+
+        require(features.fill_mode_non_solid, "fillModeNonSolid", self.polygon_mode != PolygonMode::FILL, || format!("Polygon mode {:?}", self.polygon_mode))?;
+        require(features.depth_clamp, "depthClamp", self.depth_clamp, || "Depth clamp".to_string())?;
+        require(features.wide_lines, "wideLines", line_width != 1.0, || format!("A line width of {line_width}"))?;
+        require(features.depth_bias_clamp, "depthBiasClamp", self.depth_bias.is_some_and(|bias| bias.clamp != 0.0), || "A depth bias clamp".to_string())?;
+        require(features.logic_op, "logicOp", self.logic_op.is_some(), || format!("Logic op {:?}", self.logic_op))?;
+        require(features.independent_blend, "independentBlend", self.blend.windows(2).any(|pair| pair[0] != pair[1]), || "Different blending per attachment".to_string())?;
+        require(features.sample_rate_shading, "sampleRateShading", self.sample_shading.is_some(), || "Sample shading".to_string())?;
+
+        if let Some(min_fraction) = self.sample_shading.map(f32::from_bits) && !(0.0..=1.0).contains(&min_fraction) {
+            return Err(Error::msg(format!("A minimum sample shading of {min_fraction} is outside 0 to 1")));
+        }
+
+        let [min_width, max_width] = limits.line_width_range;
+        if line_width != 1.0 && !self.dynamic_states.contains(&DynamicState::LINE_WIDTH) && !(min_width..=max_width).contains(&line_width) {
+            return Err(Error::msg(format!("A line width of {line_width} is outside the device's range of {min_width} to {max_width}")));
+        }
+
+        // Patches are only drawn through tessellation control and evaluation shaders, and pipelines here only have vertex and fragment
+        if self.topology == PrimitiveTopology::PATCH_LIST {
+            return Err(Error::msg("PATCH_LIST needs tessellation control and evaluation stages, which these pipelines don't have"));
+        }
+
+        let is_list = matches!(self.topology, PrimitiveTopology::POINT_LIST | PrimitiveTopology::LINE_LIST | PrimitiveTopology::TRIANGLE_LIST
+            | PrimitiveTopology::LINE_LIST_WITH_ADJACENCY | PrimitiveTopology::TRIANGLE_LIST_WITH_ADJACENCY);
+        if self.primitive_restart && is_list {
+            return Err(Error::msg(format!("Primitive restart only works with strips and fans, not {:?}", self.topology)));
+        }
+
+        if let Some(samples) = self.samples && !limits.framebuffer_color_sample_counts.contains(samples) {
+            return Err(Error::msg(format!(
+                "The device can't render {:?} colour samples, only {:?}",
+                samples, limits.framebuffer_color_sample_counts
+            )));
+        }
+
+        if let Some(push_constants) = &self.push_constants {
+            push_constants.check(limits.max_push_constants_size)?;
+        }
+
+        for state in [DynamicState::VIEWPORT, DynamicState::SCISSOR] {
+            if !self.dynamic_states.contains(&state) {
+                return Err(Error::msg(format!("{state:?} has to stay dynamic, the pipeline has no viewport of its own")));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build(&self, logical_device: &LogicalDevice, pipeline_cache: &PipelineCache, target: &RenderTarget) -> Result<GraphicsPipeline> {
+        self.validate(logical_device)?;
+
+        // A pipeline can only be used with render passes compatible with the one it was made for
+        if matches!(target, RenderTarget::Dynamic(_)) && logical_device.dynamic_rendering().is_none() {
+            return Err(Error::msg(format!("{} is for dynamic rendering, which this device doesn't support", self.describe())));
+        }
+        let subpass = target.subpass(self.subpass)
+            .ok_or_else(|| Error::msg(format!("{} is for subpass {}, which the render pass doesn't have", self.describe(), self.subpass)))?;
+        if self.blend.len() != subpass.colour_attachments as usize {
+            return Err(Error::msg(format!(
+                "{} has blending for {} colour attachments, but subpass {} has {}",
+                self.describe(), self.blend.len(), self.subpass, subpass.colour_attachments
+            )));
+        }
+        if (self.depth.test || self.depth.write) && subpass.depth_format.is_none() {
+            return Err(Error::msg(format!("{} uses depth, but subpass {} has no depth attachment", self.describe(), self.subpass)));
+        }
+        if let Some(samples) = self.samples && samples != subpass.samples {
+            return Err(Error::msg(format!(
+                "{} renders {:?} samples, but subpass {} has {:?}",
+                self.describe(), samples, self.subpass, subpass.samples
+            )));
+        }
+
+        let shaders = &self.shaders;
+        let specialization = &self.specialization;
+        let vertex_input = &self.vertex_input;
+
+        // Both stages can come from one module, which is then only loaded and reflected once
+        let shared_module = shaders.vertex.shader == shaders.fragment.shader;
+        let vertex_shader = load_shader(&shaders.vertex.shader)?;
+        let fragment_shader = if shared_module { None } else { Some(load_shader(&shaders.fragment.shader)?) };
+        let fragment_shader = fragment_shader.as_ref().unwrap_or(&vertex_shader);
+
+        // Catch shaders that don't fit together, or don't fit the mesh data, before the driver sees them
+        let vertex_reflection = ShaderReflection::reflect(&vertex_shader)?;
+        let fragment_reflection = if shared_module { None } else { Some(ShaderReflection::reflect(fragment_shader)?) };
+        let fragment_reflection = fragment_reflection.as_ref().unwrap_or(&vertex_reflection);
+        let vertex_entry = vertex_reflection.entry_point(&shaders.vertex.entry_point, ShaderStageFlags::VERTEX)?;
+        let fragment_entry = fragment_reflection.entry_point(&shaders.fragment.entry_point, ShaderStageFlags::FRAGMENT)?;
+
+        reflection::check_interface(vertex_entry, fragment_entry)?;
+        check_vertex_input(vertex_entry, vertex_input)?;
+        specialization.vertex.check(&vertex_reflection, vertex_shader.name())?;
+        specialization.fragment.check(fragment_reflection, fragment_shader.name())?;
+
+        let layout = PipelineLayout::from_entry_points(logical_device, &[vertex_entry, fragment_entry], self.uniform_ring_set, self.bindless_set, self.push_constants.as_ref())?;
 
         let vertex_shader_module = ShaderModule::new(logical_device, &vertex_shader)?;
-        let fragment_shader_module = ShaderModule::new(logical_device, &fragment_shader)?;
+        let fragment_shader_module = if shared_module { None } else { Some(ShaderModule::new(logical_device, fragment_shader)?) };
+        let fragment_shader_module = fragment_shader_module.as_ref().unwrap_or(&vertex_shader_module);
+        let vertex_entry_name = CString::new(vertex_entry.name.as_str())?;
+        let fragment_entry_name = CString::new(fragment_entry.name.as_str())?;
+
+        // VkSpecializationInfo
+        let vertex_map_entries = specialization.vertex.map_entries();
+        let fragment_map_entries = specialization.fragment.map_entries();
+        let vertex_specialization_info = specialization_info(&specialization.vertex, &vertex_map_entries);
+        let fragment_specialization_info = specialization_info(&specialization.fragment, &fragment_map_entries);
 
-        let vertex_shader_stage_info = ash::vk::PipelineShaderStageCreateInfo {
+        let vertex_shader_stage_info = vk::PipelineShaderStageCreateInfo {
             stage: ShaderStageFlags::VERTEX,
             module: *vertex_shader_module.raw(),
-            p_name: "main".as_ptr() as *const i8,
+            p_name: vertex_entry_name.as_ptr(),
+            p_specialization_info: vertex_specialization_info.as_ref().map_or(std::ptr::null(), |info| info),
             ..Default::default()
         };
-        let fragment_shader_module_info = ash::vk::PipelineShaderStageCreateInfo {
-            stage: ShaderStageFlags::VERTEX,
+        let fragment_shader_module_info = vk::PipelineShaderStageCreateInfo {
+            stage: ShaderStageFlags::FRAGMENT,
             module: *fragment_shader_module.raw(),
-            p_name: "main".as_ptr() as *const i8,
+            p_name: fragment_entry_name.as_ptr(),
+            p_specialization_info: fragment_specialization_info.as_ref().map_or(std::ptr::null(), |info| info),
             ..Default::default()
         };
 
         let shader_stages = [vertex_shader_stage_info, fragment_shader_module_info];
 
-        let dynamic_states: Vec<ash::vk::DynamicState> = vec![
-            ash::vk::DynamicState::VIEWPORT,
-            ash::vk::DynamicState::SCISSOR
-        ];
+        // VkPipelineDynamicStateCreateInfo
+        let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&self.dynamic_states);
 
-        let dynamic_state_create_info = ash::vk::PipelineDynamicStateCreateInfo {
-            dynamic_state_count: dynamic_states.len() as u32,
-            p_dynamic_states: dynamic_states.as_ptr(),
+        // VkPipelineVertexInputStateCreateInfo
+        let pipeline_vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo {
+            vertex_attribute_description_count: vertex_input.attributes.len() as u32,
+            p_vertex_attribute_descriptions: vertex_input.attributes.as_ptr(),
+            vertex_binding_description_count: vertex_input.bindings.len() as u32,
+            p_vertex_binding_descriptions: vertex_input.bindings.as_ptr(),
             ..Default::default()
         };
 
-        // VkPipelineVertexInputStateCreateInfo 
-        let pipeline_vertex_input_create_info = ash::vk::PipelineVertexInputStateCreateInfo {
-            vertex_attribute_description_count: 0,
-            p_vertex_attribute_descriptions: ::core::ptr::null(),
-            vertex_binding_description_count: 0,
-            p_vertex_binding_descriptions: ::core::ptr::null(),
-            ..Default::default()
-        };
-
-        // VkPipelineInputAssemblyStateCreateInfo 
+        // VkPipelineInputAssemblyStateCreateInfo
         let pipeline_input_assembly_state_create_info = PipelineInputAssemblyStateCreateInfo {
-            topology: PrimitiveTopology::TRIANGLE_LIST,
-            primitive_restart_enable: ash::vk::FALSE,
+            topology: self.topology,
+            primitive_restart_enable: self.primitive_restart.into(),
             ..Default::default()
         };
 
-        let viewport = Viewport {
-            x: 0.0f32,
-            y: 0.0f32,
-            width: swap_chain.extent().width as f32, 
-            height: swap_chain.extent().height as f32,
-            min_depth: 0.0f32,
-            max_depth: 1.0f32
-        };
-
-        let scissor = ash::vk::Rect2D {
-            offset: Offset2D { x: 0, y: 0},
-            extent: *swap_chain.extent()
-        };
-
-        // VkPipelineViewportStateCreateInfo 
+        // VkPipelineViewportStateCreateInfo
+        // Viewport and scissor are dynamic, so only the counts are given here
         let pipeline_viewport_state_create_info = PipelineViewportStateCreateInfo {
             viewport_count: 1,
             scissor_count: 1,
-            // p_scissors: &scissor, // I think this makes the scissor immutable
-            // p_viewports: &viewport, // I think this makes the viewport immutable
             ..Default::default()
         };
 
-        // VkPipelineRasterizationStateCreateInfo 
+        // VkPipelineRasterizationStateCreateInfo
+        let depth_bias = self.depth_bias.unwrap_or(DepthBias { constant_factor: 0.0, clamp: 0.0, slope_factor: 0.0 });
         let pipeline_rasterization_state_create_info = PipelineRasterizationStateCreateInfo {
-            depth_clamp_enable: ash::vk::FALSE, // Maybe useful in shadow maps
-            rasterizer_discard_enable: ash::vk::FALSE,
-            polygon_mode: PolygonMode::FILL,
-            line_width: 1.0f32,
-            cull_mode: CullModeFlags::BACK,
-            front_face: FrontFace::CLOCKWISE,
+            depth_clamp_enable: self.depth_clamp.into(),
+            rasterizer_discard_enable: vk::FALSE,
+            polygon_mode: self.polygon_mode,
+            line_width: f32::from_bits(self.line_width),
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            depth_bias_enable: self.depth_bias.is_some().into(),
+            depth_bias_constant_factor: depth_bias.constant_factor,
+            depth_bias_clamp: depth_bias.clamp,
+            depth_bias_slope_factor: depth_bias.slope_factor,
             ..Default::default()
         };
 
-        // VkPipelineMultisampleStateCreateInfo 
-        // Disabled for now
+        // VkPipelineMultisampleStateCreateInfo
         let pipeline_multisample_state_create_info = PipelineMultisampleStateCreateInfo {
-            sample_shading_enable: ash::vk::FALSE,
+            sample_shading_enable: self.sample_shading.is_some().into(),
+            min_sample_shading: self.sample_shading.map_or(0.0, f32::from_bits),
+            rasterization_samples: subpass.samples,
             ..Default::default()
         };
 
-        // VkPipelineColorBlendAttachmentState 
-        // Disabled for now
-        let pipeline_colour_blend_attachment_state = PipelineColorBlendAttachmentState {
-            blend_enable: ash::vk::FALSE,
+        // VkPipelineDepthStencilStateCreateInfo
+        let pipeline_depth_stencil_state_create_info = PipelineDepthStencilStateCreateInfo {
+            depth_test_enable: self.depth.test.into(),
+            depth_write_enable: self.depth.write.into(),
+            depth_compare_op: self.depth.compare_op,
+            depth_bounds_test_enable: vk::FALSE,
+            stencil_test_enable: vk::FALSE,
             ..Default::default()
         };
 
-        // VkPipelineColorBlendStateCreateInfo 
+        // VkPipelineColorBlendAttachmentState
+        let pipeline_colour_blend_attachment_states: Vec<PipelineColorBlendAttachmentState> = self.blend.iter().map(BlendState::raw).collect();
+
+        // VkPipelineColorBlendStateCreateInfo
         let pipeline_colour_blend_state_create_info = PipelineColorBlendStateCreateInfo {
-            logic_op_enable: ash::vk::FALSE,
-            attachment_count: 1,
-            p_attachments: &pipeline_colour_blend_attachment_state,
+            logic_op_enable: self.logic_op.is_some().into(),
+            logic_op: self.logic_op.unwrap_or(LogicOp::COPY),
+            attachment_count: pipeline_colour_blend_attachment_states.len() as u32,
+            p_attachments: pipeline_colour_blend_attachment_states.as_ptr(),
             ..Default::default()
         };
 
-        // VkPipelineLayout 
-        let pipeline_layout = PipelineLayout::null();
+        // VkPipelineRenderingCreateInfo
+        let (colour_formats, depth_format) = match target {
+            RenderTarget::Dynamic(formats) => (formats.colour.as_slice(), formats.depth.unwrap_or(vk::Format::UNDEFINED)),
+            RenderTarget::RenderPass(_) => (&[][..], vk::Format::UNDEFINED),
+        };
+        let stencil_format = if depth_buffer::has_stencil(depth_format) { depth_format } else { vk::Format::UNDEFINED };
+        let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(colour_formats)
+            .depth_attachment_format(depth_format)
+            .stencil_attachment_format(stencil_format);
 
-        // VkPipelineLayoutCreateInfo 
-        let pipeline_layout_create_info = PipelineLayoutCreateInfo {
+        // VkGraphicsPipelineCreateInfo
+        let mut pipeline_create_info = GraphicsPipelineCreateInfo {
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &pipeline_vertex_input_create_info,
+            p_input_assembly_state: &pipeline_input_assembly_state_create_info,
+            p_viewport_state: &pipeline_viewport_state_create_info,
+            p_rasterization_state: &pipeline_rasterization_state_create_info,
+            p_multisample_state: &pipeline_multisample_state_create_info,
+            p_depth_stencil_state: &pipeline_depth_stencil_state_create_info,
+            p_color_blend_state: &pipeline_colour_blend_state_create_info,
+            p_dynamic_state: &dynamic_state_create_info,
+            layout: *layout.raw(),
+            subpass: self.subpass,
             ..Default::default()
         };
+        // Without a render pass the attachment formats come from the chained rendering info instead
+        match target {
+            RenderTarget::RenderPass(render_pass) => pipeline_create_info.render_pass = *render_pass.raw(),
+            RenderTarget::Dynamic(_) => pipeline_create_info = pipeline_create_info.push_next(&mut pipeline_rendering_create_info),
+        }
+
+        let pipelines = unsafe {
+            logical_device.raw().create_graphics_pipelines(*pipeline_cache.raw(), &[pipeline_create_info], None)
+        };
 
-        let pipeline_layout = unsafe { logical_device.raw().create_pipeline_layout(&pipeline_layout_create_info, None)? };
+        let pipeline = match pipelines {
+            Ok(pipelines) => pipelines[0],
+            Err((_, e)) => return Err(e.into()),
+        };
 
-        Ok(Self {
-            pipeline_layout,
+        Ok(GraphicsPipeline {
+            raw: pipeline,
+            layout,
             device: logical_device.raw().clone()
         })
     }
 }
 
-impl Drop for GraphicsPipeline {
-    fn drop(&mut self) {
-        unsafe {
-            println!("Dropping GraphicsPipeline");
+fn require(enabled: vk::Bool32, feature: &str, needed: bool, what: impl FnOnce() -> String) -> Result<()> {
+    if needed && enabled != vk::TRUE {
+        return Err(Error::msg(format!("{} needs the {feature} device feature, which isn't enabled", what())));
+    }
+
+    Ok(())
+}
+
+// Stages without constants to set get no VkSpecializationInfo at all
+fn specialization_info<'a>(specialization: &'a Specialization, map_entries: &'a [vk::SpecializationMapEntry]) -> Option<vk::SpecializationInfo<'a>> {
+    if specialization.is_empty() {
+        return None;
+    }
+
+    Some(vk::SpecializationInfo::default()
+        .map_entries(map_entries)
+        .data(specialization.data()))
+}
+
+// How a vertex attribute format reaches the shader: normalised and scaled formats arrive as floats.
+// Formats this doesn't know about aren't checked
+fn vertex_format_type(format: vk::Format) -> Option<(ScalarType, u32)> {
+    use vk::Format as F;
+
+    let float = ScalarType::Float { width: 32 };
+    let double = ScalarType::Float { width: 64 };
+    let int = ScalarType::Int { width: 32, signed: true };
+    let uint = ScalarType::Int { width: 32, signed: false };
+
+    Some(match format {
+        F::R32_SFLOAT | F::R16_SFLOAT | F::R16_UNORM | F::R16_SNORM | F::R8_UNORM | F::R8_SNORM => (float, 1),
+        F::R32G32_SFLOAT | F::R16G16_SFLOAT | F::R16G16_UNORM | F::R16G16_SNORM | F::R8G8_UNORM | F::R8G8_SNORM => (float, 2),
+        F::R32G32B32_SFLOAT | F::B10G11R11_UFLOAT_PACK32 => (float, 3),
+        F::R32G32B32A32_SFLOAT | F::R16G16B16A16_SFLOAT | F::R16G16B16A16_UNORM | F::R16G16B16A16_SNORM
+        | F::R8G8B8A8_UNORM | F::R8G8B8A8_SNORM | F::B8G8R8A8_UNORM | F::A2B10G10R10_UNORM_PACK32 => (float, 4),
+        F::R64_SFLOAT => (double, 1),
+        F::R64G64_SFLOAT => (double, 2),
+        F::R64G64B64_SFLOAT => (double, 3),
+        F::R64G64B64A64_SFLOAT => (double, 4),
+        F::R32_SINT | F::R16_SINT | F::R8_SINT => (int, 1),
+        F::R32G32_SINT | F::R16G16_SINT | F::R8G8_SINT => (int, 2),
+        F::R32G32B32_SINT => (int, 3),
+        F::R32G32B32A32_SINT | F::R16G16B16A16_SINT | F::R8G8B8A8_SINT => (int, 4),
+        F::R32_UINT | F::R16_UINT | F::R8_UINT => (uint, 1),
+        F::R32G32_UINT | F::R16G16_UINT | F::R8G8_UINT => (uint, 2),
+        F::R32G32B32_UINT => (uint, 3),
+        F::R32G32B32A32_UINT | F::R16G16B16A16_UINT | F::R8G8B8A8_UINT => (uint, 4),
+        _ => return None,
+    })
+}
+
+// Every vertex shader input needs an attribute feeding it, of the same numeric type and with at least
+// as many components. Vulkan would fill missing ones in with 0 or 1, which is never what a mesh meant
+fn check_vertex_input(entry: &EntryPoint, vertex_input: &VertexInputDescription) -> Result<()> {
+    for input in &entry.inputs {
+        let name = input.name.as_deref().unwrap_or("unnamed");
+        let attribute = vertex_input.attributes.iter().find(|attribute| attribute.location == input.location).ok_or_else(|| Error::msg(format!(
+            "{} reads vertex attribute location {} ({name}), which the mesh data doesn't provide",
+            entry.module, input.location
+        )))?;
+
+        // Matrices and arrays span several locations, only the first is matched up
+        let (scalar, count) = match input.ty {
+            IoType::Scalar(scalar) => (scalar, 1),
+            IoType::Vector(scalar, count) => (scalar, count),
+            _ => continue,
+        };
+        let Some((format_scalar, format_count)) = vertex_format_type(attribute.format) else { continue };
+
+        // 16-bit float inputs still take 32-bit float formats
+        let same_type = match (scalar, format_scalar) {
+            (ScalarType::Float { width: 16 | 32 }, ScalarType::Float { width: 32 }) => true,
+            (scalar, format_scalar) => scalar == format_scalar,
+        };
+        if !same_type {
+            return Err(Error::msg(format!(
+                "{} reads vertex attribute location {} ({name}) as {scalar:?}, but the mesh data is {:?}",
+                entry.module, input.location, attribute.format
+            )));
+        }
+        if format_count < count {
+            return Err(Error::msg(format!(
+                "{} reads {count} components from vertex attribute location {} ({name}), but {:?} only has {format_count}",
+                entry.module, input.location, attribute.format
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::VertexInputAttributeDescription;
+
+    use super::*;
+    use crate::reflection::InterfaceVariable;
+
+    fn vertex_entry(inputs: Vec<InterfaceVariable>) -> EntryPoint {
+        EntryPoint {
+            name: "main".to_string(),
+            stage: ShaderStageFlags::VERTEX,
+            module: "test.vert".to_string(),
+            inputs,
+            outputs: vec![],
+            descriptor_bindings: vec![],
+            push_constants: None,
+        }
+    }
+
+    fn input(location: u32, ty: IoType) -> InterfaceVariable {
+        InterfaceVariable { location, name: Some(format!("in{location}")), ty }
+    }
 
-            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+    fn attributes(formats: &[vk::Format]) -> VertexInputDescription {
+        VertexInputDescription {
+            bindings: vec![],
+            attributes: formats.iter().enumerate().map(|(location, &format)| VertexInputAttributeDescription {
+                location: location as u32,
+                format,
+                ..Default::default()
+            }).collect(),
         }
     }
-}
\ No newline at end of file
+
+    const FLOAT: ScalarType = ScalarType::Float { width: 32 };
+    const UINT: ScalarType = ScalarType::Int { width: 32, signed: false };
+
+    #[test]
+    fn accepts_matching_attributes() {
+        let entry = vertex_entry(vec![
+            input(0, IoType::Vector(FLOAT, 3)),
+            input(1, IoType::Vector(FLOAT, 4)),
+            input(2, IoType::Vector(UINT, 4)),
+            // More components than the shader reads are fine
+            input(3, IoType::Vector(FLOAT, 2)),
+        ]);
+        let vertex_input = attributes(&[
+            vk::Format::R32G32B32_SFLOAT,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R16G16B16A16_UINT,
+            vk::Format::R32G32B32A32_SFLOAT,
+        ]);
+
+        check_vertex_input(&entry, &vertex_input).unwrap();
+    }
+
+    #[test]
+    fn rejects_missing_attributes() {
+        let entry = vertex_entry(vec![input(1, IoType::Vector(FLOAT, 2))]);
+
+        let error = check_vertex_input(&entry, &attributes(&[vk::Format::R32G32_SFLOAT])).unwrap_err();
+        assert!(error.to_string().contains("location 1"), "{error}");
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        let entry = vertex_entry(vec![input(0, IoType::Vector(UINT, 4))]);
+
+        let error = check_vertex_input(&entry, &attributes(&[vk::Format::R8G8B8A8_UNORM])).unwrap_err();
+        assert!(error.to_string().contains("R8G8B8A8_UNORM"), "{error}");
+    }
+
+    #[test]
+    fn rejects_missing_components() {
+        let entry = vertex_entry(vec![input(0, IoType::Vector(FLOAT, 3))]);
+
+        let error = check_vertex_input(&entry, &attributes(&[vk::Format::R32G32_SFLOAT])).unwrap_err();
+        assert!(error.to_string().contains("reads 3 components"), "{error}");
+    }
+}
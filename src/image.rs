@@ -0,0 +1,318 @@
+use ash::vk::{self, AccessFlags, DeviceMemory, Extent3D, Format, ImageAspectFlags, ImageCreateFlags, ImageLayout, ImageSubresourceRange, ImageTiling, ImageType, ImageUsageFlags, MemoryPropertyFlags, PipelineStageFlags, SampleCountFlags, SharingMode};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, buffer::Buffer, depth_buffer, command_pool::CommandPool, mipmaps::{self, MipStrategy}, texture_container::TextureContainer};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDescription {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub usage: ImageUsageFlags,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: SampleCountFlags,
+    pub flags: ImageCreateFlags,
+}
+
+impl ImageDescription {
+    pub fn new_2d(width: u32, height: u32, format: Format, usage: ImageUsageFlags) -> Self {
+        Self {
+            width,
+            height,
+            format,
+            usage,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: SampleCountFlags::TYPE_1,
+            flags: ImageCreateFlags::empty(),
+        }
+    }
+}
+
+// A VkImage with its own memory, as opposed to the swap chain images which are owned by the swap chain
+pub struct Image {
+    raw: vk::Image,
+    memory: DeviceMemory,
+    format: Format,
+    extent: Extent3D,
+    mip_levels: u32,
+    array_layers: u32,
+    samples: SampleCountFlags,
+    device: ash::Device
+}
+
+impl Image {
+    pub fn new(logical_device: &LogicalDevice, description: &ImageDescription) -> Result<Self> {
+        let device = logical_device.raw();
+        let extent = Extent3D { width: description.width, height: description.height, depth: 1 };
+
+        // VkImageCreateInfo
+        let create_info = vk::ImageCreateInfo {
+            flags: description.flags,
+            image_type: ImageType::TYPE_2D,
+            format: description.format,
+            extent,
+            mip_levels: description.mip_levels,
+            array_layers: description.array_layers,
+            samples: description.samples,
+            // Optimal tiling lets the driver lay texels out however is fastest to sample
+            tiling: ImageTiling::OPTIMAL,
+            usage: description.usage,
+            sharing_mode: SharingMode::EXCLUSIVE,
+            initial_layout: ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+
+        let image = unsafe { device.create_image(&create_info, None)? };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        // Transient attachments never leave tile memory on tilers, which can then skip backing them at all
+        let lazy_memory_type = description.usage.contains(ImageUsageFlags::TRANSIENT_ATTACHMENT)
+            .then(|| logical_device.find_memory_type(requirements.memory_type_bits, MemoryPropertyFlags::DEVICE_LOCAL | MemoryPropertyFlags::LAZILY_ALLOCATED).ok())
+            .flatten();
+        let memory_type_index = match lazy_memory_type {
+            Some(index) => index,
+            None => logical_device.find_memory_type(requirements.memory_type_bits, MemoryPropertyFlags::DEVICE_LOCAL)?,
+        };
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index,
+            ..Default::default()
+        };
+
+        let memory = unsafe { device.allocate_memory(&allocate_info, None)? };
+        unsafe { device.bind_image_memory(image, memory, 0)? };
+
+        Ok(Self {
+            raw: image,
+            memory,
+            format: description.format,
+            extent,
+            mip_levels: description.mip_levels,
+            array_layers: description.array_layers,
+            samples: description.samples,
+            device: device.clone()
+        })
+    }
+
+    // Creates a sampled image and copies the tightly packed texels into the first mip level through a staging buffer.
+    // Any further mip levels are generated on the GPU
+    pub fn from_pixels(logical_device: &LogicalDevice, command_pool: &CommandPool, width: u32, height: u32, format: Format, pixels: &[u8], mip_levels: u32) -> Result<Self> {
+        if pixels.is_empty() {
+            return Err(Error::msg("Cannot upload an empty image"));
+        }
+
+        let staging = Buffer::new(
+            logical_device,
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT
+        )?;
+        staging.write(pixels)?;
+
+        let mut description = ImageDescription {
+            mip_levels,
+            ..ImageDescription::new_2d(width, height, format, ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED)
+        };
+
+        let strategy = if mip_levels > 1 { Some(mipmaps::choose_strategy(logical_device, format)?) } else { None };
+        match strategy {
+            Some(MipStrategy::Blit) => description.usage |= ImageUsageFlags::TRANSFER_SRC,
+            // The compute shader writes through views of a storage compatible format
+            Some(MipStrategy::Compute) => {
+                description.usage |= ImageUsageFlags::STORAGE;
+                description.flags |= ImageCreateFlags::MUTABLE_FORMAT;
+                // sRGB can't be a storage image, only the image's UNORM views can
+                if mipmaps::storage_format(format) != Some(format) {
+                    description.flags |= ImageCreateFlags::EXTENDED_USAGE;
+                }
+            },
+            None => {},
+        }
+
+        let image = Self::new(logical_device, &description)?;
+        // Only needed for the compute path, and has to outlive the submission
+        let downsampler = match strategy {
+            Some(MipStrategy::Compute) => Some(mipmaps::ComputeDownsampler::new(logical_device, &image)?),
+            _ => None,
+        };
+
+        command_pool.submit_single_time(logical_device, |device, command_buffer| {
+            image.transition_layout(device, command_buffer, ImageLayout::UNDEFINED, ImageLayout::TRANSFER_DST_OPTIMAL);
+
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                // Zero means tightly packed
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: image.extent
+            };
+
+            unsafe { device.cmd_copy_buffer_to_image(command_buffer, *staging.raw(), image.raw, ImageLayout::TRANSFER_DST_OPTIMAL, &[region]) };
+
+            match &downsampler {
+                Some(downsampler) => downsampler.record(device, command_buffer, &image),
+                None if mip_levels > 1 => mipmaps::record_blits(device, command_buffer, &image),
+                None => image.transition_layout(device, command_buffer, ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            }
+        })?;
+
+        Ok(image)
+    }
+
+    // Uploads every level and layer of a pre-built container as is, no mips are generated
+    pub fn from_container(logical_device: &LogicalDevice, command_pool: &CommandPool, container: &TextureContainer) -> Result<Self> {
+        // Copies need offsets aligned to the texel block, which DDS files don't guarantee, so repack
+        const ALIGNMENT: usize = 16;
+        let mut packed = vec![];
+        let mut regions = vec![];
+
+        for subresource in &container.subresources {
+            packed.resize(packed.len().next_multiple_of(ALIGNMENT), 0);
+
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: packed.len() as vk::DeviceSize,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: ImageAspectFlags::COLOR,
+                    mip_level: subresource.level,
+                    base_array_layer: subresource.layer,
+                    layer_count: 1
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: Extent3D {
+                    width: (container.width >> subresource.level).max(1),
+                    height: (container.height >> subresource.level).max(1),
+                    depth: 1
+                }
+            });
+
+            packed.extend_from_slice(&container.data[subresource.offset..subresource.offset + subresource.size]);
+        }
+
+        if packed.is_empty() {
+            return Err(Error::msg("Cannot upload an empty image"));
+        }
+
+        let staging = Buffer::new(
+            logical_device,
+            packed.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT
+        )?;
+        staging.write(&packed)?;
+
+        let description = ImageDescription {
+            mip_levels: container.mip_levels,
+            array_layers: container.image_layers(),
+            flags: if container.cube { ImageCreateFlags::CUBE_COMPATIBLE } else { ImageCreateFlags::empty() },
+            ..ImageDescription::new_2d(container.width, container.height, container.format, ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::SAMPLED)
+        };
+        let image = Self::new(logical_device, &description)?;
+
+        command_pool.submit_single_time(logical_device, |device, command_buffer| {
+            image.transition_layout(device, command_buffer, ImageLayout::UNDEFINED, ImageLayout::TRANSFER_DST_OPTIMAL);
+            unsafe { device.cmd_copy_buffer_to_image(command_buffer, *staging.raw(), image.raw, ImageLayout::TRANSFER_DST_OPTIMAL, &regions) };
+            image.transition_layout(device, command_buffer, ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        })?;
+
+        Ok(image)
+    }
+
+    // Moves every mip level and layer at once. Only knows about the transitions needed for uploading textures
+    pub fn transition_layout(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, old_layout: ImageLayout, new_layout: ImageLayout) {
+        let (src_access_mask, dst_access_mask, src_stage, dst_stage) = match (old_layout, new_layout) {
+            (ImageLayout::UNDEFINED, ImageLayout::TRANSFER_DST_OPTIMAL) => (
+                AccessFlags::empty(),
+                AccessFlags::TRANSFER_WRITE,
+                PipelineStageFlags::TOP_OF_PIPE,
+                PipelineStageFlags::TRANSFER
+            ),
+            (ImageLayout::TRANSFER_DST_OPTIMAL, ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+                AccessFlags::TRANSFER_WRITE,
+                AccessFlags::SHADER_READ,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::FRAGMENT_SHADER
+            ),
+            _ => panic!("Unsupported layout transition {:?} -> {:?}", old_layout, new_layout),
+        };
+
+        let barrier = self.barrier(self.full_range(), old_layout, new_layout, src_access_mask, dst_access_mask);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+    }
+
+    // VkImageMemoryBarrier
+    pub fn barrier(&self, subresource_range: ImageSubresourceRange, old_layout: ImageLayout, new_layout: ImageLayout, src_access_mask: AccessFlags, dst_access_mask: AccessFlags) -> vk::ImageMemoryBarrier<'static> {
+        vk::ImageMemoryBarrier {
+            old_layout,
+            new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: self.raw,
+            subresource_range,
+            src_access_mask,
+            dst_access_mask,
+            ..Default::default()
+        }
+    }
+
+    pub fn full_range(&self) -> ImageSubresourceRange {
+        ImageSubresourceRange {
+            aspect_mask: depth_buffer::aspect_flags(self.format),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers
+        }
+    }
+
+    pub fn raw(&self) -> &vk::Image {
+        &self.raw
+    }
+
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    pub fn extent(&self) -> &Extent3D {
+        &self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.samples
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image(self.raw, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
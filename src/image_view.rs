@@ -1,7 +1,7 @@
-use ash::vk::{ComponentMapping, ComponentSwizzle, Format, Image, ImageAspectFlags, ImageSubresourceRange, ImageViewType};
+use ash::vk::{ComponentMapping, ComponentSwizzle, Format, Image, ImageSubresourceRange, ImageUsageFlags, ImageViewType};
 use anyhow::Result;
 
-use crate::LogicalDevice;
+use crate::{LogicalDevice, depth_buffer};
 
 pub struct ImageView {
     raw: ash::vk::ImageView,
@@ -9,11 +9,39 @@ pub struct ImageView {
 }
 
 impl ImageView {
+    // A view of the first mip level and layer, which is all a swap chain or depth image has.
+    // Depth formats get the depth (and stencil) aspect, everything else colour
     pub fn new(device: &LogicalDevice, image: &Image, image_format: &Format) -> Result<Self> {
+        // Can do stereographic stuff here, i.e. different layers for different eyes
+        let subresource_range = ImageSubresourceRange {
+            aspect_mask: depth_buffer::aspect_flags(*image_format),
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1
+        };
+
+        Self::with_range(device, image, image_format, ImageViewType::TYPE_2D, subresource_range)
+    }
+
+    // A view of any set of mip levels and layers, e.g. the full mip chain of a texture
+    pub fn with_range(device: &LogicalDevice, image: &Image, image_format: &Format, view_type: ImageViewType, subresource_range: ImageSubresourceRange) -> Result<Self> {
+        Self::create(device, image, image_format, view_type, subresource_range, None)
+    }
+
+    // Limited to some of the image's usage. An image created with EXTENDED_USAGE can have usage its own format
+    // doesn't support, e.g. storage on sRGB, as long as each view only asks for what its format can do
+    pub fn with_usage(device: &LogicalDevice, image: &Image, image_format: &Format, view_type: ImageViewType, subresource_range: ImageSubresourceRange, usage: ImageUsageFlags) -> Result<Self> {
+        Self::create(device, image, image_format, view_type, subresource_range, Some(usage))
+    }
 
-        let create_info = ash::vk::ImageViewCreateInfo {
+    fn create(device: &LogicalDevice, image: &Image, image_format: &Format, view_type: ImageViewType, subresource_range: ImageSubresourceRange, usage: Option<ImageUsageFlags>) -> Result<Self> {
+        // VkImageViewUsageCreateInfo
+        let mut usage_create_info = ash::vk::ImageViewUsageCreateInfo::default().usage(usage.unwrap_or_default());
+
+        let mut create_info = ash::vk::ImageViewCreateInfo {
             image: *image,
-            view_type: ImageViewType::TYPE_2D,
+            view_type,
             format: *image_format,
             // Can map the channels for i.e. monochrome textures
             components: ComponentMapping {
@@ -22,16 +50,12 @@ impl ImageView {
                 b: ComponentSwizzle::IDENTITY,
                 a: ComponentSwizzle::IDENTITY,
             },
-            // Can do stereographic stuff here, i.e. different layers for different eyes
-            subresource_range: ImageSubresourceRange {
-                aspect_mask: ImageAspectFlags::COLOR,
-                base_mip_level: 0,
-                level_count: 1,
-                base_array_layer: 0,
-                layer_count: 1
-            },
+            subresource_range,
             ..Default::default()
         };
+        if usage.is_some() {
+            create_info = create_info.push_next(&mut usage_create_info);
+        }
 
         let image_view = unsafe { device.raw().create_image_view(&create_info, None)? };
 
@@ -40,6 +64,10 @@ impl ImageView {
             device: device.raw().clone()
         })
     }
+
+    pub fn raw(&self) -> &ash::vk::ImageView {
+        &self.raw
+    }
 }
 
 impl Drop for ImageView {
@@ -48,4 +76,4 @@ impl Drop for ImageView {
             self.device.destroy_image_view(self.raw, None);
         }
     }
-}
\ No newline at end of file
+}
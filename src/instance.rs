@@ -47,8 +47,8 @@ impl Instance {
             debug_ci_opt = Some(debug_messenger_create_info);
         }
 
-        if exts.is_some() {
-            enabled_exts.extend(exts.unwrap());
+        if let Some(exts) = exts {
+            enabled_exts.extend(exts);
         }
 
         let mut create_info = vk::InstanceCreateInfo {
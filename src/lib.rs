@@ -5,10 +5,46 @@ mod logical_device;
 mod surface;
 mod utils;
 mod swap_chain;
-mod image_view;
+pub mod image_view;
 mod shader_module;
-mod graphics_pipeline;
-mod render_pass;
+pub mod spirv;
+pub mod shaders;
+mod shader_watcher;
+#[cfg(feature = "naga")]
+pub mod shader_preprocessor;
+#[cfg(feature = "naga")]
+pub mod shader_compiler;
+#[cfg(feature = "naga")]
+pub mod shader_permutations;
+pub mod reflection;
+pub mod specialization;
+mod pipeline_cache;
+pub mod pipeline_compiler;
+mod pipeline_layout;
+pub mod graphics_pipeline;
+pub mod descriptors;
+pub mod uniform_ring;
+pub mod push_constants;
+pub mod bindless;
+pub mod pipeline_description;
+pub mod render_pass;
+pub mod depth_buffer;
+pub mod multisample;
+pub mod dynamic_rendering;
+mod buffer;
+mod command_pool;
+mod framebuffer;
+mod sync;
+pub mod mesh;
+pub mod obj_loader;
+mod image;
+mod mipmaps;
+pub mod camera;
+pub mod gltf_loader;
+pub mod scene;
+pub mod sampler;
+pub mod texture;
+pub mod texture_container;
 
 pub use engine::VulkanEngine;
 pub use instance::Instance;
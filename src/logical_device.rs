@@ -1,6 +1,8 @@
+use std::sync::OnceLock;
+
 use ash::vk::{self, PhysicalDevice, QueueFlags};
-use crate::{Surface, instance::Instance, utils::VkStringArray};
-use anyhow::Result;
+use crate::{Surface, dynamic_rendering::DynamicRendering, instance::Instance, mipmaps::DownsamplePipeline, utils::{VkStringArray, vk_str_to_string}};
+use anyhow::{Error, Result};
 
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
@@ -15,6 +17,16 @@ impl QueueFamilyIndices {
 
 pub struct LogicalDevice {
     raw: ash::Device,
+    instance: ash::Instance,
+    physical_device: PhysicalDevice,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    properties: vk::PhysicalDeviceProperties,
+    enabled_features: vk::PhysicalDeviceFeatures,
+    descriptor_indexing: Option<vk::PhysicalDeviceDescriptorIndexingProperties<'static>>,
+    dynamic_rendering: Option<DynamicRendering>,
+    // Built on first use, by textures that can't have their mips blitted
+    downsample_pipeline: OnceLock<DownsamplePipeline>,
+    queue_family_indices: QueueFamilyIndices,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
 }
@@ -35,20 +47,68 @@ impl LogicalDevice {
             ..Default::default()
         }).collect();
 
+        // Optional features get turned on whenever the GPU has them, on purpose: pipelines are described long after
+        // the device exists, and asking for a feature then would mean recreating the device and everything on it.
+        // GraphicsPipelineBuilder::validate still checks enabled_features, for GPUs that lack one
+        let supported_features = unsafe { instance.raw().get_physical_device_features(*physical_device) };
         let device_features = vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: supported_features.sampler_anisotropy,
+            texture_compression_bc: supported_features.texture_compression_bc,
+            image_cube_array: supported_features.image_cube_array,
+            // Checked by GraphicsPipelineBuilder when a pipeline asks for them
+            fill_mode_non_solid: supported_features.fill_mode_non_solid,
+            depth_clamp: supported_features.depth_clamp,
+            depth_bias_clamp: supported_features.depth_bias_clamp,
+            wide_lines: supported_features.wide_lines,
+            logic_op: supported_features.logic_op,
+            independent_blend: supported_features.independent_blend,
+            sample_rate_shading: supported_features.sample_rate_shading,
             ..Default::default()
         };
 
-        let prepared_required_props_names = VkStringArray::new(required_props_names);
+        // Bindless descriptors are turned off rather than failing on devices without descriptor indexing
+        let indexing_support = descriptor_indexing_support(instance, physical_device)?;
+        let mut extension_names = required_props_names.to_vec();
+        let mut indexing_features = match indexing_support {
+            Some((features, extensions)) => {
+                extension_names.extend(extensions);
+                Some(features)
+            },
+            None => {
+                println!("Descriptor indexing isn't supported, bindless descriptors are disabled");
+                None
+            },
+        };
+
+        // Render passes are used instead where dynamic rendering isn't available
+        let rendering_support = dynamic_rendering_support(instance, physical_device)?;
+        let mut rendering_features = match &rendering_support {
+            Some(extensions) => {
+                extension_names.extend(extensions.iter().cloned());
+                Some(vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true))
+            },
+            None => {
+                println!("Dynamic rendering isn't supported, drawing with render passes");
+                None
+            },
+        };
+
+        let prepared_required_props_names = VkStringArray::new(&extension_names);
         
-        let device_create_info = vk::DeviceCreateInfo {
+        let mut device_create_info = vk::DeviceCreateInfo {
             p_queue_create_infos: queue_create_infos.as_ptr(),
             queue_create_info_count: queue_create_infos.len() as u32,
             p_enabled_features: &device_features,
             pp_enabled_extension_names: prepared_required_props_names.as_ptrs(),
-            enabled_extension_count: required_props_names.len() as u32,
+            enabled_extension_count: extension_names.len() as u32,
             ..Default::default()
         };
+        if let Some(indexing_features) = &mut indexing_features {
+            device_create_info = device_create_info.push_next(indexing_features);
+        }
+        if let Some(rendering_features) = &mut rendering_features {
+            device_create_info = device_create_info.push_next(rendering_features);
+        }
         // Can set validation layers here
 
         let device = unsafe { instance.raw().create_device(*physical_device, &device_create_info, None)? };
@@ -56,8 +116,32 @@ impl LogicalDevice {
         let graphics_queue = unsafe { device.get_device_queue(family_indicies.graphics_family.unwrap(), 0) };
         let present_queue = unsafe { device.get_device_queue(family_indicies.present_family.unwrap(), 0) };
 
+        let memory_properties = unsafe { instance.raw().get_physical_device_memory_properties(*physical_device) };
+        let properties = unsafe { instance.raw().get_physical_device_properties(*physical_device) };
+        let descriptor_indexing = indexing_features.map(|_| {
+            let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut indexing_properties);
+            unsafe { instance.raw().get_physical_device_properties2(*physical_device, &mut properties2) };
+
+            vk::PhysicalDeviceDescriptorIndexingProperties {
+                p_next: std::ptr::null_mut(),
+                ..indexing_properties
+            }
+        });
+
+        let dynamic_rendering = rendering_support.map(|extensions| DynamicRendering::new(instance.raw(), &device, !extensions.is_empty()));
+
         Ok(Self {
             raw: device,
+            instance: instance.raw().clone(),
+            physical_device: *physical_device,
+            memory_properties,
+            properties,
+            enabled_features: device_features,
+            descriptor_indexing,
+            dynamic_rendering,
+            downsample_pipeline: OnceLock::new(),
+            queue_family_indices: family_indicies,
             graphics_queue,
             present_queue
         })
@@ -66,6 +150,70 @@ impl LogicalDevice {
     pub fn raw(&self) -> &ash::Device {
         &self.raw
     }
+
+    pub fn physical_device(&self) -> &PhysicalDevice {
+        &self.physical_device
+    }
+
+    // Limits such as alignments and maximum sizes live in here
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    // What was actually enabled at creation, which can be less than the GPU supports
+    pub fn enabled_features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    // The update-after-bind limits, or None when bindless descriptors are disabled
+    pub fn descriptor_indexing(&self) -> Option<&vk::PhysicalDeviceDescriptorIndexingProperties<'static>> {
+        self.descriptor_indexing.as_ref()
+    }
+
+    // None when the device has no dynamic rendering, so pipelines have to be built for render passes
+    pub fn dynamic_rendering(&self) -> Option<&DynamicRendering> {
+        self.dynamic_rendering.as_ref()
+    }
+
+    pub fn downsample_pipeline(&self) -> Result<&DownsamplePipeline> {
+        if let Some(pipeline) = self.downsample_pipeline.get() {
+            return Ok(pipeline);
+        }
+
+        // Another thread may get there first, in which case its pipeline is kept and this one dropped
+        let _ = self.downsample_pipeline.set(DownsamplePipeline::new(self)?);
+        Ok(self.downsample_pipeline.get().unwrap())
+    }
+
+    // What the GPU can do with a format for each tiling mode
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        unsafe { self.instance.get_physical_device_format_properties(self.physical_device, format) }
+    }
+
+    pub fn queue_family_indices(&self) -> &QueueFamilyIndices {
+        &self.queue_family_indices
+    }
+
+    pub fn graphics_queue(&self) -> vk::Queue {
+        self.graphics_queue
+    }
+
+    pub fn present_queue(&self) -> vk::Queue {
+        self.present_queue
+    }
+
+    // Memory types differ per GPU, so ask for one that matches both the resource and what we need
+    pub fn find_memory_type(&self, type_filter: u32, properties: vk::MemoryPropertyFlags) -> Result<u32> {
+        for ix in 0..self.memory_properties.memory_type_count {
+            let memory_type = self.memory_properties.memory_types[ix as usize];
+
+            if type_filter & (1 << ix) != 0 && memory_type.property_flags.contains(properties) {
+                return Ok(ix);
+            }
+        }
+
+        Err(Error::msg(format!("Failed to find a memory type supporting {:?}", properties)))
+    }
 }
 
 impl Drop for LogicalDevice {
@@ -73,11 +221,90 @@ impl Drop for LogicalDevice {
         println!("Dropping LogicalDevice");
         unsafe {
             self.raw.device_wait_idle().ok();
+            // Objects on the device have to go before it does
+            self.downsample_pipeline.take();
             self.raw.destroy_device(None);
         }
     }
 }
 
+// The descriptor indexing features bindless descriptors need, and the extensions that provide them
+// on devices older than Vulkan 1.2, where it became core. None if any of them is missing
+fn descriptor_indexing_support(instance: &Instance, physical_device: &PhysicalDevice) -> Result<Option<(vk::PhysicalDeviceDescriptorIndexingFeatures<'static>, Vec<String>)>> {
+    let api_version = unsafe { instance.raw().get_physical_device_properties(*physical_device) }.api_version;
+
+    let extensions = if vk::api_version_major(api_version) > 1 || vk::api_version_minor(api_version) >= 2 {
+        vec![]
+    } else {
+        let available: Vec<String> = unsafe { instance.raw().enumerate_device_extension_properties(*physical_device)? }
+            .iter().map(|prop| vk_str_to_string(&prop.extension_name)).collect();
+        let needed = ["VK_EXT_descriptor_indexing", "VK_KHR_maintenance3"];
+        if !needed.iter().all(|name| available.iter().any(|a| a == name)) {
+            return Ok(None);
+        }
+        needed.iter().map(|name| name.to_string()).collect()
+    };
+
+    let mut supported = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    {
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut supported);
+        unsafe { instance.raw().get_physical_device_features2(*physical_device, &mut features2) };
+    }
+
+    let required = [
+        supported.runtime_descriptor_array,
+        supported.descriptor_binding_partially_bound,
+        supported.descriptor_binding_variable_descriptor_count,
+        supported.descriptor_binding_sampled_image_update_after_bind,
+        supported.descriptor_binding_storage_buffer_update_after_bind,
+    ];
+    if required.contains(&vk::FALSE) {
+        return Ok(None);
+    }
+
+    let enabled = vk::PhysicalDeviceDescriptorIndexingFeatures {
+        runtime_descriptor_array: vk::TRUE,
+        descriptor_binding_partially_bound: vk::TRUE,
+        descriptor_binding_variable_descriptor_count: vk::TRUE,
+        descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
+        descriptor_binding_storage_buffer_update_after_bind: vk::TRUE,
+        // Only needed when an index varies within a draw, so it's fine to go without
+        shader_sampled_image_array_non_uniform_indexing: supported.shader_sampled_image_array_non_uniform_indexing,
+        shader_storage_buffer_array_non_uniform_indexing: supported.shader_storage_buffer_array_non_uniform_indexing,
+        ..Default::default()
+    };
+
+    Ok(Some((enabled, extensions)))
+}
+
+// The extensions that provide dynamic rendering on devices older than Vulkan 1.3, where it became core,
+// or None if it's missing. Only Vulkan 1.2 devices get the extension, as it needs some of what 1.2 made core
+fn dynamic_rendering_support(instance: &Instance, physical_device: &PhysicalDevice) -> Result<Option<Vec<String>>> {
+    let api_version = unsafe { instance.raw().get_physical_device_properties(*physical_device) }.api_version;
+    let (major, minor) = (vk::api_version_major(api_version), vk::api_version_minor(api_version));
+
+    let extensions = if major > 1 || minor >= 3 {
+        vec![]
+    } else if minor == 2 {
+        let name = ash::khr::dynamic_rendering::NAME.to_str()?.to_string();
+        let available = unsafe { instance.raw().enumerate_device_extension_properties(*physical_device)? };
+        if !available.iter().any(|prop| vk_str_to_string(&prop.extension_name) == name) {
+            return Ok(None);
+        }
+        vec![name]
+    } else {
+        return Ok(None);
+    };
+
+    let mut supported = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+    {
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut supported);
+        unsafe { instance.raw().get_physical_device_features2(*physical_device, &mut features2) };
+    }
+
+    Ok((supported.dynamic_rendering == vk::TRUE).then_some(extensions))
+}
+
 pub fn find_queue_families(instance: &Instance, physical_device: &PhysicalDevice, surface: &Surface) -> Result<QueueFamilyIndices> {
     let props = unsafe { instance.raw().get_physical_device_queue_family_properties(*physical_device) };
 
@@ -0,0 +1,220 @@
+use ash::vk::{self, BufferUsageFlags, Format, IndexType, VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate};
+use anyhow::{Error, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+use crate::{LogicalDevice, buffer::Buffer, command_pool::CommandPool};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    // Bitwise key so identical vertices can be merged through a HashMap
+    pub fn key(&self) -> [u32; 8] {
+        bytemuck::cast(*self)
+    }
+
+    pub fn input_description() -> VertexInputDescription {
+        VertexInputDescription {
+            bindings: vec![VertexInputBindingDescription {
+                binding: 0,
+                stride: size_of::<Vertex>() as u32,
+                input_rate: VertexInputRate::VERTEX,
+            }],
+            attributes: vec![
+                VertexInputAttributeDescription {
+                    location: 0,
+                    binding: 0,
+                    format: Format::R32G32B32_SFLOAT,
+                    offset: std::mem::offset_of!(Vertex, position) as u32,
+                },
+                VertexInputAttributeDescription {
+                    location: 1,
+                    binding: 0,
+                    format: Format::R32G32B32_SFLOAT,
+                    offset: std::mem::offset_of!(Vertex, normal) as u32,
+                },
+                VertexInputAttributeDescription {
+                    location: 2,
+                    binding: 0,
+                    format: Format::R32G32_SFLOAT,
+                    offset: std::mem::offset_of!(Vertex, uv) as u32,
+                },
+            ],
+        }
+    }
+}
+
+// What the pipeline needs to know about the layout of the vertex buffers it will be fed
+#[derive(Clone, Debug, Default)]
+pub struct VertexInputDescription {
+    pub bindings: Vec<VertexInputBindingDescription>,
+    pub attributes: Vec<VertexInputAttributeDescription>,
+}
+
+// ash's descriptions aren't Eq or Hash, but pipelines are keyed on their vertex layout
+impl VertexInputDescription {
+    fn binding_keys(&self) -> impl Iterator<Item = (u32, u32, VertexInputRate)> + '_ {
+        self.bindings.iter().map(|b| (b.binding, b.stride, b.input_rate))
+    }
+
+    fn attribute_keys(&self) -> impl Iterator<Item = (u32, u32, Format, u32)> + '_ {
+        self.attributes.iter().map(|a| (a.location, a.binding, a.format, a.offset))
+    }
+}
+
+impl PartialEq for VertexInputDescription {
+    fn eq(&self, other: &Self) -> bool {
+        self.binding_keys().eq(other.binding_keys()) && self.attribute_keys().eq(other.attribute_keys())
+    }
+}
+
+impl Eq for VertexInputDescription {}
+
+impl std::hash::Hash for VertexInputDescription {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bindings.len().hash(state);
+        self.binding_keys().for_each(|key| key.hash(state));
+        self.attribute_keys().for_each(|key| key.hash(state));
+    }
+}
+
+// CPU side geometry, ready to be uploaded
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    // Counter clockwise and facing +Z, like the models we import
+    pub fn triangle() -> Self {
+        let normal = [0.0, 0.0, 1.0];
+
+        Self {
+            vertices: vec![
+                Vertex { position: [0.0, 0.5, 0.0], normal, uv: [0.5, 0.0] },
+                Vertex { position: [-0.5, -0.5, 0.0], normal, uv: [0.0, 1.0] },
+                Vertex { position: [0.5, -0.5, 0.0], normal, uv: [1.0, 1.0] },
+            ],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    // Gives every triangle its own vertices so each can carry the face normal
+    pub fn generate_flat_normals(&mut self) {
+        let mut vertices = Vec::with_capacity(self.indices.len());
+
+        for triangle in self.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize]);
+            let normal = face_normal(corners.map(|c| c.position));
+
+            vertices.extend(corners.map(|c| Vertex { normal, ..c }));
+        }
+
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        let first = Vec3::from(self.vertices.first()?.position);
+
+        Some(self.vertices.iter().fold(Bounds { min: first, max: first }, |bounds, v| {
+            bounds.include(Vec3::from(v.position))
+        }))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    pub fn include(self, point: Vec3) -> Self {
+        Self { min: self.min.min(point), max: self.max.max(point) }
+    }
+
+    pub fn union(self, other: Bounds) -> Self {
+        self.include(other.min).include(other.max)
+    }
+
+    pub fn transformed(self, transform: &Mat4) -> Self {
+        let corners = [
+            Vec3::new(self.min.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.min.y, self.min.z),
+            Vec3::new(self.min.x, self.max.y, self.min.z),
+            Vec3::new(self.max.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.min.y, self.max.z),
+            Vec3::new(self.min.x, self.max.y, self.max.z),
+            Vec3::new(self.max.x, self.max.y, self.max.z),
+        ].map(|c| transform.transform_point3(c));
+
+        let first = Bounds { min: corners[0], max: corners[0] };
+        corners.iter().fold(first, |bounds, &c| bounds.include(c))
+    }
+
+    pub fn centre(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+}
+
+pub fn face_normal([a, b, c]: [[f32; 3]; 3]) -> [f32; 3] {
+    let [a, b, c] = [Vec3::from(a), Vec3::from(b), Vec3::from(c)];
+
+    // Degenerate triangles get an arbitrary direction
+    (b - a).cross(c - a).try_normalize().unwrap_or(Vec3::Z).into()
+}
+
+// Geometry living in device local memory
+pub struct Mesh {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+}
+
+impl Mesh {
+    pub fn upload(logical_device: &LogicalDevice, command_pool: &CommandPool, data: &MeshData) -> Result<Self> {
+        if data.vertices.is_empty() || data.indices.is_empty() {
+            return Err(Error::msg("Cannot upload an empty mesh"));
+        }
+
+        let vertex_buffer = Buffer::new_device_local(
+            logical_device,
+            command_pool,
+            bytemuck::cast_slice(&data.vertices),
+            BufferUsageFlags::VERTEX_BUFFER
+        )?;
+        let index_buffer = Buffer::new_device_local(
+            logical_device,
+            command_pool,
+            bytemuck::cast_slice(&data.indices),
+            BufferUsageFlags::INDEX_BUFFER
+        )?;
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+        })
+    }
+
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[*self.vertex_buffer.raw()], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, *self.index_buffer.raw(), 0, IndexType::UINT32);
+            device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+}
@@ -0,0 +1,374 @@
+use ash::vk::{self, AccessFlags, DescriptorType, Filter, Format, FormatFeatureFlags, ImageAspectFlags, ImageLayout, ImageSubresourceRange, ImageUsageFlags, ImageViewType, PipelineBindPoint, PipelineStageFlags, ShaderStageFlags};
+use anyhow::{Error, Result};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{LogicalDevice, image::Image, image_view::ImageView, shader_module::ShaderModule, shaders::load_shader};
+
+// Enough levels to get down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipStrategy {
+    // vkCmdBlitImage with linear filtering, the cheap option
+    Blit,
+    // A compute shader averaging 2x2 blocks, for formats that can't be linearly blitted
+    Compute,
+}
+
+pub fn choose_strategy(logical_device: &LogicalDevice, format: Format) -> Result<MipStrategy> {
+    let features = logical_device.format_properties(format).optimal_tiling_features;
+    let blit_features = FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR | FormatFeatureFlags::BLIT_SRC | FormatFeatureFlags::BLIT_DST;
+
+    if features.contains(blit_features) {
+        return Ok(MipStrategy::Blit);
+    }
+
+    let supports_storage = storage_format(format).is_some_and(|storage| {
+        logical_device.format_properties(storage).optimal_tiling_features.contains(FormatFeatureFlags::STORAGE_IMAGE)
+    });
+
+    if supports_storage {
+        return Ok(MipStrategy::Compute);
+    }
+
+    Err(Error::msg(format!("No way to generate mipmaps for {:?} on this GPU", format)))
+}
+
+// The compute shader only knows about rgba8, sRGB gets converted by hand.
+// Images in any other format need EXTENDED_USAGE to be written through views of this one
+pub fn storage_format(format: Format) -> Option<Format> {
+    match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => Some(Format::R8G8B8A8_UNORM),
+        _ => None,
+    }
+}
+
+fn level_range(level: u32) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask: ImageAspectFlags::COLOR,
+        base_mip_level: level,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1
+    }
+}
+
+fn level_size(size: u32, level: u32) -> u32 {
+    (size >> level).max(1)
+}
+
+// Expects every level in TRANSFER_DST_OPTIMAL with level 0 filled in, and leaves them all SHADER_READ_ONLY_OPTIMAL
+pub fn record_blits(device: &ash::Device, command_buffer: vk::CommandBuffer, image: &Image) {
+    let extent = *image.extent();
+
+    for level in 1..image.mip_levels() {
+        // The previous level has been written, now it gets read from
+        let to_src = image.barrier(
+            level_range(level - 1),
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::TRANSFER_READ
+        );
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::TRANSFER, PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[], &[], &[to_src]);
+        }
+
+        let offsets = |level: u32| [
+            vk::Offset3D { x: 0, y: 0, z: 0 },
+            vk::Offset3D { x: level_size(extent.width, level) as i32, y: level_size(extent.height, level) as i32, z: 1 },
+        ];
+        let layers = |level: u32| vk::ImageSubresourceLayers {
+            aspect_mask: ImageAspectFlags::COLOR,
+            mip_level: level,
+            base_array_layer: 0,
+            layer_count: 1
+        };
+
+        let blit = vk::ImageBlit {
+            src_subresource: layers(level - 1),
+            src_offsets: offsets(level - 1),
+            dst_subresource: layers(level),
+            dst_offsets: offsets(level),
+        };
+
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                *image.raw(),
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                *image.raw(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                Filter::LINEAR
+            );
+        }
+
+        let to_shader = image.barrier(
+            level_range(level - 1),
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            AccessFlags::TRANSFER_READ,
+            AccessFlags::SHADER_READ
+        );
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::TRANSFER, PipelineStageFlags::FRAGMENT_SHADER, vk::DependencyFlags::empty(), &[], &[], &[to_shader]);
+        }
+    }
+
+    // The last level is never blitted from
+    let last = image.barrier(
+        level_range(image.mip_levels() - 1),
+        ImageLayout::TRANSFER_DST_OPTIMAL,
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        AccessFlags::TRANSFER_WRITE,
+        AccessFlags::SHADER_READ
+    );
+
+    unsafe {
+        device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::TRANSFER, PipelineStageFlags::FRAGMENT_SHADER, vk::DependencyFlags::empty(), &[], &[], &[last]);
+    }
+}
+
+// Matches the push_constant block in downsample.comp
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DownsamplePushConstants {
+    dst_size: [i32; 2],
+    srgb: u32,
+}
+
+const WORKGROUP_SIZE: u32 = 8;
+
+// The downsample shader's pipeline, built the first time a texture needs it and kept by LogicalDevice
+pub struct DownsamplePipeline {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    device: ash::Device
+}
+
+impl DownsamplePipeline {
+    pub fn new(logical_device: &LogicalDevice) -> Result<Self> {
+        let device = logical_device.raw();
+        let shader = ShaderModule::new(logical_device, &load_shader("downsample.comp")?)?;
+
+        // Binding 0 is the level being read, binding 1 the level being written
+        let bindings = [0, 1].map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: DescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+            stage_flags: ShaderStageFlags::COMPUTE,
+            ..Default::default()
+        });
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe { device.create_descriptor_set_layout(&descriptor_set_layout_create_info, None)? };
+
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: size_of::<DownsamplePushConstants>() as u32,
+        };
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constant_range,
+            ..Default::default()
+        };
+        let pipeline_layout = match unsafe { device.create_pipeline_layout(&pipeline_layout_create_info, None) } {
+            Ok(layout) => layout,
+            Err(e) => {
+                unsafe { device.destroy_descriptor_set_layout(descriptor_set_layout, None) };
+                return Err(e.into());
+            },
+        };
+
+        let pipeline_create_info = vk::ComputePipelineCreateInfo {
+            stage: vk::PipelineShaderStageCreateInfo {
+                stage: ShaderStageFlags::COMPUTE,
+                module: *shader.raw(),
+                p_name: c"main".as_ptr(),
+                ..Default::default()
+            },
+            layout: pipeline_layout,
+            ..Default::default()
+        };
+        let pipeline = match unsafe { device.create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None) } {
+            Ok(pipelines) => pipelines[0],
+            Err((_, e)) => {
+                unsafe {
+                    device.destroy_pipeline_layout(pipeline_layout, None);
+                    device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+                }
+                return Err(e.into());
+            },
+        };
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            device: device.clone()
+        })
+    }
+}
+
+impl Drop for DownsamplePipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+// The views and descriptor sets for downsampling one image with the shared DownsamplePipeline
+pub struct ComputeDownsampler {
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_pool: vk::DescriptorPool,
+    level_views: Vec<ImageView>,
+    // Owned by LogicalDevice, which outlives this
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    srgb: bool,
+    device: ash::Device
+}
+
+impl ComputeDownsampler {
+    pub fn new(logical_device: &LogicalDevice, image: &Image) -> Result<Self> {
+        let device = logical_device.raw();
+        let storage = storage_format(*image.format())
+            .ok_or_else(|| Error::msg(format!("Cannot downsample {:?} in a compute shader", image.format())))?;
+        let shared = logical_device.downsample_pipeline()?;
+        let descriptor_set_layout = shared.descriptor_set_layout;
+
+        let level_views = (0..image.mip_levels())
+            .map(|level| ImageView::with_usage(logical_device, image.raw(), &storage, ImageViewType::TYPE_2D, level_range(level), ImageUsageFlags::STORAGE))
+            .collect::<Result<Vec<_>>>()?;
+
+        let set_count = image.mip_levels() - 1;
+        let pool_size = vk::DescriptorPoolSize {
+            ty: DescriptorType::STORAGE_IMAGE,
+            descriptor_count: set_count * 2,
+        };
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            max_sets: set_count,
+            pool_size_count: 1,
+            p_pool_sizes: &pool_size,
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&descriptor_pool_create_info, None)? };
+
+        let set_layouts = vec![descriptor_set_layout; set_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_count,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(&allocate_info)? };
+
+        for (ix, set) in descriptor_sets.iter().enumerate() {
+            let image_infos = [&level_views[ix], &level_views[ix + 1]].map(|view| vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: *view.raw(),
+                image_layout: ImageLayout::GENERAL,
+            });
+            let writes = [0, 1].map(|binding| vk::WriteDescriptorSet {
+                dst_set: *set,
+                dst_binding: binding,
+                descriptor_count: 1,
+                descriptor_type: DescriptorType::STORAGE_IMAGE,
+                p_image_info: &image_infos[binding as usize],
+                ..Default::default()
+            });
+
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        Ok(Self {
+            descriptor_sets,
+            descriptor_pool,
+            level_views,
+            pipeline: shared.pipeline,
+            pipeline_layout: shared.pipeline_layout,
+            srgb: *image.format() == Format::R8G8B8A8_SRGB,
+            device: device.clone()
+        })
+    }
+
+    // Same contract as `record_blits`
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, image: &Image) {
+        let extent = *image.extent();
+
+        let to_general = image.barrier(
+            image.full_range(),
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageLayout::GENERAL,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE
+        );
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::TRANSFER, PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[], &[to_general]);
+            device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::COMPUTE, self.pipeline);
+        }
+
+        for level in 1..image.mip_levels() {
+            let width = level_size(extent.width, level);
+            let height = level_size(extent.height, level);
+            let push_constants = DownsamplePushConstants {
+                dst_size: [width as i32, height as i32],
+                srgb: self.srgb as u32,
+            };
+
+            let written = image.barrier(
+                level_range(level),
+                ImageLayout::GENERAL,
+                ImageLayout::GENERAL,
+                AccessFlags::SHADER_WRITE,
+                AccessFlags::SHADER_READ
+            );
+
+            unsafe {
+                device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::COMPUTE, self.pipeline_layout, 0, &[self.descriptor_sets[level as usize - 1]], &[]);
+                device.cmd_push_constants(command_buffer, self.pipeline_layout, ShaderStageFlags::COMPUTE, 0, bytemuck::bytes_of(&push_constants));
+                device.cmd_dispatch(command_buffer, width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+                // The next dispatch reads what this one wrote
+                device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::COMPUTE_SHADER, PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[], &[], &[written]);
+            }
+        }
+
+        let to_shader = image.barrier(
+            image.full_range(),
+            ImageLayout::GENERAL,
+            ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            AccessFlags::SHADER_WRITE,
+            AccessFlags::SHADER_READ
+        );
+
+        unsafe {
+            device.cmd_pipeline_barrier(command_buffer, PipelineStageFlags::COMPUTE_SHADER, PipelineStageFlags::FRAGMENT_SHADER, vk::DependencyFlags::empty(), &[], &[], &[to_shader]);
+        }
+    }
+}
+
+impl Drop for ComputeDownsampler {
+    fn drop(&mut self) {
+        unsafe {
+            // Frees the sets along with it
+            self.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.level_views.clear();
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use ash::vk::{Extent2D, Format, ImageUsageFlags, SampleCountFlags};
+use anyhow::Result;
+
+use crate::{LogicalDevice, image::{Image, ImageDescription}, image_view::ImageView};
+
+// Highest first, so the first one the device has is the closest to what was asked for
+const SAMPLE_COUNTS: [SampleCountFlags; 7] = [
+    SampleCountFlags::TYPE_64,
+    SampleCountFlags::TYPE_32,
+    SampleCountFlags::TYPE_16,
+    SampleCountFlags::TYPE_8,
+    SampleCountFlags::TYPE_4,
+    SampleCountFlags::TYPE_2,
+    SampleCountFlags::TYPE_1,
+];
+
+// The largest count no higher than the requested one that both colour and depth attachments support.
+// One sample is always supported
+pub fn clamp_samples(logical_device: &LogicalDevice, requested: SampleCountFlags) -> SampleCountFlags {
+    let limits = &logical_device.properties().limits;
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    SAMPLE_COUNTS.into_iter()
+        .find(|&count| count.as_raw() <= requested.as_raw() && supported.contains(count))
+        .unwrap_or(SampleCountFlags::TYPE_1)
+}
+
+// The multisampled image drawn into and then resolved into the swap chain image. Only the resolved
+// image is kept, so this one lives in transient memory where the device has it.
+// Sized to the swap chain, so it's recreated along with it
+pub struct MultisampleTarget {
+    view: ImageView,
+    image: Image,
+}
+
+impl MultisampleTarget {
+    pub fn new(logical_device: &LogicalDevice, extent: &Extent2D, format: Format, samples: SampleCountFlags) -> Result<Self> {
+        let description = ImageDescription {
+            samples,
+            ..ImageDescription::new_2d(extent.width, extent.height, format, ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSIENT_ATTACHMENT)
+        };
+        let image = Image::new(logical_device, &description)?;
+        let view = ImageView::new(logical_device, image.raw(), &format)?;
+
+        Ok(Self {
+            view,
+            image,
+        })
+    }
+
+    pub fn view(&self) -> &ImageView {
+        &self.view
+    }
+
+    // For the layout transitions dynamic rendering leaves to the caller
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn format(&self) -> Format {
+        *self.image.format()
+    }
+}
@@ -0,0 +1,419 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, path::{Path, PathBuf}};
+
+use anyhow::Result;
+
+use crate::mesh::{MeshData, Vertex, face_normal};
+
+// Parse failure pointing at the file and line that caused it
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emissive: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub illumination_model: Option<u32>,
+    // Texture paths are resolved relative to the MTL file
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub dissolve_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            emissive: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            illumination_model: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            dissolve_texture: None,
+        }
+    }
+}
+
+// One chunk of geometry per object/group and material combination
+#[derive(Clone, Debug)]
+pub struct ObjMesh {
+    pub name: String,
+    pub material: Option<usize>,
+    pub data: MeshData,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+impl ObjModel {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+
+        Self::parse(&source, path)
+    }
+
+    // `path` is only used for error messages and finding `mtllib` files next to the model
+    pub fn parse(source: &str, path: &Path) -> Result<Self> {
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut normals: Vec<[f32; 3]> = vec![];
+        let mut uvs: Vec<[f32; 2]> = vec![];
+
+        let mut model = ObjModel::default();
+        let mut material_ids: HashMap<String, usize> = HashMap::new();
+        let mut builder = MeshBuilder::new("default", None);
+        let mut ignored = HashSet::new();
+
+        for (ix, line) in source.lines().enumerate() {
+            let line_number = ix + 1;
+            let error = |message: String| ObjError { path: path.to_path_buf(), line: line_number, message };
+
+            let line = strip_comment(line);
+            let mut tokens = line.split_whitespace();
+            let Some(keyword) = tokens.next() else {
+                continue;
+            };
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => positions.push(parse_floats::<3>(&args).map_err(error)?),
+                "vn" => normals.push(parse_floats::<3>(&args).map_err(error)?),
+                "vt" => {
+                    // v is optional and defaults to 0, the optional w component is ignored
+                    let uv = match args.len() {
+                        1 => [parse_floats::<1>(&args).map_err(error)?[0], 0.0],
+                        _ => parse_floats::<2>(&args[..args.len().min(2)]).map_err(error)?,
+                    };
+                    // OBJ has v pointing up, Vulkan samples with v pointing down
+                    uvs.push([uv[0], 1.0 - uv[1]]);
+                },
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(error(format!("Face needs at least 3 vertices, found {}", args.len())).into());
+                    }
+
+                    let corners = args.iter()
+                        .map(|a| parse_face_vertex(a, positions.len(), uvs.len(), normals.len()))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(error)?;
+
+                    // Fan triangulation, fine for the convex polygons modelling tools export
+                    for i in 1..corners.len() - 1 {
+                        builder.push_triangle([corners[0], corners[i], corners[i + 1]], &positions, &uvs, &normals);
+                    }
+                },
+                "o" | "g" => {
+                    let name = args.first().copied().unwrap_or("default");
+                    let material = builder.material;
+                    model.meshes.extend(builder.finish());
+                    builder = MeshBuilder::new(name, material);
+                },
+                "usemtl" => {
+                    let name = args.first().ok_or_else(|| error("usemtl needs a material name".to_string()))?;
+                    let material = *material_ids.get(*name)
+                        .ok_or_else(|| error(format!("Unknown material '{name}'")))?;
+
+                    if builder.material != Some(material) {
+                        let name = builder.name.clone();
+                        model.meshes.extend(builder.finish());
+                        builder = MeshBuilder::new(&name, Some(material));
+                    }
+                },
+                "mtllib" => {
+                    if args.is_empty() {
+                        return Err(error("mtllib needs a file name".to_string()).into());
+                    }
+
+                    // Several libraries are separated by spaces, unless it's one file name with spaces in it
+                    let joined = base_dir.join(args.join(" "));
+                    let mtl_paths = if args.len() > 1 && joined.is_file() {
+                        vec![joined]
+                    } else {
+                        args.iter().map(|name| base_dir.join(name)).collect()
+                    };
+
+                    for mtl_path in mtl_paths {
+                        let mtl_source = fs::read_to_string(&mtl_path)
+                            .map_err(|e| error(format!("Cannot read material library {}: {e}", mtl_path.display())))?;
+
+                        for material in parse_mtl(&mtl_source, &mtl_path)? {
+                            material_ids.insert(material.name.clone(), model.materials.len());
+                            model.materials.push(material);
+                        }
+                    }
+                },
+                // Smoothing groups, lines and points don't affect triangle meshes
+                "s" | "l" | "p" => {},
+                // Free-form geometry, merging groups and the like, which exporters rarely write but are valid
+                other => warn_ignored(&mut ignored, path, line_number, other),
+            }
+        }
+
+        model.meshes.extend(builder.finish());
+
+        Ok(model)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    data: MeshData,
+    lookup: HashMap<[u32; 8], u32>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            material,
+            data: MeshData::default(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn push_triangle(&mut self, corners: [FaceVertex; 3], positions: &[[f32; 3]], uvs: &[[f32; 2]], normals: &[[f32; 3]]) {
+        let corner_positions = corners.map(|c| positions[c.position]);
+        let flat_normal = face_normal(corner_positions);
+
+        for (corner, position) in corners.iter().zip(corner_positions) {
+            let vertex = Vertex {
+                position,
+                normal: corner.normal.map_or(flat_normal, |n| normals[n]),
+                uv: corner.uv.map_or([0.0, 0.0], |t| uvs[t]),
+            };
+
+            // Identical vertices share an index
+            let next_index = self.data.vertices.len() as u32;
+            let index = *self.lookup.entry(vertex.key()).or_insert(next_index);
+            if index == next_index {
+                self.data.vertices.push(vertex);
+            }
+
+            self.data.indices.push(index);
+        }
+    }
+
+    fn finish(self) -> Option<ObjMesh> {
+        if self.data.indices.is_empty() {
+            return None;
+        }
+
+        Some(ObjMesh {
+            name: self.name,
+            material: self.material,
+            data: self.data,
+        })
+    }
+}
+
+// Once per statement and file, so a file full of them doesn't flood the log
+fn warn_ignored(ignored: &mut HashSet<String>, path: &Path, line: usize, keyword: &str) {
+    if ignored.insert(keyword.to_string()) {
+        eprintln!("{}:{line}: Ignoring unsupported statement '{keyword}'", path.display());
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(ix) => &line[..ix],
+        None => line,
+    }
+}
+
+fn parse_floats<const N: usize>(args: &[&str]) -> Result<[f32; N], String> {
+    if args.len() < N {
+        return Err(format!("Expected {N} numbers, found {}", args.len()));
+    }
+
+    let mut values = [0.0f32; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse().map_err(|_| format!("'{arg}' is not a number"))?;
+    }
+
+    Ok(values)
+}
+
+// OBJ indices start at 1, negative indices count back from the latest element
+fn resolve_index(token: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index: i64 = token.parse().map_err(|_| format!("'{token}' is not a valid {kind} index"))?;
+
+    let resolved = match index {
+        i if i > 0 => i - 1,
+        i if i < 0 => count as i64 + i,
+        _ => return Err(format!("{kind} index cannot be 0")),
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{kind} index {index} is out of range, only {count} defined so far"));
+    }
+
+    Ok(resolved as usize)
+}
+
+// Accepts v, v/vt, v//vn and v/vt/vn
+fn parse_face_vertex(token: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<FaceVertex, String> {
+    let mut parts = token.split('/');
+
+    let position = resolve_index(parts.next().unwrap_or(""), position_count, "Position")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(t) => Some(resolve_index(t, uv_count, "Texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(n) => Some(resolve_index(n, normal_count, "Normal")?),
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("Malformed face vertex '{token}'"));
+    }
+
+    Ok(FaceVertex { position, uv, normal })
+}
+
+fn parse_mtl(source: &str, path: &Path) -> Result<Vec<ObjMaterial>> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut materials: Vec<ObjMaterial> = vec![];
+    let mut ignored = HashSet::new();
+
+    for (ix, line) in source.lines().enumerate() {
+        let line_number = ix + 1;
+        let error = |message: String| ObjError { path: path.to_path_buf(), line: line_number, message };
+
+        let line = strip_comment(line);
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            let name = args.first().ok_or_else(|| error("newmtl needs a material name".to_string()))?;
+            materials.push(ObjMaterial::new(name));
+            continue;
+        }
+
+        let material = materials.last_mut()
+            .ok_or_else(|| error(format!("'{keyword}' appears before any newmtl")))?;
+
+        // Texture maps can carry options such as -bm 1.0 before the file name, which is always last
+        let texture = || args.last().map(|f| base_dir.join(f)).ok_or_else(|| error(format!("{keyword} needs a file name")));
+
+        match keyword {
+            "Ka" => material.ambient = parse_floats::<3>(&args).map_err(error)?,
+            "Kd" => material.diffuse = parse_floats::<3>(&args).map_err(error)?,
+            "Ks" => material.specular = parse_floats::<3>(&args).map_err(error)?,
+            "Ke" => material.emissive = parse_floats::<3>(&args).map_err(error)?,
+            "Ns" => material.shininess = parse_floats::<1>(&args).map_err(error)?[0],
+            "d" => material.dissolve = parse_floats::<1>(&args).map_err(error)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(&args).map_err(error)?[0],
+            "illum" => {
+                let model = args.first().and_then(|a| a.parse().ok())
+                    .ok_or_else(|| error("illum needs an integer".to_string()))?;
+                material.illumination_model = Some(model);
+            },
+            "map_Kd" => material.diffuse_texture = Some(texture()?),
+            "map_Ks" => material.specular_texture = Some(texture()?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_texture = Some(texture()?),
+            "map_d" => material.dissolve_texture = Some(texture()?),
+            // Rarely used and not needed by the renderer
+            "Ni" | "Tf" | "map_Ka" | "map_Ns" | "disp" | "decal" | "refl" => {},
+            // PBR extensions and vendor statements
+            other => warn_ignored(&mut ignored, path, line_number, other),
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ObjModel {
+        ObjModel::parse(source, Path::new("inline.obj")).unwrap()
+    }
+
+    #[test]
+    fn triangulates_quads() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n");
+
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].data.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn single_component_uv_defaults_v() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0.25\nf 1/1 2/1 3/1\n");
+
+        // v is flipped for Vulkan, so the default 0 becomes 1
+        assert_eq!(model.meshes[0].data.vertices[0].uv, [0.25, 1.0]);
+    }
+
+    #[test]
+    fn skips_unsupported_statements() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nvp 0.5 0.5\nl 1 2\ncstype bspline\nf 1 2 3\n");
+
+        assert_eq!(model.meshes[0].data.indices.len(), 3);
+    }
+
+    #[test]
+    fn reports_bad_indices_with_line() {
+        let error = ObjModel::parse("v 0 0 0\nf 1 2 3\n", Path::new("inline.obj")).unwrap_err();
+
+        assert!(error.to_string().starts_with("inline.obj:2: "), "{error}");
+    }
+
+    #[test]
+    fn loads_every_material_library() {
+        let dir = std::env::temp_dir().join(format!("vulkrust-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mtl"), "newmtl red\nKd 1 0 0\nPr 0.5\n").unwrap();
+        fs::write(dir.join("b.mtl"), "newmtl green\nKd 0 1 0\nmap_Bump -bm 1.0 normal.png\n").unwrap();
+
+        let source = "mtllib a.mtl b.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl red\nf 1 2 3\nusemtl green\nf 1 3 2\n";
+        let model = ObjModel::parse(source, &dir.join("model.obj")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = model.materials.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["red", "green"]);
+        assert_eq!(model.materials[1].normal_texture, Some(dir.join("normal.png")));
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.meshes[1].material, Some(1));
+    }
+}
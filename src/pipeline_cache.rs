@@ -0,0 +1,168 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Error, Result};
+use ash::vk;
+
+use crate::LogicalDevice;
+
+// VkPipelineCacheHeaderVersionOne: header size, header version, vendor ID, device ID, then the 16 byte UUID
+const HEADER_SIZE: usize = 32;
+
+// Keeps compiled pipelines between runs. Anything that doesn't match the current GPU and driver is thrown away
+pub struct PipelineCache {
+    raw: vk::PipelineCache,
+    path: Option<PathBuf>,
+    device: ash::Device,
+}
+
+impl PipelineCache {
+    pub fn new(logical_device: &LogicalDevice) -> Result<Self> {
+        let path = dirs::cache_dir().map(|dir| dir.join("vulkrust-play").join("pipeline_cache.bin"));
+
+        let initial_data = match &path {
+            Some(path) if path.exists() => match fs::read(path).map_err(Error::from).and_then(|data| check_header(&data, logical_device.properties()).map(|_| data)) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("Discarding pipeline cache {}: {e}", path.display());
+                    vec![]
+                },
+            },
+            _ => vec![],
+        };
+
+        // VkPipelineCacheCreateInfo
+        let device = logical_device.raw();
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&initial_data);
+        let raw = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(raw) => raw,
+            // The header can be fine and the rest still not to the driver's liking
+            Err(e) if !initial_data.is_empty() => {
+                println!("Driver rejected the pipeline cache, starting empty: {e}");
+                unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)? }
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            raw,
+            path,
+            device: device.clone(),
+        })
+    }
+
+    pub fn raw(&self) -> &vk::PipelineCache {
+        &self.raw
+    }
+
+    // Written to a temporary file first, so a crash part way through can't leave a truncated cache behind
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let data = unsafe { self.device.get_pipeline_cache_data(self.raw)? };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, &data)?;
+        fs::rename(&temp, path)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        println!("Dropping PipelineCache");
+        if let Err(e) = self.save() {
+            eprintln!("Cannot save pipeline cache: {e}");
+        }
+        unsafe { self.device.destroy_pipeline_cache(self.raw, None) };
+    }
+}
+
+// A cache from another GPU or driver version is useless at best, so it has to match exactly
+fn check_header(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<()> {
+    if data.len() < HEADER_SIZE {
+        return Err(Error::msg(format!("only {} bytes, too short for a header", data.len())));
+    }
+
+    let word = |ix: usize| u32::from_ne_bytes(data[ix * 4..ix * 4 + 4].try_into().unwrap());
+    let header_size = word(0) as usize;
+    let header_version = word(1);
+    let vendor_id = word(2);
+    let device_id = word(3);
+    let uuid = &data[16..32];
+
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err(Error::msg(format!("header size {header_size} doesn't fit a {} byte file", data.len())));
+    }
+    if header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(Error::msg(format!("unknown header version {header_version}")));
+    }
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(Error::msg(format!(
+            "made for device {vendor_id:#06x}:{device_id:#06x}, this is {:#06x}:{:#06x}",
+            properties.vendor_id, properties.device_id
+        )));
+    }
+    if uuid != properties.pipeline_cache_uuid {
+        return Err(Error::msg("made by a different driver version"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2684,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    // What the driver would write for `properties`, followed by some cache data
+    fn cache_data(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = vec![];
+        for word in [HEADER_SIZE as u32, vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32, properties.vendor_id, properties.device_id] {
+            data.extend(word.to_ne_bytes());
+        }
+        data.extend(properties.pipeline_cache_uuid);
+        data.extend([0xab; 64]);
+        data
+    }
+
+    #[test]
+    fn accepts_a_matching_header() {
+        check_header(&cache_data(&properties()), &properties()).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let data = cache_data(&properties());
+
+        let error = check_header(&data[..HEADER_SIZE - 1], &properties()).unwrap_err();
+        assert!(error.to_string().contains("31 bytes, too short for a header"), "{error}");
+
+        // The header claims to be longer than the file
+        let mut data = data;
+        data[..4].copy_from_slice(&1000u32.to_ne_bytes());
+        let error = check_header(&data, &properties()).unwrap_err();
+        assert!(error.to_string().contains("header size 1000 doesn't fit"), "{error}");
+    }
+
+    #[test]
+    fn rejects_other_devices_and_drivers() {
+        let other_driver = vk::PhysicalDeviceProperties { pipeline_cache_uuid: [8; vk::UUID_SIZE], ..properties() };
+        let error = check_header(&cache_data(&other_driver), &properties()).unwrap_err();
+        assert!(error.to_string().contains("different driver version"), "{error}");
+
+        let other_device = vk::PhysicalDeviceProperties { device_id: 0x2204, ..properties() };
+        let error = check_header(&cache_data(&other_device), &properties()).unwrap_err();
+        assert!(error.to_string().contains("made for device 0x10de:0x2204"), "{error}");
+    }
+}
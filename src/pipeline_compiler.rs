@@ -0,0 +1,344 @@
+use std::{any::Any, collections::HashMap, panic::{self, AssertUnwindSafe}, sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}}, thread::{self, JoinHandle}};
+
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, graphics_pipeline::{GraphicsPipeline, GraphicsPipelineBuilder, RenderTarget}, pipeline_cache::PipelineCache};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineHandle(usize);
+
+pub enum PipelineStatus<'a> {
+    Pending,
+    Ready(&'a GraphicsPipeline),
+    Failed(&'a Error),
+}
+
+enum PipelineState<P> {
+    Pending,
+    Ready(Box<P>),
+    Failed(Error),
+}
+
+// The state of one handle across builds. Generic over the pipeline so it can be tested without a device
+struct PipelineSlot<P> {
+    state: PipelineState<P>,
+    // Bumped on every rebuild, so a slow result from an older build can't replace a newer one
+    requested: u64,
+    completed: u64,
+}
+
+struct PipelineEntry {
+    builder: GraphicsPipelineBuilder,
+    slot: PipelineSlot<GraphicsPipeline>,
+}
+
+struct Job {
+    handle: PipelineHandle,
+    generation: u64,
+    builder: GraphicsPipelineBuilder,
+}
+
+struct Built {
+    handle: PipelineHandle,
+    generation: u64,
+    pipeline: Result<GraphicsPipeline>,
+}
+
+// Builds pipelines on worker threads so the frame loop never waits on shader compilation.
+// Vulkan lets any thread create objects on a device, and the pipeline cache does its own locking,
+// so the workers only ever share the device for vkCreate* and vkDestroy* calls and never touch a queue
+pub struct PipelineCompiler {
+    entries: Vec<PipelineEntry>,
+    handles: HashMap<GraphicsPipelineBuilder, PipelineHandle>,
+    // Replaced by a rebuild but maybe still in use by frames in flight
+    retired: Vec<GraphicsPipeline>,
+    jobs: Option<Sender<Job>>,
+    built: Receiver<Built>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl PipelineCompiler {
+    pub fn new(logical_device: &Arc<LogicalDevice>, pipeline_cache: &Arc<PipelineCache>, target: &RenderTarget) -> Result<Self> {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (built_sender, built) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        // Leave a core for the frame loop
+        let worker_count = thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).clamp(1, 4));
+
+        let workers = (0..worker_count).map(|ix| {
+            let job_receiver = job_receiver.clone();
+            let built_sender = built_sender.clone();
+            let logical_device = logical_device.clone();
+            let pipeline_cache = pipeline_cache.clone();
+            let target = target.clone();
+
+            thread::Builder::new().name(format!("pipeline-compiler-{ix}")).spawn(move || {
+                loop {
+                    // The lock is only held while waiting for a job, not while building it
+                    let job = job_receiver.lock().unwrap().recv();
+                    let Ok(job) = job else { break };
+
+                    let pipeline = catch_panic(|| job.builder.build(&logical_device, &pipeline_cache, &target));
+                    if built_sender.send(Built { handle: job.handle, generation: job.generation, pipeline }).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            entries: vec![],
+            handles: HashMap::new(),
+            retired: vec![],
+            jobs: Some(jobs),
+            built,
+            workers,
+        })
+    }
+
+    // Asking for the same description again gives back the same handle
+    pub fn request(&mut self, builder: &GraphicsPipelineBuilder) -> PipelineHandle {
+        match self.handles.get(builder) {
+            Some(&handle) => handle,
+            None => self.request_unique(builder),
+        }
+    }
+
+    // A handle nobody else shares, for pipelines that will be changed with update
+    pub fn request_unique(&mut self, builder: &GraphicsPipelineBuilder) -> PipelineHandle {
+        let handle = PipelineHandle(self.entries.len());
+        self.entries.push(PipelineEntry {
+            builder: builder.clone(),
+            slot: PipelineSlot::new(),
+        });
+        self.handles.entry(builder.clone()).or_insert(handle);
+        self.submit(handle);
+
+        handle
+    }
+
+    // Rebuilds the pipeline behind a handle from a new description, e.g. when its file is edited.
+    // The old pipeline stays in use until the new one is ready
+    pub fn update(&mut self, handle: PipelineHandle, builder: &GraphicsPipelineBuilder) {
+        let entry = &mut self.entries[handle.0];
+        if self.handles.get(&entry.builder) == Some(&handle) {
+            self.handles.remove(&entry.builder);
+        }
+        entry.builder = builder.clone();
+        self.handles.entry(builder.clone()).or_insert(handle);
+        self.submit(handle);
+    }
+
+    // After the shaders change on disk. Ready pipelines stay usable until their replacements are built
+    pub fn rebuild_all(&mut self) {
+        for ix in 0..self.entries.len() {
+            self.submit(PipelineHandle(ix));
+        }
+    }
+
+    fn submit(&mut self, handle: PipelineHandle) {
+        let entry = &mut self.entries[handle.0];
+
+        let job = Job {
+            handle,
+            generation: entry.slot.submit(),
+            builder: entry.builder.clone(),
+        };
+        if let Some(jobs) = &self.jobs {
+            // Workers catch panics from the build, so there is always one left to take the job
+            jobs.send(job).ok();
+        }
+    }
+
+    // Picks up whatever the workers have finished, once per frame. Returns the pipelines that were
+    // replaced, which can only be dropped once the GPU is done with them
+    pub fn poll(&mut self) -> Vec<GraphicsPipeline> {
+        while let Ok(built) = self.built.try_recv() {
+            self.receive(built);
+        }
+
+        std::mem::take(&mut self.retired)
+    }
+
+    // Blocks until the pipeline is built, for the ones that have to exist before the first frame
+    pub fn wait(&mut self, handle: PipelineHandle) -> Result<&GraphicsPipeline> {
+        while matches!(self.entries[handle.0].slot.state, PipelineState::Pending) {
+            let built = self.built.recv().map_err(|_| Error::msg("Every pipeline compiler thread has stopped"))?;
+            self.receive(built);
+        }
+
+        match &self.entries[handle.0].slot.state {
+            PipelineState::Ready(pipeline) => Ok(pipeline),
+            PipelineState::Failed(e) => Err(Error::msg(e.to_string())),
+            PipelineState::Pending => unreachable!(),
+        }
+    }
+
+    fn receive(&mut self, built: Built) {
+        let entry = &mut self.entries[built.handle.0];
+        let replaced = entry.slot.receive(built.generation, built.pipeline, &entry.builder.describe());
+        self.retired.extend(replaced);
+    }
+
+    pub fn status(&self, handle: PipelineHandle) -> PipelineStatus<'_> {
+        match &self.entries[handle.0].slot.state {
+            PipelineState::Pending => PipelineStatus::Pending,
+            PipelineState::Ready(pipeline) => PipelineStatus::Ready(pipeline),
+            PipelineState::Failed(e) => PipelineStatus::Failed(e),
+        }
+    }
+
+    // What the pipeline was requested with, to derive variants from
+    pub fn builder(&self, handle: PipelineHandle) -> &GraphicsPipelineBuilder {
+        &self.entries[handle.0].builder
+    }
+
+    pub fn get(&self, handle: PipelineHandle) -> Option<&GraphicsPipeline> {
+        match &self.entries[handle.0].slot.state {
+            PipelineState::Ready(pipeline) => Some(pipeline),
+            _ => None,
+        }
+    }
+}
+
+impl<P> PipelineSlot<P> {
+    fn new() -> Self {
+        Self { state: PipelineState::Pending, requested: 0, completed: 0 }
+    }
+
+    // The generation to tag the new build with
+    fn submit(&mut self) -> u64 {
+        self.requested += 1;
+        self.requested
+    }
+
+    // Returns the pipeline a newer build replaced, if any. `name` describes the pipeline for the log
+    fn receive(&mut self, generation: u64, pipeline: Result<P>, name: &str) -> Option<P> {
+        if generation <= self.completed {
+            return None;
+        }
+        self.completed = generation;
+
+        match (pipeline, &self.state) {
+            (Ok(pipeline), _) => match std::mem::replace(&mut self.state, PipelineState::Ready(Box::new(pipeline))) {
+                PipelineState::Ready(old) => {
+                    println!("Rebuilt pipeline for {name}");
+                    Some(*old)
+                },
+                _ => None,
+            },
+            // Keeps drawing with the old pipeline when the new shaders don't build
+            (Err(e), PipelineState::Ready(_)) => {
+                eprintln!("Rebuilding the pipeline for {name} failed, keeping the previous one:\n{e}");
+                None
+            },
+            (Err(e), _) => {
+                eprintln!("Cannot build the pipeline for {name}:\n{e}");
+                self.state = PipelineState::Failed(e);
+                None
+            },
+        }
+    }
+}
+
+// A panic would otherwise take the worker down with the job, leaving its handle pending and wait blocked for good.
+// Whatever the build had created by then is leaked, which beats hanging
+fn catch_panic<P>(build: impl FnOnce() -> Result<P>) -> Result<P> {
+    panic::catch_unwind(AssertUnwindSafe(build)).unwrap_or_else(|payload| Err(Error::msg(format!(
+        "Building the pipeline panicked: {}",
+        panic_message(payload.as_ref())
+    ))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+    }
+}
+
+impl Drop for PipelineCompiler {
+    fn drop(&mut self) {
+        println!("Dropping PipelineCompiler");
+
+        // Closing the channel lets each worker finish its current job and stop
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready(slot: &PipelineSlot<&'static str>) -> Option<&'static str> {
+        match &slot.state {
+            PipelineState::Ready(pipeline) => Some(**pipeline),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn becomes_ready_when_built() {
+        let mut slot = PipelineSlot::new();
+        let generation = slot.submit();
+        assert!(matches!(slot.state, PipelineState::Pending));
+
+        assert_eq!(slot.receive(generation, Ok("first"), "test"), None);
+        assert_eq!(ready(&slot), Some("first"));
+
+        // A rebuild hands back the pipeline it replaced, for the frames still using it
+        let generation = slot.submit();
+        assert_eq!(slot.receive(generation, Ok("second"), "test"), Some("first"));
+        assert_eq!(ready(&slot), Some("second"));
+    }
+
+    #[test]
+    fn drops_results_from_older_builds() {
+        let mut slot = PipelineSlot::new();
+        let older = slot.submit();
+        let newer = slot.submit();
+
+        assert_eq!(slot.receive(newer, Ok("newer"), "test"), None);
+        assert_eq!(slot.receive(older, Ok("older"), "test"), None);
+        assert_eq!(ready(&slot), Some("newer"));
+
+        // Nor can an older failure replace a newer pipeline
+        assert_eq!(slot.receive(older, Err(Error::msg("old shaders")), "test"), None);
+        assert_eq!(ready(&slot), Some("newer"));
+    }
+
+    #[test]
+    fn keeps_the_old_pipeline_when_a_rebuild_fails() {
+        let mut slot = PipelineSlot::new();
+        let generation = slot.submit();
+        slot.receive(generation, Ok("working"), "test");
+
+        let generation = slot.submit();
+        assert_eq!(slot.receive(generation, Err(Error::msg("syntax error")), "test"), None);
+        assert_eq!(ready(&slot), Some("working"));
+    }
+
+    #[test]
+    fn fails_when_the_first_build_fails() {
+        let mut slot = PipelineSlot::<&str>::new();
+        let generation = slot.submit();
+        slot.receive(generation, Err(Error::msg("syntax error")), "test");
+
+        assert!(matches!(&slot.state, PipelineState::Failed(e) if e.to_string() == "syntax error"));
+    }
+
+    #[test]
+    fn turns_panics_into_errors() {
+        let error = catch_panic::<()>(|| panic!("index out of bounds")).unwrap_err();
+        assert_eq!(error.to_string(), "Building the pipeline panicked: index out of bounds");
+
+        let error = catch_panic::<()>(|| panic!("{} is missing", "a binding")).unwrap_err();
+        assert_eq!(error.to_string(), "Building the pipeline panicked: a binding is missing");
+
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+    }
+}
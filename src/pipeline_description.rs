@@ -0,0 +1,644 @@
+use std::{fs, path::{Path, PathBuf}, sync::mpsc::{self, Receiver}};
+
+use anyhow::{Error, Result};
+use ash::vk;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{graphics_pipeline::{BlendState, DepthBias, DepthState, GraphicsPipelineBuilder, PipelineShaders, ShaderEntry}, mesh::{Vertex, VertexInputDescription}, pipeline_compiler::PipelineHandle};
+
+// A pipeline as a .ron or .toml file, so it can be changed without touching Rust.
+// Everything but the shaders can be left out, which gives GraphicsPipelineBuilder::opaque
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineDescription {
+    pub vertex_shader: StageDescription,
+    pub fragment_shader: StageDescription,
+    #[serde(default)]
+    pub vertex_layout: VertexLayout,
+    #[serde(default)]
+    pub raster: RasterDescription,
+    #[serde(default)]
+    pub depth: DepthDescription,
+    // One for every colour attachment, or a single one used for all of them
+    #[serde(default = "default_blend")]
+    pub blend: Vec<BlendMode>,
+    #[serde(default)]
+    pub render_pass: RenderPassDescription,
+    // The descriptor set whose binding 0 is filled from the engine's uniform ring
+    #[serde(default)]
+    pub uniform_ring_set: Option<u32>,
+    // The descriptor set to bind the engine's bindless set to
+    #[serde(default)]
+    pub bindless_set: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageDescription {
+    pub shader: String,
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub enum VertexLayout {
+    // mesh::Vertex, which is what the engine's meshes are made of
+    #[default]
+    Mesh,
+    Custom {
+        bindings: Vec<VertexBinding>,
+        attributes: Vec<VertexAttribute>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    #[serde(default)]
+    pub per_instance: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VertexAttribute {
+    pub location: u32,
+    #[serde(default)]
+    pub binding: u32,
+    pub format: VertexFormat,
+    pub offset: u32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum VertexFormat {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Int,
+    IVec2,
+    IVec3,
+    IVec4,
+    UInt,
+    UVec2,
+    UVec3,
+    UVec4,
+    // Four bytes read as 0.0 to 1.0, e.g. vertex colours
+    Unorm8x4,
+}
+
+impl From<VertexFormat> for vk::Format {
+    fn from(format: VertexFormat) -> Self {
+        match format {
+            VertexFormat::Float => vk::Format::R32_SFLOAT,
+            VertexFormat::Vec2 => vk::Format::R32G32_SFLOAT,
+            VertexFormat::Vec3 => vk::Format::R32G32B32_SFLOAT,
+            VertexFormat::Vec4 => vk::Format::R32G32B32A32_SFLOAT,
+            VertexFormat::Int => vk::Format::R32_SINT,
+            VertexFormat::IVec2 => vk::Format::R32G32_SINT,
+            VertexFormat::IVec3 => vk::Format::R32G32B32_SINT,
+            VertexFormat::IVec4 => vk::Format::R32G32B32A32_SINT,
+            VertexFormat::UInt => vk::Format::R32_UINT,
+            VertexFormat::UVec2 => vk::Format::R32G32_UINT,
+            VertexFormat::UVec3 => vk::Format::R32G32B32_UINT,
+            VertexFormat::UVec4 => vk::Format::R32G32B32A32_UINT,
+            VertexFormat::Unorm8x4 => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RasterDescription {
+    pub topology: PrimitiveTopology,
+    pub primitive_restart: bool,
+    pub polygon_mode: PolygonMode,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub line_width: f32,
+    pub depth_clamp: bool,
+    pub depth_bias: Option<DepthBias>,
+    // The minimum fraction of samples shaded separately, needs sampleRateShading
+    pub sample_shading: Option<f32>,
+}
+
+impl Default for RasterDescription {
+    fn default() -> Self {
+        Self {
+            topology: PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            polygon_mode: PolygonMode::Fill,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            line_width: 1.0,
+            depth_clamp: false,
+            depth_bias: None,
+            sample_shading: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DepthDescription {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+impl Default for DepthDescription {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: CompareOp::Less,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum CompareOp {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl From<CompareOp> for vk::CompareOp {
+    fn from(op: CompareOp) -> Self {
+        match op {
+            CompareOp::Never => vk::CompareOp::NEVER,
+            CompareOp::Less => vk::CompareOp::LESS,
+            CompareOp::Equal => vk::CompareOp::EQUAL,
+            CompareOp::LessOrEqual => vk::CompareOp::LESS_OR_EQUAL,
+            CompareOp::Greater => vk::CompareOp::GREATER,
+            CompareOp::NotEqual => vk::CompareOp::NOT_EQUAL,
+            CompareOp::GreaterOrEqual => vk::CompareOp::GREATER_OR_EQUAL,
+            CompareOp::Always => vk::CompareOp::ALWAYS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum PrimitiveTopology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl From<PrimitiveTopology> for vk::PrimitiveTopology {
+    fn from(topology: PrimitiveTopology) -> Self {
+        match topology {
+            PrimitiveTopology::PointList => vk::PrimitiveTopology::POINT_LIST,
+            PrimitiveTopology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            PrimitiveTopology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            PrimitiveTopology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            PrimitiveTopology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            PrimitiveTopology::TriangleFan => vk::PrimitiveTopology::TRIANGLE_FAN,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
+}
+
+impl From<PolygonMode> for vk::PolygonMode {
+    fn from(mode: PolygonMode) -> Self {
+        match mode {
+            PolygonMode::Fill => vk::PolygonMode::FILL,
+            PolygonMode::Line => vk::PolygonMode::LINE,
+            PolygonMode::Point => vk::PolygonMode::POINT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum CullMode {
+    // Not None, which RON reads as an Option
+    Nothing,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl From<CullMode> for vk::CullModeFlags {
+    fn from(mode: CullMode) -> Self {
+        match mode {
+            CullMode::Nothing => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+            CullMode::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+impl From<FrontFace> for vk::FrontFace {
+    fn from(face: FrontFace) -> Self {
+        match face {
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum BlendMode {
+    Opaque,
+    AlphaBlended,
+    Additive,
+    Premultiplied,
+    Custom {
+        src_colour: BlendFactor,
+        dst_colour: BlendFactor,
+        #[serde(default = "default_blend_op")]
+        colour_op: BlendOp,
+        src_alpha: BlendFactor,
+        dst_alpha: BlendFactor,
+        #[serde(default = "default_blend_op")]
+        alpha_op: BlendOp,
+        // Any of the letters RGBA
+        #[serde(default = "default_write_mask", deserialize_with = "write_mask")]
+        write_mask: vk::ColorComponentFlags,
+    },
+}
+
+impl From<BlendMode> for BlendState {
+    fn from(mode: BlendMode) -> Self {
+        match mode {
+            BlendMode::Opaque => BlendState::OPAQUE,
+            BlendMode::AlphaBlended => BlendState::ALPHA_BLENDED,
+            BlendMode::Additive => BlendState::ADDITIVE,
+            BlendMode::Premultiplied => BlendState::PREMULTIPLIED,
+            BlendMode::Custom { src_colour, dst_colour, colour_op, src_alpha, dst_alpha, alpha_op, write_mask } => BlendState {
+                enable: true,
+                src_colour_factor: src_colour.into(),
+                dst_colour_factor: dst_colour.into(),
+                colour_op: colour_op.into(),
+                src_alpha_factor: src_alpha.into(),
+                dst_alpha_factor: dst_alpha.into(),
+                alpha_op: alpha_op.into(),
+                write_mask,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColour,
+    OneMinusSrcColour,
+    DstColour,
+    OneMinusDstColour,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+}
+
+impl From<BlendFactor> for vk::BlendFactor {
+    fn from(factor: BlendFactor) -> Self {
+        match factor {
+            BlendFactor::Zero => vk::BlendFactor::ZERO,
+            BlendFactor::One => vk::BlendFactor::ONE,
+            BlendFactor::SrcColour => vk::BlendFactor::SRC_COLOR,
+            BlendFactor::OneMinusSrcColour => vk::BlendFactor::ONE_MINUS_SRC_COLOR,
+            BlendFactor::DstColour => vk::BlendFactor::DST_COLOR,
+            BlendFactor::OneMinusDstColour => vk::BlendFactor::ONE_MINUS_DST_COLOR,
+            BlendFactor::SrcAlpha => vk::BlendFactor::SRC_ALPHA,
+            BlendFactor::OneMinusSrcAlpha => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            BlendFactor::DstAlpha => vk::BlendFactor::DST_ALPHA,
+            BlendFactor::OneMinusDstAlpha => vk::BlendFactor::ONE_MINUS_DST_ALPHA,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl From<BlendOp> for vk::BlendOp {
+    fn from(op: BlendOp) -> Self {
+        match op {
+            BlendOp::Add => vk::BlendOp::ADD,
+            BlendOp::Subtract => vk::BlendOp::SUBTRACT,
+            BlendOp::ReverseSubtract => vk::BlendOp::REVERSE_SUBTRACT,
+            BlendOp::Min => vk::BlendOp::MIN,
+            BlendOp::Max => vk::BlendOp::MAX,
+        }
+    }
+}
+
+// What the pipeline expects of the render pass it's used in, checked when it's built
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderPassDescription {
+    pub subpass: u32,
+    pub colour_attachments: u32,
+    // Left out, the pipeline takes the engine's sample count
+    #[serde(deserialize_with = "sample_count")]
+    pub samples: Option<vk::SampleCountFlags>,
+}
+
+impl Default for RenderPassDescription {
+    fn default() -> Self {
+        Self {
+            subpass: 0,
+            colour_attachments: 1,
+            samples: None,
+        }
+    }
+}
+
+fn default_entry_point() -> String {
+    "main".to_string()
+}
+
+fn default_blend() -> Vec<BlendMode> {
+    vec![BlendMode::Opaque]
+}
+
+fn default_blend_op() -> BlendOp {
+    BlendOp::Add
+}
+
+fn default_write_mask() -> vk::ColorComponentFlags {
+    vk::ColorComponentFlags::RGBA
+}
+
+fn write_mask<'de, D: Deserializer<'de>>(deserializer: D) -> Result<vk::ColorComponentFlags, D::Error> {
+    let letters = String::deserialize(deserializer)?;
+    letters.chars().try_fold(vk::ColorComponentFlags::empty(), |mask, letter| match letter.to_ascii_uppercase() {
+        'R' => Ok(mask | vk::ColorComponentFlags::R),
+        'G' => Ok(mask | vk::ColorComponentFlags::G),
+        'B' => Ok(mask | vk::ColorComponentFlags::B),
+        'A' => Ok(mask | vk::ColorComponentFlags::A),
+        _ => Err(de::Error::custom(format!("'{letter}' isn't a colour channel, expected some of RGBA"))),
+    })
+}
+
+fn sample_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<vk::SampleCountFlags>, D::Error> {
+    let samples = u32::deserialize(deserializer)?;
+    if !samples.is_power_of_two() || samples > 64 {
+        return Err(de::Error::custom(format!("{samples} samples isn't possible, expected 1, 2, 4, 8, 16, 32 or 64")));
+    }
+
+    Ok(Some(vk::SampleCountFlags::from_raw(samples)))
+}
+
+impl PipelineDescription {
+    // Errors name the field, e.g. "raster.cull_mode: unknown variant `Bak`", with the line in the file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| Error::msg(format!("Cannot read pipeline {}: {e}", path.display())))?;
+        Self::parse(&text, path).map_err(|e| Error::msg(format!("{}: {e}", path.display())))
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ron") => {
+                let mut deserializer = ron::Deserializer::from_str(text)?;
+                let description = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+                    let field = e.path().to_string();
+                    let located = deserializer.span_error(e.into_inner());
+                    Error::msg(format!("{field}: {located}"))
+                })?;
+                deserializer.end().map_err(|e| Error::msg(deserializer.span_error(e).to_string()))?;
+                Ok(description)
+            },
+            Some("toml") => {
+                let deserializer = toml::Deserializer::parse(text)?;
+                serde_path_to_error::deserialize(deserializer).map_err(|e| Error::msg(format!("{}: {}", e.path(), e.inner())))
+            },
+            _ => Err(Error::msg("unknown pipeline format, expected .ron or .toml")),
+        }
+    }
+
+    pub fn builder(&self) -> Result<GraphicsPipelineBuilder> {
+        let shaders = PipelineShaders {
+            vertex: ShaderEntry::new(&self.vertex_shader.shader, &self.vertex_shader.entry_point),
+            fragment: ShaderEntry::new(&self.fragment_shader.shader, &self.fragment_shader.entry_point),
+        };
+
+        let vertex_input = match &self.vertex_layout {
+            VertexLayout::Mesh => Vertex::input_description(),
+            VertexLayout::Custom { bindings, attributes } => VertexInputDescription {
+                bindings: bindings.iter().map(|binding| vk::VertexInputBindingDescription {
+                    binding: binding.binding,
+                    stride: binding.stride,
+                    input_rate: if binding.per_instance { vk::VertexInputRate::INSTANCE } else { vk::VertexInputRate::VERTEX },
+                }).collect(),
+                attributes: attributes.iter().map(|attribute| vk::VertexInputAttributeDescription {
+                    location: attribute.location,
+                    binding: attribute.binding,
+                    format: attribute.format.into(),
+                    offset: attribute.offset,
+                }).collect(),
+            },
+        };
+
+        let raster = &self.raster;
+        let mut builder = GraphicsPipelineBuilder::opaque(shaders)
+            .vertex_input(vertex_input)
+            .topology(raster.topology.into())
+            .primitive_restart(raster.primitive_restart)
+            .polygon_mode(raster.polygon_mode.into())
+            .cull_mode(raster.cull_mode.into())
+            .front_face(raster.front_face.into())
+            .line_width(raster.line_width)
+            .depth_clamp(raster.depth_clamp)
+            .depth_bias(raster.depth_bias)
+            .sample_shading(raster.sample_shading)
+            .depth(DepthState {
+                test: self.depth.test,
+                write: self.depth.write,
+                compare_op: self.depth.compare_op.into(),
+            })
+            .samples(self.render_pass.samples)
+            .subpass(self.render_pass.subpass);
+
+        if let Some(set) = self.uniform_ring_set {
+            builder = builder.uniform_ring(set);
+        }
+        if let Some(set) = self.bindless_set {
+            builder = builder.bindless(set);
+        }
+
+        let attachments = self.render_pass.colour_attachments as usize;
+        let blend = match self.blend.as_slice() {
+            [single] => vec![*single; attachments],
+            blend if blend.len() == attachments => blend.to_vec(),
+            blend => return Err(Error::msg(format!(
+                "blend: {} blend modes for {attachments} colour attachments, give one for each or a single one for all",
+                blend.len()
+            ))),
+        };
+        for (attachment, mode) in blend.into_iter().enumerate() {
+            builder = builder.attachment_blend(attachment, mode.into());
+        }
+
+        Ok(builder)
+    }
+}
+
+// Pipelines loaded from description files, which are rebuilt when a file changes
+pub struct PipelineFiles {
+    files: Vec<(PathBuf, PipelineHandle)>,
+    events: Receiver<notify::Result<Event>>,
+    watcher: RecommendedWatcher,
+}
+
+impl PipelineFiles {
+    pub fn new() -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+
+        Ok(Self {
+            files: vec![],
+            events,
+            watcher: notify::recommended_watcher(sender)?,
+        })
+    }
+
+    // Watches the directory rather than the file, as editors often save by replacing the file
+    pub fn watch(&mut self, path: &Path, handle: PipelineHandle) -> Result<()> {
+        let path = fs::canonicalize(path)?;
+        if let Some(dir) = path.parent() {
+            self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        self.files.push((path, handle));
+
+        Ok(())
+    }
+
+    // The files changed since the last call, each once however many events it got
+    pub fn poll(&self) -> Vec<(PathBuf, PipelineHandle)> {
+        let mut changed: Vec<(PathBuf, PipelineHandle)> = vec![];
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    for (path, handle) in &self.files {
+                        if event.paths.contains(path) && !changed.iter().any(|(p, _)| p == path) {
+                            changed.push((path.clone(), *handle));
+                        }
+                    }
+                },
+                Ok(_) => {},
+                Err(e) => eprintln!("Pipeline file watcher error: {e}"),
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RON: &str = r#"PipelineDescription(
+        vertex_shader: (shader: "shader.wgsl", entry_point: "vs_main"),
+        fragment_shader: (shader: "shader.wgsl", entry_point: "fs_main"),
+        raster: (cull_mode: Nothing, depth_bias: Some((constant_factor: 1.0, clamp: 0.0, slope_factor: 2.0))),
+        depth: (write: false, compare_op: LessOrEqual),
+        blend: [Custom(src_colour: One, dst_colour: One, src_alpha: Zero, dst_alpha: One, write_mask: "rgb")],
+        render_pass: (samples: 4),
+    )"#;
+
+    const TOML: &str = r#"
+        [vertex_shader]
+        shader = "shader.wgsl"
+        entry_point = "vs_main"
+
+        [fragment_shader]
+        shader = "shader.wgsl"
+        entry_point = "fs_main"
+
+        [raster]
+        cull_mode = "Nothing"
+        depth_bias = { constant_factor = 1.0, clamp = 0.0, slope_factor = 2.0 }
+
+        [depth]
+        write = false
+        compare_op = "LessOrEqual"
+
+        [[blend]]
+        Custom = { src_colour = "One", dst_colour = "One", src_alpha = "Zero", dst_alpha = "One", write_mask = "rgb" }
+
+        [render_pass]
+        samples = 4
+    "#;
+
+    fn parse(text: &str, file: &str) -> Result<PipelineDescription> {
+        PipelineDescription::parse(text, Path::new(file))
+    }
+
+    #[test]
+    fn reads_ron_and_toml_alike() {
+        let ron = parse(RON, "test.ron").unwrap();
+        let toml = parse(TOML, "test.toml").unwrap();
+
+        assert_eq!(ron.builder().unwrap(), toml.builder().unwrap());
+        assert!(matches!(toml.blend[..], [BlendMode::Custom { write_mask, .. }] if write_mask == vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B));
+        assert_eq!(toml.render_pass.samples, Some(vk::SampleCountFlags::TYPE_4));
+    }
+
+    #[test]
+    fn reads_the_example_pipelines() {
+        for path in ["pipelines/wireframe.ron", "pipelines/alpha_blended.toml"] {
+            PipelineDescription::load(Path::new(path)).unwrap().builder().unwrap();
+        }
+    }
+
+    #[test]
+    fn names_the_misspelled_field() {
+        for (text, file) in [(RON, "test.ron"), (TOML, "test.toml")] {
+            let error = parse(&text.replace("compare_op", "compare"), file).unwrap_err();
+            assert!(error.to_string().starts_with("depth.compare: "), "{error}");
+            assert!(error.to_string().contains("expected one of"), "{error}");
+
+            // Two levels down, inside an Option
+            let error = parse(&text.replace("slope_factor", "slope"), file).unwrap_err();
+            assert!(error.to_string().starts_with("raster.depth_bias.slope: "), "{error}");
+        }
+    }
+
+    #[test]
+    fn names_the_invalid_field() {
+        let error = parse(&RON.replace("cull_mode: Nothing", "cull_mode: Bak"), "test.ron").unwrap_err();
+        assert!(error.to_string().starts_with("raster.cull_mode: "), "{error}");
+        assert!(error.to_string().contains("Bak"), "{error}");
+
+        let error = parse(&TOML.replace("samples = 4", "samples = 3"), "test.toml").unwrap_err();
+        assert!(error.to_string().starts_with("render_pass.samples: "), "{error}");
+        assert!(error.to_string().contains("3 samples isn't possible"), "{error}");
+
+        let error = parse(&TOML.replace(r#"write_mask = "rgb""#, r#"write_mask = "rgx""#), "test.toml").unwrap_err();
+        assert!(error.to_string().starts_with("blend[0].Custom.write_mask: "), "{error}");
+        assert!(error.to_string().contains("'x' isn't a colour channel"), "{error}");
+    }
+}
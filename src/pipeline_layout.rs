@@ -0,0 +1,188 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use ash::vk::{self, DescriptorSetLayoutBinding, DescriptorType, PushConstantRange, ShaderStageFlags};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, bindless::{IMAGES_BINDING, SAMPLERS_BINDING, STORAGE_BUFFERS_BINDING, bindless_layout}, descriptors::DescriptorSetLayout, push_constants::{PushConstants, PushConstantsLayout}, reflection::EntryPoint, uniform_ring::uniform_ring_binding};
+
+// A VkPipelineLayout and the descriptor set layouts it was made from, all worked out from shader reflection
+pub struct PipelineLayout {
+    raw: vk::PipelineLayout,
+    set_layouts: Vec<Arc<DescriptorSetLayout>>,
+    push_constants: Option<PushConstantsLayout>,
+    bindless_set: Option<u32>,
+    device: ash::Device
+}
+
+impl PipelineLayout {
+    // Stages sharing a binding must agree on its type and count, their stage flags are combined.
+    // uniform_ring_set is left to UniformRing, with its dynamic uniform buffer in place of whatever the shaders declare.
+    // bindless_set likewise gets BindlessSet's layout. The push constant range is the declared struct's,
+    // which the shaders' block has to match
+    pub fn from_entry_points(logical_device: &LogicalDevice, entry_points: &[&EntryPoint], uniform_ring_set: Option<u32>, bindless_set: Option<u32>, push_constants: Option<&PushConstantsLayout>) -> Result<Self> {
+        let mut sets: BTreeMap<u32, BTreeMap<u32, DescriptorSetLayoutBinding>> = BTreeMap::new();
+        let mut reflected_range: Option<PushConstantRange> = None;
+
+        for entry in entry_points {
+            for binding in &entry.descriptor_bindings {
+                let bindings = sets.entry(binding.set).or_default();
+                match bindings.get_mut(&binding.binding) {
+                    Some(existing) if existing.descriptor_type != binding.descriptor_type || existing.descriptor_count != binding.count => {
+                        return Err(Error::msg(format!(
+                            "Set {} binding {} is {:?} x{} in one stage but {:?} x{} in {:?} entry point '{}' of {}",
+                            binding.set, binding.binding, existing.descriptor_type, existing.descriptor_count,
+                            binding.descriptor_type, binding.count, entry.stage, entry.name, entry.module
+                        )));
+                    },
+                    Some(existing) => existing.stage_flags |= entry.stage,
+                    None => {
+                        bindings.insert(binding.binding, DescriptorSetLayoutBinding {
+                            binding: binding.binding,
+                            descriptor_type: binding.descriptor_type,
+                            descriptor_count: binding.count,
+                            stage_flags: entry.stage,
+                            ..Default::default()
+                        });
+                    },
+                }
+            }
+
+            // One range covering every stage's block, so a single push can update them all
+            if let Some(block) = entry.push_constants {
+                let range = reflected_range.get_or_insert(PushConstantRange {
+                    stage_flags: ShaderStageFlags::empty(),
+                    offset: block.offset,
+                    size: 0,
+                });
+                let end = (range.offset + range.size).max(block.offset + block.size);
+                range.offset = range.offset.min(block.offset);
+                range.size = end - range.offset;
+                range.stage_flags |= entry.stage;
+            }
+        }
+
+        match (push_constants, reflected_range) {
+            (Some(declared), Some(reflected)) => {
+                if reflected.offset + reflected.size != declared.size {
+                    return Err(Error::msg(format!(
+                        "The shaders' push constant block ends at byte {}, but {} is {} bytes",
+                        reflected.offset + reflected.size, declared.type_name(), declared.size
+                    )));
+                }
+                if !declared.stages.contains(reflected.stage_flags) {
+                    return Err(Error::msg(format!(
+                        "{:?} shaders read push constants, but {} is only pushed to {:?}",
+                        reflected.stage_flags, declared.type_name(), declared.stages
+                    )));
+                }
+            },
+            (None, Some(reflected)) => {
+                return Err(Error::msg(format!(
+                    "The shaders read {} bytes of push constants in {:?}, but the pipeline doesn't declare a PushConstants type for them",
+                    reflected.offset + reflected.size, reflected.stage_flags
+                )));
+            },
+            // Not every shader reads its push constants, e.g. while debugging one
+            _ => {},
+        }
+
+        // Reflection can't tell a dynamic uniform buffer from any other
+        if let Some(ring_set) = uniform_ring_set {
+            let bindings = sets.entry(ring_set).or_default();
+            if let Some(binding) = bindings.values().find(|b| b.binding != 0 || b.descriptor_type != DescriptorType::UNIFORM_BUFFER || b.descriptor_count != 1) {
+                return Err(Error::msg(format!(
+                    "Set {ring_set} belongs to the uniform ring, which only has a uniform buffer at binding 0, but the shaders have binding {} as {:?} x{}",
+                    binding.binding, binding.descriptor_type, binding.descriptor_count
+                )));
+            }
+            *bindings = BTreeMap::from([(0, uniform_ring_binding())]);
+        }
+
+        if let Some(bindless_set) = bindless_set {
+            if uniform_ring_set == Some(bindless_set) {
+                return Err(Error::msg(format!("Set {bindless_set} can't be both the uniform ring's and the bindless set")));
+            }
+
+            let expected = [(IMAGES_BINDING, DescriptorType::SAMPLED_IMAGE), (SAMPLERS_BINDING, DescriptorType::SAMPLER), (STORAGE_BUFFERS_BINDING, DescriptorType::STORAGE_BUFFER)];
+            let bindings = sets.entry(bindless_set).or_default();
+            if let Some(binding) = bindings.values().find(|b| !expected.contains(&(b.binding, b.descriptor_type))) {
+                return Err(Error::msg(format!(
+                    "Set {bindless_set} is the bindless set, which has sampled images at binding {IMAGES_BINDING}, samplers at {SAMPLERS_BINDING} \
+                     and storage buffers at {STORAGE_BUFFERS_BINDING}, but the shaders have binding {} as {:?}",
+                    binding.binding, binding.descriptor_type
+                )));
+            }
+        }
+
+        // Sets nobody uses still need a layout so the numbering lines up
+        let set_count = sets.keys().next_back().map_or(0, |last| last + 1);
+        let set_layouts = (0..set_count).map(|set| {
+            if bindless_set == Some(set) {
+                return bindless_layout(logical_device).map(Arc::new);
+            }
+            let bindings: Vec<DescriptorSetLayoutBinding> = sets.get(&set).map(|b| b.values().copied().collect()).unwrap_or_default();
+            DescriptorSetLayout::new(logical_device, &bindings).map(Arc::new)
+        }).collect::<Result<Vec<_>>>()?;
+
+        let mut layout = Self::new(logical_device, set_layouts, push_constants.copied())?;
+        layout.bindless_set = bindless_set;
+
+        Ok(layout)
+    }
+
+    pub fn new(logical_device: &LogicalDevice, set_layouts: Vec<Arc<DescriptorSetLayout>>, push_constants: Option<PushConstantsLayout>) -> Result<Self> {
+        let push_constant_range = push_constants.map(|declared| declared.range());
+        let raw_set_layouts: Vec<vk::DescriptorSetLayout> = set_layouts.iter().map(|layout| *layout.raw()).collect();
+
+        // VkPipelineLayoutCreateInfo
+        let create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&raw_set_layouts)
+            .push_constant_ranges(push_constant_range.as_slice());
+
+        let raw = unsafe { logical_device.raw().create_pipeline_layout(&create_info, None)? };
+
+        Ok(Self {
+            raw,
+            set_layouts,
+            push_constants,
+            bindless_set: None,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn raw(&self) -> &vk::PipelineLayout {
+        &self.raw
+    }
+
+    // What to allocate descriptor sets with for this pipeline. Shared, so sets can outlive a rebuild
+    pub fn set_layout(&self, set: u32) -> Option<&Arc<DescriptorSetLayout>> {
+        self.set_layouts.get(set as usize)
+    }
+
+    pub fn set_layouts(&self) -> &[Arc<DescriptorSetLayout>] {
+        &self.set_layouts
+    }
+
+    // Where to bind BindlessSet::set, if the pipeline uses it
+    pub fn bindless_set(&self) -> Option<u32> {
+        self.bindless_set
+    }
+
+    pub fn push_constants(&self) -> Option<&PushConstantsLayout> {
+        self.push_constants.as_ref()
+    }
+
+    // Panics if T isn't what the pipeline was declared with, as that's a bug rather than bad data
+    pub fn push<T: PushConstants>(&self, command_buffer: vk::CommandBuffer, value: &T) {
+        let declared = self.push_constants.as_ref().expect("Pushing constants to a pipeline without any");
+        assert!(declared.is::<T>(), "Pushing {} to a pipeline declared with {}", std::any::type_name::<T>(), declared.type_name());
+
+        unsafe { self.device.cmd_push_constants(command_buffer, self.raw, declared.stages, 0, bytemuck::bytes_of(value)) };
+    }
+}
+
+impl Drop for PipelineLayout {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_pipeline_layout(self.raw, None) };
+    }
+}
@@ -0,0 +1,108 @@
+use std::any::TypeId;
+
+use ash::vk::{PushConstantRange, ShaderStageFlags};
+use anyhow::{Error, Result};
+use bytemuck::Pod;
+
+// A #[repr(C)] struct pushed as a whole, starting at offset 0, to the stages it names
+pub trait PushConstants: Pod {
+    const STAGES: ShaderStageFlags;
+}
+
+// What a pipeline was declared with, so pushes of any other type can be caught
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PushConstantsLayout {
+    type_id: TypeId,
+    type_name: &'static str,
+    pub size: u32,
+    pub stages: ShaderStageFlags,
+}
+
+impl PushConstantsLayout {
+    pub fn of<T: PushConstants>() -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            size: size_of::<T>() as u32,
+            stages: T::STAGES,
+        }
+    }
+
+    pub fn is<T: PushConstants>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    // VkPushConstantRange's size has to be a non-zero multiple of 4, and within maxPushConstantsSize
+    pub fn check(&self, max_size: u32) -> Result<()> {
+        if self.size == 0 || !self.size.is_multiple_of(4) {
+            return Err(Error::msg(format!(
+                "{} is {} bytes, push constants have to be a non-zero multiple of 4 bytes. Pad the struct out",
+                self.type_name, self.size
+            )));
+        }
+        if self.size > max_size {
+            return Err(Error::msg(format!(
+                "{} is {} bytes, more than the device's maxPushConstantsSize of {max_size}",
+                self.type_name, self.size
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn range(&self) -> PushConstantRange {
+        PushConstantRange {
+            stage_flags: self.stages,
+            offset: 0,
+            size: self.size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Mvp {
+        matrix: [f32; 16],
+    }
+
+    impl PushConstants for Mvp {
+        const STAGES: ShaderStageFlags = ShaderStageFlags::VERTEX;
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Flags {
+        bits: [u8; 6],
+    }
+
+    impl PushConstants for Flags {
+        const STAGES: ShaderStageFlags = ShaderStageFlags::FRAGMENT;
+    }
+
+    #[test]
+    fn accepts_aligned_constants_within_the_limit() {
+        PushConstantsLayout::of::<Mvp>().check(128).unwrap();
+    }
+
+    #[test]
+    fn rejects_sizes_that_are_not_a_multiple_of_4() {
+        let error = PushConstantsLayout::of::<Flags>().check(128).unwrap_err();
+        assert!(error.to_string().contains("multiple of 4"), "{error}");
+    }
+
+    #[test]
+    fn rejects_constants_over_the_limit() {
+        let error = PushConstantsLayout::of::<Mvp>().check(32).unwrap_err();
+        assert!(error.to_string().contains("maxPushConstantsSize of 32"), "{error}");
+    }
+}
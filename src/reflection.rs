@@ -0,0 +1,770 @@
+use std::collections::{HashMap, HashSet};
+
+use ash::vk::{DescriptorType, ShaderStageFlags};
+use anyhow::{Error, Result};
+
+use crate::spirv::SpirvBinary;
+
+// Opcodes and enums from the SPIR-V spec, only the ones reflection looks at
+const OP_NAME: u32 = 5;
+const OP_EXT_INST: u32 = 12;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_VOID: u32 = 19;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_FUNCTION: u32 = 54;
+const OP_FUNCTION_END: u32 = 56;
+const OP_FUNCTION_CALL: u32 = 57;
+const OP_VARIABLE: u32 = 59;
+const OP_IMAGE_TEXEL_POINTER: u32 = 60;
+const OP_LOAD: u32 = 61;
+const OP_STORE: u32 = 62;
+const OP_COPY_MEMORY: u32 = 63;
+const OP_COPY_MEMORY_SIZED: u32 = 64;
+const OP_ACCESS_CHAIN: u32 = 65;
+const OP_IN_BOUNDS_ACCESS_CHAIN: u32 = 66;
+const OP_PTR_ACCESS_CHAIN: u32 = 67;
+const OP_ARRAY_LENGTH: u32 = 68;
+const OP_IN_BOUNDS_PTR_ACCESS_CHAIN: u32 = 70;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_COPY_OBJECT: u32 = 83;
+const OP_SELECT: u32 = 169;
+const OP_ATOMIC_LOAD: u32 = 227;
+const OP_ATOMIC_STORE: u32 = 228;
+const OP_ATOMIC_XOR: u32 = 242;
+const OP_PHI: u32 = 245;
+const OP_ATOMIC_FLAG_TEST_AND_SET: u32 = 318;
+const OP_ATOMIC_FLAG_CLEAR: u32 = 319;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScalarType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+}
+
+// The type of a stage input or output, enough to tell whether two stages agree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IoType {
+    Scalar(ScalarType),
+    Vector(ScalarType, u32),
+    Matrix { scalar: ScalarType, columns: u32, rows: u32 },
+    Array(Box<IoType>, u32),
+    Struct(Vec<IoType>),
+}
+
+#[derive(Clone, Debug)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub name: Option<String>,
+    pub ty: IoType,
+}
+
+#[derive(Clone, Debug)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: DescriptorType,
+    // 0 for a runtime sized array
+    pub count: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushConstantBlock {
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpecConstant {
+    pub id: u32,
+    pub name: Option<String>,
+    pub ty: ScalarType,
+    // The raw bits of the default value, 1 or 0 for bools
+    pub default: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: ShaderStageFlags,
+    // The file or shader name the module came from, for error messages
+    pub module: String,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub push_constants: Option<PushConstantBlock>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub module: String,
+    pub entry_points: Vec<EntryPoint>,
+    pub spec_constants: Vec<SpecConstant>,
+}
+
+impl ShaderReflection {
+    pub fn reflect(spirv: &SpirvBinary) -> Result<Self> {
+        Module::parse(spirv.words())
+            .and_then(|module| module.reflect(spirv.name()))
+            .map_err(|e| Error::msg(format!("{}: {e}", spirv.name())))
+    }
+
+    // Looks the name up in the module's OpEntryPoints, so a typo fails here rather than in the driver
+    pub fn entry_point(&self, name: &str, stage: ShaderStageFlags) -> Result<&EntryPoint> {
+        self.entry_points.iter()
+            .find(|entry| entry.name == name && entry.stage == stage)
+            .ok_or_else(|| {
+                let available: Vec<String> = self.entry_points.iter().map(|entry| format!("'{}' ({:?})", entry.name, entry.stage)).collect();
+                Error::msg(format!(
+                    "{} has no {stage:?} entry point called '{name}', it has {}",
+                    self.module,
+                    if available.is_empty() { "none".to_string() } else { available.join(", ") }
+                ))
+            })
+    }
+}
+
+impl EntryPoint {
+    fn describe(&self) -> String {
+        format!("{:?} entry point '{}' in {}", self.stage, self.name, self.module)
+    }
+}
+
+// Every input the next stage reads has to be written by the previous one with the same type.
+// Outputs nobody reads are allowed
+pub fn check_interface(producer: &EntryPoint, consumer: &EntryPoint) -> Result<()> {
+    for input in &consumer.inputs {
+        let name = input.name.as_deref().unwrap_or("unnamed");
+        let output = producer.outputs.iter().find(|output| output.location == input.location).ok_or_else(|| Error::msg(format!(
+            "{} reads location {} ({name}), which {} never writes",
+            consumer.describe(), input.location, producer.describe()
+        )))?;
+
+        if output.ty != input.ty {
+            return Err(Error::msg(format!(
+                "{} reads location {} ({name}) as {:?}, but {} writes it as {:?}",
+                consumer.describe(), input.location, input.ty, producer.describe(), output.ty
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Void,
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Decorations {
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    spec_id: Option<u32>,
+    array_stride: Option<u32>,
+    built_in: bool,
+    buffer_block: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+    built_in: bool,
+}
+
+#[derive(Debug)]
+struct RawEntryPoint {
+    stage: ShaderStageFlags,
+    function: u32,
+    name: String,
+    interface: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct Variable {
+    pointer_type: u32,
+    storage: u32,
+}
+
+// The parts of the module reflection needs, indexed by result id
+#[derive(Debug, Default)]
+struct Module {
+    // From SPIR-V 1.4 an entry point's interface lists every global it uses, not just its inputs and outputs
+    interface_lists_globals: bool,
+    entry_points: Vec<RawEntryPoint>,
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, Vec<u32>>,
+    spec_constants: Vec<(u32, u32, u64)>,
+    variables: HashMap<u32, Variable>,
+    // Globals each function touches directly, and the functions it calls
+    function_globals: HashMap<u32, HashSet<u32>>,
+    function_calls: HashMap<u32, HashSet<u32>>,
+}
+
+fn read_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = vec![];
+    for (i, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), i + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+// The operands of an instruction inside a function that can be a pointer to a global, so literals
+// that happen to match a variable's id aren't taken for a use of it
+fn pointer_operands(opcode: u32, operands: &[u32]) -> &[u32] {
+    let range = match opcode {
+        OP_LOAD | OP_ACCESS_CHAIN | OP_IN_BOUNDS_ACCESS_CHAIN | OP_PTR_ACCESS_CHAIN | OP_IN_BOUNDS_PTR_ACCESS_CHAIN
+        | OP_ARRAY_LENGTH | OP_IMAGE_TEXEL_POINTER | OP_COPY_OBJECT | OP_ATOMIC_FLAG_TEST_AND_SET => 2..3,
+        OP_ATOMIC_LOAD..=OP_ATOMIC_XOR if opcode != OP_ATOMIC_STORE => 2..3,
+        OP_STORE | OP_ATOMIC_STORE | OP_ATOMIC_FLAG_CLEAR => 0..1,
+        OP_COPY_MEMORY | OP_COPY_MEMORY_SIZED => 0..2,
+        OP_SELECT => 3..5,
+        // Arguments, e.g. a global passed by reference
+        OP_FUNCTION_CALL => 3..operands.len(),
+        // Operands, e.g. interpolateAtCentroid's input
+        OP_EXT_INST => 4..operands.len(),
+        // Pairs of value and parent block, the blocks can't be globals
+        OP_PHI => 2..operands.len(),
+        _ => return &[],
+    };
+
+    operands.get(range).unwrap_or(&[])
+}
+
+fn stage(execution_model: u32) -> Result<ShaderStageFlags> {
+    match execution_model {
+        0 => Ok(ShaderStageFlags::VERTEX),
+        1 => Ok(ShaderStageFlags::TESSELLATION_CONTROL),
+        2 => Ok(ShaderStageFlags::TESSELLATION_EVALUATION),
+        3 => Ok(ShaderStageFlags::GEOMETRY),
+        4 => Ok(ShaderStageFlags::FRAGMENT),
+        5 => Ok(ShaderStageFlags::COMPUTE),
+        5364 => Ok(ShaderStageFlags::TASK_EXT),
+        5365 => Ok(ShaderStageFlags::MESH_EXT),
+        other => Err(Error::msg(format!("unsupported execution model {other}"))),
+    }
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self> {
+        let mut module = Module {
+            interface_lists_globals: words.get(1).is_some_and(|&version| version >= 0x0001_0400),
+            ..Default::default()
+        };
+        let mut current_function = None;
+        // Variables are declared before any function, so by the time a body is read they are all known
+        let mut offset = 5;
+
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err(Error::msg(format!("instruction at word {offset} runs past the end of the module")));
+            }
+
+            let operands = &words[offset + 1..offset + word_count];
+            let missing = || Error::msg(format!("opcode {opcode} at word {offset} is missing operands"));
+            let operand = |i: usize| operands.get(i).copied().ok_or_else(missing);
+            // Operands from i on, which may be none
+            let rest = |i: usize| operands.get(i..).ok_or_else(missing);
+
+            match opcode {
+                OP_NAME => {
+                    module.names.insert(operand(0)?, read_string(rest(1)?).0);
+                },
+                OP_ENTRY_POINT => {
+                    let (name, name_words) = read_string(rest(2)?);
+                    module.entry_points.push(RawEntryPoint {
+                        stage: stage(operand(0)?)?,
+                        function: operand(1)?,
+                        name,
+                        interface: rest(2 + name_words)?.to_vec(),
+                    });
+                },
+                OP_TYPE_VOID => { module.types.insert(operand(0)?, Type::Void); },
+                OP_TYPE_BOOL => { module.types.insert(operand(0)?, Type::Bool); },
+                OP_TYPE_INT => { module.types.insert(operand(0)?, Type::Int { width: operand(1)?, signed: operand(2)? != 0 }); },
+                OP_TYPE_FLOAT => { module.types.insert(operand(0)?, Type::Float { width: operand(1)? }); },
+                OP_TYPE_VECTOR => { module.types.insert(operand(0)?, Type::Vector { component: operand(1)?, count: operand(2)? }); },
+                OP_TYPE_MATRIX => { module.types.insert(operand(0)?, Type::Matrix { column: operand(1)?, count: operand(2)? }); },
+                OP_TYPE_IMAGE => { module.types.insert(operand(0)?, Type::Image { dim: operand(2)?, sampled: operand(6)? }); },
+                OP_TYPE_SAMPLER => { module.types.insert(operand(0)?, Type::Sampler); },
+                OP_TYPE_SAMPLED_IMAGE => { module.types.insert(operand(0)?, Type::SampledImage); },
+                OP_TYPE_ARRAY => { module.types.insert(operand(0)?, Type::Array { element: operand(1)?, length: operand(2)? }); },
+                OP_TYPE_RUNTIME_ARRAY => { module.types.insert(operand(0)?, Type::RuntimeArray { element: operand(1)? }); },
+                OP_TYPE_STRUCT => { module.types.insert(operand(0)?, Type::Struct { members: rest(1)?.to_vec() }); },
+                OP_TYPE_POINTER => { module.types.insert(operand(0)?, Type::Pointer { pointee: operand(2)? }); },
+                OP_TYPE_ACCELERATION_STRUCTURE => { module.types.insert(operand(0)?, Type::AccelerationStructure); },
+                OP_CONSTANT => { module.constants.insert(operand(1)?, rest(2)?.to_vec()); },
+                OP_SPEC_CONSTANT_TRUE => module.spec_constants.push((operand(1)?, operand(0)?, 1)),
+                OP_SPEC_CONSTANT_FALSE => module.spec_constants.push((operand(1)?, operand(0)?, 0)),
+                OP_SPEC_CONSTANT => {
+                    let low = operand(2)? as u64;
+                    let high = operands.get(3).copied().unwrap_or(0) as u64;
+                    module.spec_constants.push((operand(1)?, operand(0)?, low | high << 32));
+                    // Array lengths can be spec constants, their default is what the layout uses
+                    module.constants.insert(operand(1)?, rest(2)?.to_vec());
+                },
+                OP_VARIABLE if current_function.is_none() => {
+                    module.variables.insert(operand(1)?, Variable { pointer_type: operand(0)?, storage: operand(2)? });
+                },
+                OP_DECORATE => {
+                    let decorations = module.decorations.entry(operand(0)?).or_default();
+                    match operand(1)? {
+                        DECORATION_SPEC_ID => decorations.spec_id = Some(operand(2)?),
+                        DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                        DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_LOCATION => decorations.location = Some(operand(2)?),
+                        DECORATION_BINDING => decorations.binding = Some(operand(2)?),
+                        DECORATION_DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                        _ => {},
+                    }
+                },
+                OP_MEMBER_DECORATE => {
+                    let decorations = module.member_decorations.entry((operand(0)?, operand(1)?)).or_default();
+                    match operand(2)? {
+                        DECORATION_OFFSET => decorations.offset = Some(operand(3)?),
+                        DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        _ => {},
+                    }
+                },
+                OP_FUNCTION => current_function = Some(operand(1)?),
+                OP_FUNCTION_END => current_function = None,
+                _ => {},
+            }
+
+            if let Some(function) = current_function {
+                if opcode == OP_FUNCTION_CALL {
+                    module.function_calls.entry(function).or_default().insert(operand(2)?);
+                }
+
+                let globals: Vec<u32> = pointer_operands(opcode, operands).iter().copied()
+                    .filter(|id| module.variables.contains_key(id))
+                    .collect();
+                module.function_globals.entry(function).or_default().extend(globals);
+            }
+
+            offset += word_count;
+        }
+
+        Ok(module)
+    }
+
+    fn reflect(&self, module_name: &str) -> Result<ShaderReflection> {
+        let entry_points = self.entry_points.iter()
+            .map(|entry| self.reflect_entry_point(entry, module_name)
+                .map_err(|e| Error::msg(format!("entry point '{}': {e}", entry.name))))
+            .collect::<Result<_>>()?;
+
+        let mut spec_constants = vec![];
+        for &(id, ty, default) in &self.spec_constants {
+            // Constants derived from others with OpSpecConstantOp have no SpecId and can't be set directly
+            let Some(spec_id) = self.decorations.get(&id).and_then(|d| d.spec_id) else { continue };
+            spec_constants.push(SpecConstant {
+                id: spec_id,
+                name: self.names.get(&id).cloned(),
+                ty: self.scalar(ty)?,
+                default,
+            });
+        }
+        spec_constants.sort_by_key(|constant| constant.id);
+
+        Ok(ShaderReflection { module: module_name.to_string(), entry_points, spec_constants })
+    }
+
+    fn reflect_entry_point(&self, entry: &RawEntryPoint, module_name: &str) -> Result<EntryPoint> {
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+
+        for id in &entry.interface {
+            let Some(variable) = self.variables.get(id) else { continue };
+            let decorations = self.decorations.get(id).copied().unwrap_or_default();
+            let pointee = self.pointee(variable.pointer_type)?;
+
+            // gl_Position and friends aren't matched by location
+            if decorations.built_in || self.is_built_in_block(pointee) {
+                continue;
+            }
+
+            let list = match variable.storage {
+                STORAGE_INPUT => &mut inputs,
+                STORAGE_OUTPUT => &mut outputs,
+                _ => continue,
+            };
+
+            let name = self.names.get(id).cloned();
+            let location = decorations.location.ok_or_else(|| Error::msg(format!(
+                "{} has no location", name.as_deref().unwrap_or("an unnamed input or output")
+            )))?;
+
+            list.push(InterfaceVariable { location, name, ty: self.io_type(pointee)? });
+        }
+
+        inputs.sort_by_key(|variable| variable.location);
+        outputs.sort_by_key(|variable| variable.location);
+
+        let mut descriptor_bindings = vec![];
+        let mut push_constants = None;
+
+        let mut used: Vec<u32> = if self.interface_lists_globals {
+            entry.interface.iter().copied().filter(|id| self.variables.contains_key(id)).collect()
+        } else {
+            self.globals_used_by(entry.function).into_iter().collect()
+        };
+        used.sort();
+
+        for id in used {
+            let variable = &self.variables[&id];
+            let pointee = self.pointee(variable.pointer_type)?;
+
+            match variable.storage {
+                STORAGE_PUSH_CONSTANT => push_constants = Some(self.push_constant_block(pointee)?),
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let decorations = self.decorations.get(&id).copied().unwrap_or_default();
+                    let name = self.names.get(&id).cloned();
+                    let (binding, set) = decorations.binding.zip(decorations.set).ok_or_else(|| Error::msg(format!(
+                        "resource {} is missing a set or binding", name.as_deref().unwrap_or("unnamed")
+                    )))?;
+
+                    let (element, count) = self.unwrap_array(pointee)?;
+                    descriptor_bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        descriptor_type: self.descriptor_type(variable.storage, element)?,
+                        count,
+                        name,
+                    });
+                },
+                _ => {},
+            }
+        }
+
+        descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        Ok(EntryPoint {
+            name: entry.name.clone(),
+            stage: entry.stage,
+            module: module_name.to_string(),
+            inputs,
+            outputs,
+            descriptor_bindings,
+            push_constants,
+        })
+    }
+
+    // Before SPIR-V 1.4 the entry point's call tree has to be walked to find the globals it uses
+    fn globals_used_by(&self, function: u32) -> HashSet<u32> {
+        let mut used = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![function];
+
+        while let Some(function) = pending.pop() {
+            if !visited.insert(function) {
+                continue;
+            }
+            if let Some(globals) = self.function_globals.get(&function) {
+                used.extend(globals);
+            }
+            if let Some(calls) = self.function_calls.get(&function) {
+                pending.extend(calls);
+            }
+        }
+
+        used
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type> {
+        self.types.get(&id).ok_or_else(|| Error::msg(format!("type %{id} is not declared")))
+    }
+
+    fn pointee(&self, pointer: u32) -> Result<u32> {
+        match self.ty(pointer)? {
+            Type::Pointer { pointee } => Ok(*pointee),
+            other => Err(Error::msg(format!("expected a pointer type, found {other:?}"))),
+        }
+    }
+
+    fn constant(&self, id: u32) -> Result<u32> {
+        self.constants.get(&id).and_then(|words| words.first().copied())
+            .ok_or_else(|| Error::msg(format!("array length %{id} is not a constant")))
+    }
+
+    fn scalar(&self, id: u32) -> Result<ScalarType> {
+        match *self.ty(id)? {
+            Type::Bool => Ok(ScalarType::Bool),
+            Type::Int { width, signed } => Ok(ScalarType::Int { width, signed }),
+            Type::Float { width } => Ok(ScalarType::Float { width }),
+            ref other => Err(Error::msg(format!("expected a scalar type, found {other:?}"))),
+        }
+    }
+
+    fn is_built_in_block(&self, id: u32) -> bool {
+        let id = match self.types.get(&id) {
+            Some(Type::Array { element, .. }) => *element,
+            _ => id,
+        };
+
+        self.member_decorations.iter().any(|(&(ty, _), decorations)| ty == id && decorations.built_in)
+    }
+
+    fn io_type(&self, id: u32) -> Result<IoType> {
+        Ok(match self.ty(id)? {
+            Type::Bool | Type::Int { .. } | Type::Float { .. } => IoType::Scalar(self.scalar(id)?),
+            Type::Vector { component, count } => IoType::Vector(self.scalar(*component)?, *count),
+            Type::Matrix { column, count } => match self.ty(*column)? {
+                Type::Vector { component, count: rows } => IoType::Matrix { scalar: self.scalar(*component)?, columns: *count, rows: *rows },
+                other => return Err(Error::msg(format!("matrix column is {other:?}, expected a vector"))),
+            },
+            Type::Array { element, length } => IoType::Array(Box::new(self.io_type(*element)?), self.constant(*length)?),
+            Type::Struct { members } => IoType::Struct(members.iter().map(|&member| self.io_type(member)).collect::<Result<_>>()?),
+            other => return Err(Error::msg(format!("{other:?} cannot be a stage input or output"))),
+        })
+    }
+
+    // Descriptor arrays become a count on the binding
+    fn unwrap_array(&self, id: u32) -> Result<(u32, u32)> {
+        match *self.ty(id)? {
+            Type::Array { element, length } => Ok((element, self.constant(length)?)),
+            Type::RuntimeArray { element } => Ok((element, 0)),
+            _ => Ok((id, 1)),
+        }
+    }
+
+    fn descriptor_type(&self, storage: u32, id: u32) -> Result<DescriptorType> {
+        let decorations = self.decorations.get(&id).copied().unwrap_or_default();
+
+        Ok(match (storage, self.ty(id)?) {
+            (STORAGE_UNIFORM_CONSTANT, Type::Sampler) => DescriptorType::SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::SampledImage) => DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { dim: DIM_BUFFER, sampled: 2 }) => DescriptorType::STORAGE_TEXEL_BUFFER,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { dim: DIM_BUFFER, .. }) => DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { dim: DIM_SUBPASS_DATA, .. }) => DescriptorType::INPUT_ATTACHMENT,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { sampled: 2, .. }) => DescriptorType::STORAGE_IMAGE,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { .. }) => DescriptorType::SAMPLED_IMAGE,
+            (STORAGE_UNIFORM_CONSTANT, Type::AccelerationStructure) => DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            // Before SPIR-V 1.3 storage buffers were Uniform blocks decorated BufferBlock
+            (STORAGE_UNIFORM, Type::Struct { .. }) if decorations.buffer_block => DescriptorType::STORAGE_BUFFER,
+            (STORAGE_UNIFORM, Type::Struct { .. }) => DescriptorType::UNIFORM_BUFFER,
+            (STORAGE_STORAGE_BUFFER, Type::Struct { .. }) => DescriptorType::STORAGE_BUFFER,
+            (storage, other) => return Err(Error::msg(format!("no descriptor type for {other:?} in storage class {storage}"))),
+        })
+    }
+
+    fn push_constant_block(&self, id: u32) -> Result<PushConstantBlock> {
+        let Type::Struct { members } = self.ty(id)? else {
+            return Err(Error::msg("push constants must be a block"));
+        };
+
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for index in 0..members.len() as u32 {
+            let offset = self.member_offset(id, index)?;
+            start = start.min(offset);
+            end = end.max(overflow(offset.checked_add(self.member_size(id, index)?), id)?);
+        }
+
+        if members.is_empty() {
+            return Ok(PushConstantBlock { offset: 0, size: 0 });
+        }
+
+        Ok(PushConstantBlock { offset: start, size: end - start })
+    }
+
+    fn member_offset(&self, structure: u32, index: u32) -> Result<u32> {
+        self.member_decorations.get(&(structure, index)).and_then(|d| d.offset)
+            .ok_or_else(|| Error::msg(format!("member {index} of block %{structure} has no offset")))
+    }
+
+    // Matrices are only laid out explicitly through the member that holds them
+    fn member_size(&self, structure: u32, index: u32) -> Result<u32> {
+        let member = match self.ty(structure)? {
+            Type::Struct { members } => members.get(index as usize).copied(),
+            _ => None,
+        };
+        let member = member.ok_or_else(|| Error::msg(format!("%{structure} has no member {index}")))?;
+        let stride = self.member_decorations.get(&(structure, index)).and_then(|d| d.matrix_stride);
+
+        match (self.ty(member)?, stride) {
+            (Type::Matrix { count, .. }, Some(stride)) => overflow(count.checked_mul(stride), structure),
+            _ => self.size(member),
+        }
+    }
+
+    fn size(&self, id: u32) -> Result<u32> {
+        Ok(match self.ty(id)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => overflow(count.checked_mul(self.size(*component)?), id)?,
+            Type::Matrix { column, count } => overflow(count.checked_mul(self.size(*column)?), id)?,
+            Type::Array { element, length } => {
+                let stride = match self.decorations.get(&id).and_then(|d| d.array_stride) {
+                    Some(stride) => stride,
+                    None => self.size(*element)?,
+                };
+                overflow(self.constant(*length)?.checked_mul(stride), id)?
+            },
+            Type::RuntimeArray { .. } => 0,
+            Type::Struct { members } => {
+                let mut end = 0;
+                for index in 0..members.len() as u32 {
+                    end = end.max(overflow(self.member_offset(id, index)?.checked_add(self.member_size(id, index)?), id)?);
+                }
+                end
+            },
+            other => return Err(Error::msg(format!("{other:?} has no size in a block"))),
+        })
+    }
+}
+
+// Sizes come from the module, so a hostile or corrupt one can ask for more than a u32 holds
+fn overflow(size: Option<u32>, id: u32) -> Result<u32> {
+    size.ok_or_else(|| Error::msg(format!("the size of %{id} doesn't fit in 32 bits")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shaders::embedded;
+
+    fn reflect_embedded(name: &str) -> ShaderReflection {
+        let bytes = embedded(name).expect("the shader is embedded");
+        ShaderReflection::reflect(&SpirvBinary::from_bytes(bytes, name).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn finds_globals_used_through_access_chains() {
+        let reflection = reflect_embedded("tests/specialised.comp");
+        let entry = reflection.entry_point("main", ShaderStageFlags::COMPUTE).unwrap();
+
+        assert_eq!(entry.descriptor_bindings.len(), 1);
+        let binding = &entry.descriptor_bindings[0];
+        assert_eq!((binding.set, binding.binding, binding.descriptor_type), (0, 0, DescriptorType::STORAGE_BUFFER));
+        assert_eq!(binding.count, 1);
+    }
+
+    #[test]
+    fn finds_images_and_push_constants() {
+        let reflection = reflect_embedded("downsample.comp");
+        let entry = reflection.entry_point("main", ShaderStageFlags::COMPUTE).unwrap();
+
+        let bindings: Vec<_> = entry.descriptor_bindings.iter().map(|b| (b.binding, b.descriptor_type)).collect();
+        assert_eq!(bindings, [(0, DescriptorType::STORAGE_IMAGE), (1, DescriptorType::STORAGE_IMAGE)]);
+        assert!(entry.push_constants.is_some());
+    }
+
+    #[test]
+    fn ignores_literals_that_look_like_globals() {
+        let variable = 7;
+
+        // OpLoad %type %result %pointer reads the pointer
+        assert_eq!(pointer_operands(OP_LOAD, &[1, 2, variable]), [variable]);
+        // OpStore %pointer %value writes it
+        assert_eq!(pointer_operands(OP_STORE, &[variable, 3]), [variable]);
+        // An OpConstant's value, or a decoration's literal, is never a use
+        assert!(pointer_operands(OP_CONSTANT, &[1, 2, variable]).is_empty());
+        assert!(pointer_operands(OP_DECORATE, &[2, DECORATION_BINDING, variable]).is_empty());
+        // Truncated instructions are left for the rest of parsing to complain about
+        assert!(pointer_operands(OP_LOAD, &[1, 2]).is_empty());
+    }
+
+    // A module header followed by the given instructions, each an opcode and its operands
+    fn module(instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        let mut words = vec![0x0723_0203, 0x0001_0000, 0, 100, 0];
+        for (opcode, operands) in instructions {
+            words.push(((operands.len() as u32 + 1) << 16) | opcode);
+            words.extend_from_slice(operands);
+        }
+        words
+    }
+
+    #[test]
+    fn rejects_truncated_instructions() {
+        for instruction in [(OP_ENTRY_POINT, &[5][..]), (OP_TYPE_STRUCT, &[]), (OP_CONSTANT, &[1]), (OP_SPEC_CONSTANT, &[1, 2]), (OP_NAME, &[])] {
+            let error = Module::parse(&module(&[instruction])).unwrap_err();
+            assert!(error.to_string().contains("is missing operands"), "{error}");
+        }
+
+        // Ends partway through its last instruction
+        let mut words = module(&[(OP_TYPE_FLOAT, &[1, 32])]);
+        words.pop();
+        let error = Module::parse(&words).unwrap_err();
+        assert!(error.to_string().contains("runs past the end of the module"), "{error}");
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        let module = Module::parse(&module(&[
+            (OP_TYPE_INT, &[1, 32, 0]),
+            (OP_TYPE_FLOAT, &[2, 32]),
+            (OP_CONSTANT, &[1, 3, u32::MAX]),
+            (OP_TYPE_ARRAY, &[4, 2, 3]),
+        ])).unwrap();
+
+        let error = module.size(4).unwrap_err();
+        assert!(error.to_string().contains("the size of %4 doesn't fit in 32 bits"), "{error}");
+    }
+}
@@ -1,61 +1,381 @@
-use crate::{LogicalDevice, shader_module::ShaderModule, swap_chain::SwapChain, utils::read_file};
-use anyhow::Result;
-use ash::vk::{AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp, CullModeFlags, FrontFace, ImageLayout, Offset2D, PipelineBindPoint, PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo, PipelineInputAssemblyStateCreateInfo, PipelineLayout, PipelineLayoutCreateInfo, PipelineMultisampleStateCreateInfo, PipelineRasterizationStateCreateInfo, PipelineViewportStateCreateInfo, PolygonMode, PrimitiveTopology, RenderPassCreateInfo, SampleCountFlags, ShaderStageFlags, SubpassDescription, Viewport};
+use crate::{LogicalDevice, depth_buffer};
+use anyhow::{Error, Result};
+use ash::vk::{self, AccessFlags, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp, Format, ImageAspectFlags, ImageLayout, PipelineBindPoint, PipelineStageFlags, RenderPassCreateInfo, SUBPASS_EXTERNAL, SampleCountFlags, SubpassDependency};
 
-
-pub struct RenderPass {
-    raw: ash::vk::RenderPass,
-    device: ash::Device
+// An attachment of the render pass, referred to by its index in RenderPassBuilder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub format: Format,
+    pub samples: SampleCountFlags,
+    pub load_op: AttachmentLoadOp,
+    pub store_op: AttachmentStoreOp,
+    pub stencil_load_op: AttachmentLoadOp,
+    pub stencil_store_op: AttachmentStoreOp,
+    pub initial_layout: ImageLayout,
+    pub final_layout: ImageLayout,
 }
 
-impl RenderPass {
-    pub fn new(logical_device: &LogicalDevice, swap_chain: &SwapChain) -> Result<Self> {
-
-        // VkAttachmentDescription 
-        let colour_attachment_description = AttachmentDescription {
-            format: *swap_chain.image_format(),
-            samples: SampleCountFlags::TYPE_1,
+impl Attachment {
+    // Cleared and kept, e.g. for a later pass to sample
+    pub fn colour(format: Format, samples: SampleCountFlags) -> Self {
+        Self {
+            format,
+            samples,
             load_op: AttachmentLoadOp::CLEAR,
             store_op: AttachmentStoreOp::STORE,
             stencil_load_op: AttachmentLoadOp::DONT_CARE,
             stencil_store_op: AttachmentStoreOp::DONT_CARE,
             initial_layout: ImageLayout::UNDEFINED,
+            final_layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }
+    }
+
+    // Cleared each frame and never stored, stencil included
+    pub fn depth(format: Format, samples: SampleCountFlags) -> Self {
+        Self {
+            format,
+            samples,
+            load_op: AttachmentLoadOp::CLEAR,
+            store_op: AttachmentStoreOp::DONT_CARE,
+            stencil_load_op: if depth_buffer::has_stencil(format) { AttachmentLoadOp::CLEAR } else { AttachmentLoadOp::DONT_CARE },
+            stencil_store_op: AttachmentStoreOp::DONT_CARE,
+            initial_layout: ImageLayout::UNDEFINED,
+            final_layout: ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        }
+    }
+
+    // Multisampled colour that's only needed until it's resolved
+    pub fn transient_colour(format: Format, samples: SampleCountFlags) -> Self {
+        Self {
+            store_op: AttachmentStoreOp::DONT_CARE,
+            ..Self::colour(format, samples)
+        }
+    }
+
+    // A swap chain image, drawn to directly
+    pub fn present(format: Format) -> Self {
+        Self {
             final_layout: ImageLayout::PRESENT_SRC_KHR,
-            ..Default::default()
-        };
+            ..Self::colour(format, SampleCountFlags::TYPE_1)
+        }
+    }
 
-        // VkAttachmentReference 
-        let colour_attachment_ref = AttachmentReference {
-            attachment: 0,
-            layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            ..Default::default()
-        };
+    // A swap chain image, written only by the resolve at the end of the subpass
+    pub fn present_resolve(format: Format) -> Self {
+        Self {
+            load_op: AttachmentLoadOp::DONT_CARE,
+            ..Self::present(format)
+        }
+    }
+
+    fn is_depth(&self) -> bool {
+        depth_buffer::aspect_flags(self.format) != ImageAspectFlags::COLOR
+    }
+}
 
-        let subpass_description = SubpassDescription {
-            pipeline_bind_point: PipelineBindPoint::GRAPHICS,
-            color_attachment_count: 1,
-            p_color_attachments: &colour_attachment_ref,
+// Attachment indices used by one subpass. Resolve attachments pair up with the colour ones,
+// so there are either none or as many
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subpass {
+    colour: Vec<u32>,
+    resolve: Vec<u32>,
+    input: Vec<u32>,
+    depth: Option<u32>,
+    preserve: Vec<u32>,
+}
+
+impl Subpass {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Becomes location N in the fragment shader, N counting the colour attachments added before it
+    pub fn colour(mut self, attachment: u32) -> Self {
+        self.colour.push(attachment);
+        self
+    }
+
+    pub fn resolve(mut self, attachment: u32) -> Self {
+        self.resolve.push(attachment);
+        self
+    }
+
+    // Read by the fragment shader at the same pixel, after an earlier subpass wrote it
+    pub fn input(mut self, attachment: u32) -> Self {
+        self.input.push(attachment);
+        self
+    }
+
+    pub fn depth(mut self, attachment: u32) -> Self {
+        self.depth = Some(attachment);
+        self
+    }
+
+    // Not used by this subpass, but kept for a later one
+    pub fn preserve(mut self, attachment: u32) -> Self {
+        self.preserve.push(attachment);
+        self
+    }
+}
+
+// What a pipeline built for a subpass has to match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubpassInfo {
+    pub colour_attachments: u32,
+    pub samples: SampleCountFlags,
+    pub depth_format: Option<Format>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderPassBuilder {
+    attachments: Vec<Attachment>,
+    subpasses: Vec<Subpass>,
+    dependencies: Vec<SubpassDependency>,
+}
+
+impl RenderPassBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // One subpass drawing to a swap chain image, with a depth attachment when given its format.
+    // With more than one sample, attachment 0 is a multisampled colour target resolved into the swap chain
+    // image, which comes after the depth attachment, and depth has as many samples
+    pub fn swap_chain(image_format: Format, depth_format: Option<Format>, samples: SampleCountFlags) -> Self {
+        let multisampled = samples != SampleCountFlags::TYPE_1;
+        let mut builder = Self::new();
+        let mut subpass = Subpass::new().colour(0);
+
+        builder = builder.attachment(if multisampled { Attachment::transient_colour(image_format, samples) } else { Attachment::present(image_format) });
+        if let Some(format) = depth_format {
+            subpass = subpass.depth(builder.attachments.len() as u32);
+            builder = builder.attachment(Attachment::depth(format, samples));
+        }
+        if multisampled {
+            subpass = subpass.resolve(builder.attachments.len() as u32);
+            builder = builder.attachment(Attachment::present_resolve(image_format));
+        }
+
+        // The swap chain image's layout transition happens at the start of the subpass, which has to wait for
+        // the acquire semaphore. Submission waits on it at COLOR_ATTACHMENT_OUTPUT, so the transition goes there too.
+        // The depth buffer and multisampled target are shared by every frame in flight, so a frame's
+        // depth tests, clears and writes have to wait for the previous frame's as well
+        let mut dependency = SubpassDependency {
+            src_subpass: SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: AccessFlags::COLOR_ATTACHMENT_WRITE,
             ..Default::default()
         };
+        if depth_format.is_some() {
+            dependency.src_stage_mask |= PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS;
+            dependency.dst_stage_mask |= PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS;
+            dependency.src_access_mask |= AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+            dependency.dst_access_mask |= AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        }
 
-        // VkRenderPassCreateInfo 
-        let render_pass_create_info = RenderPassCreateInfo {
-            attachment_count: 1,
-            p_attachments: &colour_attachment_description,
-            subpass_count: 1,
-            p_subpasses: &subpass_description,
+        builder.subpass(subpass).dependency(dependency)
+    }
+
+    // Attachments are numbered in the order they're added, which is also the order of the framebuffer's views
+    // and of the clear values
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    // Subpasses run in the order they're added, numbered from 0
+    pub fn subpass(mut self, subpass: Subpass) -> Self {
+        self.subpasses.push(subpass);
+        self
+    }
+
+    // Nothing orders subpasses against each other or against other work without these
+    pub fn dependency(mut self, dependency: SubpassDependency) -> Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    // Catches the commoner mistakes the validation layers would report, as drivers may not.
+    // Takes the device's maxColorAttachments rather than the device, so it can be tested without one
+    fn validate(&self, max_colour_attachments: u32) -> Result<()> {
+        if self.subpasses.is_empty() {
+            return Err(Error::msg("A render pass needs at least one subpass"));
+        }
+
+        for (ix, subpass) in self.subpasses.iter().enumerate() {
+            let all = subpass.colour.iter().chain(&subpass.resolve).chain(&subpass.input).chain(&subpass.depth).chain(&subpass.preserve);
+            if let Some(attachment) = all.copied().find(|&attachment| attachment as usize >= self.attachments.len()) {
+                return Err(Error::msg(format!("Subpass {ix} uses attachment {attachment}, but there are only {}", self.attachments.len())));
+            }
+
+            if subpass.colour.len() as u32 > max_colour_attachments {
+                return Err(Error::msg(format!(
+                    "Subpass {ix} has {} colour attachments, more than the device's maxColorAttachments of {max_colour_attachments}",
+                    subpass.colour.len()
+                )));
+            }
+            if let Some(&attachment) = subpass.colour.iter().find(|&&attachment| self.attachments[attachment as usize].is_depth()) {
+                return Err(Error::msg(format!("Subpass {ix} uses depth attachment {attachment} as a colour attachment")));
+            }
+            if let Some(attachment) = subpass.depth && !self.attachments[attachment as usize].is_depth() {
+                return Err(Error::msg(format!("Subpass {ix} uses colour attachment {attachment} as its depth attachment")));
+            }
+
+            // Reading what's being drawn to needs the GENERAL layout, which the references built here never use
+            let drawn_to = subpass.colour.iter().chain(&subpass.resolve).chain(&subpass.depth);
+            if let Some(attachment) = drawn_to.copied().find(|attachment| subpass.input.contains(attachment)) {
+                return Err(Error::msg(format!("Subpass {ix} uses attachment {attachment} as an input attachment while drawing to it")));
+            }
+
+            let used = subpass.colour.iter().chain(&subpass.resolve).chain(&subpass.input).chain(&subpass.depth);
+            if let Some(attachment) = used.copied().find(|attachment| subpass.preserve.contains(attachment)) {
+                return Err(Error::msg(format!("Subpass {ix} preserves attachment {attachment}, but also uses it")));
+            }
+
+            // Without VK_AMD_mixed_attachment_samples everything drawn to has the same sample count
+            let mut drawn = subpass.colour.iter().chain(&subpass.depth).map(|&attachment| self.attachments[attachment as usize].samples);
+            if let Some(samples) = drawn.next() && drawn.any(|other| other != samples) {
+                return Err(Error::msg(format!("Subpass {ix} draws to attachments with different sample counts")));
+            }
+
+            if !subpass.resolve.is_empty() {
+                if subpass.resolve.len() != subpass.colour.len() {
+                    return Err(Error::msg(format!(
+                        "Subpass {ix} has {} resolve attachments for {} colour attachments, it needs one for each",
+                        subpass.resolve.len(), subpass.colour.len()
+                    )));
+                }
+                for (&colour, &resolve) in subpass.colour.iter().zip(&subpass.resolve) {
+                    let (colour, resolve) = (self.attachments[colour as usize], self.attachments[resolve as usize]);
+                    if colour.samples == SampleCountFlags::TYPE_1 || resolve.samples != SampleCountFlags::TYPE_1 {
+                        return Err(Error::msg(format!("Subpass {ix} resolves {:?} samples into {:?}, only multisampled into single sampled works", colour.samples, resolve.samples)));
+                    }
+                    if colour.format != resolve.format {
+                        return Err(Error::msg(format!("Subpass {ix} resolves {:?} into {:?}, the formats have to match", colour.format, resolve.format)));
+                    }
+                }
+            }
+        }
+
+        let subpass_count = self.subpasses.len() as u32;
+        for dependency in &self.dependencies {
+            if dependency.src_subpass == SUBPASS_EXTERNAL && dependency.dst_subpass == SUBPASS_EXTERNAL {
+                return Err(Error::msg("A dependency can't be from outside the render pass to outside it, one end has to be a subpass"));
+            }
+
+            let in_range = |subpass: u32| subpass == SUBPASS_EXTERNAL || subpass < subpass_count;
+            if !in_range(dependency.src_subpass) || !in_range(dependency.dst_subpass) {
+                return Err(Error::msg(format!(
+                    "A dependency from subpass {} to {} refers to a subpass that doesn't exist, there are {subpass_count}",
+                    dependency.src_subpass, dependency.dst_subpass
+                )));
+            }
+            // Subpasses can only wait for earlier ones
+            if dependency.src_subpass != SUBPASS_EXTERNAL && dependency.dst_subpass != SUBPASS_EXTERNAL && dependency.src_subpass > dependency.dst_subpass {
+                return Err(Error::msg(format!("Subpass {} can't depend on the later subpass {}", dependency.dst_subpass, dependency.src_subpass)));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build(&self, logical_device: &LogicalDevice) -> Result<RenderPass> {
+        self.validate(logical_device.properties().limits.max_color_attachments)?;
+
+        // VkAttachmentDescription
+        let attachments: Vec<vk::AttachmentDescription> = self.attachments.iter().map(|attachment| vk::AttachmentDescription {
+            format: attachment.format,
+            samples: attachment.samples,
+            load_op: attachment.load_op,
+            store_op: attachment.store_op,
+            stencil_load_op: attachment.stencil_load_op,
+            stencil_store_op: attachment.stencil_store_op,
+            initial_layout: attachment.initial_layout,
+            final_layout: attachment.final_layout,
             ..Default::default()
-        };
+        }).collect();
+
+        // VkAttachmentReference
+        // Kept per subpass until the render pass is created, as the descriptions point into them
+        let reference = |attachment: u32, layout: ImageLayout| AttachmentReference { attachment, layout };
+        let references: Vec<[Vec<AttachmentReference>; 4]> = self.subpasses.iter().map(|subpass| [
+            subpass.colour.iter().map(|&attachment| reference(attachment, ImageLayout::COLOR_ATTACHMENT_OPTIMAL)).collect(),
+            subpass.resolve.iter().map(|&attachment| reference(attachment, ImageLayout::COLOR_ATTACHMENT_OPTIMAL)).collect(),
+            subpass.input.iter().map(|&attachment| {
+                let layout = if self.attachments[attachment as usize].is_depth() { ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL } else { ImageLayout::SHADER_READ_ONLY_OPTIMAL };
+                reference(attachment, layout)
+            }).collect(),
+            subpass.depth.iter().map(|&attachment| reference(attachment, ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)).collect(),
+        ]).collect();
+
+        // VkSubpassDescription
+        let subpass_descriptions: Vec<vk::SubpassDescription> = self.subpasses.iter().zip(&references).map(|(subpass, [colour, resolve, input, depth])| {
+            let mut description = vk::SubpassDescription::default()
+                .pipeline_bind_point(PipelineBindPoint::GRAPHICS)
+                .color_attachments(colour)
+                .input_attachments(input)
+                .preserve_attachments(&subpass.preserve);
+            if !resolve.is_empty() {
+                description = description.resolve_attachments(resolve);
+            }
+            if let Some(depth) = depth.first() {
+                description = description.depth_stencil_attachment(depth);
+            }
+            description
+        }).collect();
+
+        // VkRenderPassCreateInfo
+        let render_pass_create_info = RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&subpass_descriptions)
+            .dependencies(&self.dependencies);
 
         let render_pass = unsafe { logical_device.raw().create_render_pass(&render_pass_create_info, None)? };
-        
-        Ok(Self {
+
+        let subpasses = self.subpasses.iter().map(|subpass| SubpassInfo {
+            colour_attachments: subpass.colour.len() as u32,
+            samples: subpass.colour.iter().chain(&subpass.depth)
+                .map(|&attachment| self.attachments[attachment as usize].samples)
+                .next()
+                .unwrap_or(SampleCountFlags::TYPE_1),
+            depth_format: subpass.depth.map(|attachment| self.attachments[attachment as usize].format),
+        }).collect();
+
+        Ok(RenderPass {
             raw: render_pass,
+            attachment_count: self.attachments.len() as u32,
+            subpasses,
             device: logical_device.raw().clone()
         })
     }
 }
 
+pub struct RenderPass {
+    raw: vk::RenderPass,
+    attachment_count: u32,
+    // What pipelines used with it have to match
+    subpasses: Vec<SubpassInfo>,
+    device: ash::Device
+}
+
+impl RenderPass {
+    pub fn raw(&self) -> &vk::RenderPass {
+        &self.raw
+    }
+
+    // How many views a framebuffer for it takes
+    pub fn attachment_count(&self) -> u32 {
+        self.attachment_count
+    }
+
+    pub fn subpass(&self, subpass: u32) -> Option<&SubpassInfo> {
+        self.subpasses.get(subpass as usize)
+    }
+}
+
 impl Drop for RenderPass {
     fn drop(&mut self) {
         unsafe {
@@ -64,4 +384,81 @@ impl Drop for RenderPass {
             self.device.destroy_render_pass(self.raw, None);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_COLOUR_ATTACHMENTS: u32 = 8;
+
+    // A G-buffer written in subpass 0 and read as an input attachment in subpass 1
+    fn deferred() -> RenderPassBuilder {
+        RenderPassBuilder::new()
+            .attachment(Attachment::present(Format::B8G8R8A8_SRGB))
+            .attachment(Attachment::transient_colour(Format::R16G16B16A16_SFLOAT, SampleCountFlags::TYPE_1))
+            .attachment(Attachment::depth(Format::D32_SFLOAT, SampleCountFlags::TYPE_1))
+            .subpass(Subpass::new().colour(1).depth(2))
+            .subpass(Subpass::new().colour(0).input(1))
+            .dependency(SubpassDependency { src_subpass: 0, dst_subpass: 1, ..Default::default() })
+    }
+
+    fn error(builder: &RenderPassBuilder) -> String {
+        builder.validate(MAX_COLOUR_ATTACHMENTS).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_the_swap_chain_passes() {
+        for samples in [SampleCountFlags::TYPE_1, SampleCountFlags::TYPE_4] {
+            RenderPassBuilder::swap_chain(Format::B8G8R8A8_SRGB, Some(Format::D32_SFLOAT), samples).validate(MAX_COLOUR_ATTACHMENTS).unwrap();
+        }
+        deferred().validate(MAX_COLOUR_ATTACHMENTS).unwrap();
+    }
+
+    #[test]
+    fn rejects_dependencies_with_no_subpass() {
+        let builder = deferred().dependency(SubpassDependency { src_subpass: SUBPASS_EXTERNAL, dst_subpass: SUBPASS_EXTERNAL, ..Default::default() });
+        assert!(error(&builder).contains("one end has to be a subpass"));
+
+        let builder = deferred().dependency(SubpassDependency { src_subpass: 1, dst_subpass: 0, ..Default::default() });
+        assert!(error(&builder).contains("later subpass"));
+
+        let builder = deferred().dependency(SubpassDependency { src_subpass: 0, dst_subpass: 2, ..Default::default() });
+        assert!(error(&builder).contains("doesn't exist"));
+    }
+
+    #[test]
+    fn rejects_preserving_a_used_attachment() {
+        let builder = deferred().subpass(Subpass::new().colour(0).preserve(0));
+        assert!(error(&builder).contains("preserves attachment 0"));
+    }
+
+    #[test]
+    fn rejects_reading_an_attachment_being_drawn_to() {
+        let builder = deferred().subpass(Subpass::new().colour(1).input(1));
+        assert!(error(&builder).contains("attachment 1 as an input attachment"));
+
+        let builder = deferred().subpass(Subpass::new().colour(0).depth(2).input(2));
+        assert!(error(&builder).contains("attachment 2 as an input attachment"));
+    }
+
+    #[test]
+    fn rejects_too_many_colour_attachments() {
+        let builder = deferred();
+        let message = builder.validate(0).unwrap_err().to_string();
+        assert!(message.contains("maxColorAttachments of 0"), "{message}");
+    }
+
+    #[test]
+    fn rejects_mismatched_attachments() {
+        let builder = deferred().subpass(Subpass::new().colour(2));
+        assert!(error(&builder).contains("depth attachment 2 as a colour attachment"));
+
+        let builder = deferred().subpass(Subpass::new().colour(3));
+        assert!(error(&builder).contains("only 3"));
+
+        // Resolving needs a multisampled source
+        let builder = deferred().subpass(Subpass::new().colour(1).resolve(0));
+        assert!(error(&builder).contains("resolves"));
+    }
+}
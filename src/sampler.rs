@@ -0,0 +1,107 @@
+use ash::vk::{self, BorderColor, CompareOp, Filter, SamplerAddressMode, SamplerMipmapMode};
+use anyhow::Result;
+
+use crate::LogicalDevice;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerOptions {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    pub address_mode_u: SamplerAddressMode,
+    pub address_mode_v: SamplerAddressMode,
+    pub address_mode_w: SamplerAddressMode,
+    // Requested maximum anisotropy. Clamped to what the device allows, and ignored if it isn't enabled
+    pub anisotropy: Option<f32>,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: Filter::LINEAR,
+            min_filter: Filter::LINEAR,
+            mipmap_mode: SamplerMipmapMode::LINEAR,
+            address_mode_u: SamplerAddressMode::REPEAT,
+            address_mode_v: SamplerAddressMode::REPEAT,
+            address_mode_w: SamplerAddressMode::REPEAT,
+            anisotropy: Some(16.0),
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: Filter::NEAREST,
+            min_filter: Filter::NEAREST,
+            mipmap_mode: SamplerMipmapMode::NEAREST,
+            anisotropy: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_address_mode(self, address_mode: SamplerAddressMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            ..self
+        }
+    }
+}
+
+pub struct Sampler {
+    raw: vk::Sampler,
+    device: ash::Device
+}
+
+impl Sampler {
+    pub fn new(logical_device: &LogicalDevice, options: &SamplerOptions, mip_levels: u32) -> Result<Self> {
+        let anisotropy_enabled = logical_device.enabled_features().sampler_anisotropy == vk::TRUE;
+        let max_anisotropy = match options.anisotropy {
+            Some(requested) if anisotropy_enabled => requested.min(logical_device.properties().limits.max_sampler_anisotropy),
+            _ => 1.0,
+        };
+
+        // VkSamplerCreateInfo
+        let create_info = vk::SamplerCreateInfo {
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_mode: options.mipmap_mode,
+            address_mode_u: options.address_mode_u,
+            address_mode_v: options.address_mode_v,
+            address_mode_w: options.address_mode_w,
+            anisotropy_enable: if max_anisotropy > 1.0 { vk::TRUE } else { vk::FALSE },
+            max_anisotropy,
+            border_color: BorderColor::INT_OPAQUE_BLACK,
+            // Texels are addressed with [0, 1) coordinates rather than [0, width)
+            unnormalized_coordinates: vk::FALSE,
+            // Only used for shadow map style comparisons
+            compare_enable: vk::FALSE,
+            compare_op: CompareOp::ALWAYS,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: (mip_levels - 1) as f32,
+            ..Default::default()
+        };
+
+        let sampler = unsafe { logical_device.raw().create_sampler(&create_info, None)? };
+
+        Ok(Self {
+            raw: sampler,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn raw(&self) -> &vk::Sampler {
+        &self.raw
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.raw, None);
+        }
+    }
+}
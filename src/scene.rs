@@ -0,0 +1,121 @@
+use ash::vk;
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec4};
+
+use crate::{LogicalDevice, command_pool::CommandPool, gltf_loader::GltfScene, mesh::{Bounds, Mesh, MeshData}, pipeline_layout::PipelineLayout, push_constants::PushConstants, sampler::SamplerOptions, texture::Texture};
+
+// Matches the push_constant block in shader.vert and shader.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct MeshPushConstants {
+    pub mvp: Mat4,
+    pub base_colour: Vec4,
+}
+
+impl PushConstants for MeshPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+struct DrawItem {
+    mesh: usize,
+    transform: Mat4,
+    base_colour: Vec4,
+}
+
+// Everything that has been uploaded and gets drawn each frame
+#[derive(Default)]
+pub struct Scene {
+    meshes: Vec<Mesh>,
+    textures: Vec<Texture>,
+    draws: Vec<DrawItem>,
+    bounds: Option<Bounds>,
+}
+
+impl Scene {
+    pub fn add_mesh(&mut self, logical_device: &LogicalDevice, command_pool: &CommandPool, data: &MeshData, transform: Mat4) -> Result<()> {
+        self.meshes.push(Mesh::upload(logical_device, command_pool, data)?);
+        self.draws.push(DrawItem {
+            mesh: self.meshes.len() - 1,
+            transform,
+            base_colour: Vec4::ONE,
+        });
+        self.include_bounds(data.bounds().map(|b| b.transformed(&transform)));
+
+        Ok(())
+    }
+
+    pub fn add_gltf(&mut self, logical_device: &LogicalDevice, command_pool: &CommandPool, gltf: &GltfScene) -> Result<()> {
+        for image in &gltf.images {
+            self.textures.push(Texture::from_rgba8(
+                logical_device,
+                command_pool,
+                image.width,
+                image.height,
+                &image.pixels,
+                image.usage,
+                &SamplerOptions::default()
+            )?);
+        }
+
+        // Primitives become meshes of their own, so remember where each glTF mesh starts
+        let mut primitive_ranges = vec![];
+        for mesh in &gltf.meshes {
+            let start = self.meshes.len();
+            for primitive in &mesh.primitives {
+                self.meshes.push(Mesh::upload(logical_device, command_pool, &primitive.data)?);
+            }
+            primitive_ranges.push(start..self.meshes.len());
+        }
+
+        for instance in &gltf.instances {
+            let primitives = &gltf.meshes[instance.mesh].primitives;
+
+            for (mesh, primitive) in primitive_ranges[instance.mesh].clone().zip(primitives) {
+                let base_colour = primitive.material
+                    .map_or([1.0; 4], |m| gltf.materials[m].base_colour_factor);
+
+                self.draws.push(DrawItem {
+                    mesh,
+                    transform: instance.transform,
+                    base_colour: Vec4::from(base_colour),
+                });
+            }
+        }
+
+        self.include_bounds(gltf.bounds());
+
+        Ok(())
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    pub fn textures(&self) -> &[Texture] {
+        &self.textures
+    }
+
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, layout: &PipelineLayout, view_projection: Mat4) {
+        // Pipelines declared with other push constants draw without them
+        let push = layout.push_constants().is_some_and(|declared| declared.is::<MeshPushConstants>());
+
+        for draw in &self.draws {
+            if push {
+                layout.push(command_buffer, &MeshPushConstants {
+                    mvp: view_projection * draw.transform,
+                    base_colour: draw.base_colour,
+                });
+            }
+
+            self.meshes[draw.mesh].draw(device, command_buffer);
+        }
+    }
+
+    fn include_bounds(&mut self, bounds: Option<Bounds>) {
+        self.bounds = match (self.bounds, bounds) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
@@ -0,0 +1,132 @@
+// Compiles preprocessed GLSL or WGSL to SPIR-V words with naga, for shaders loaded or varied at runtime
+use std::{error::Error as _, fmt::Write};
+
+use anyhow::{Error, Result};
+use naga::{ShaderStage, SourceLocation, back::{pipeline_constants, spv}, front::{glsl, wgsl}, valid::{Capabilities, ValidationFlags, Validator}};
+
+use crate::shader_preprocessor::Preprocessed;
+
+// GLSL picks its stage from the file extension like glslc does, WGSL declares its own entry points
+enum Language {
+    Glsl(ShaderStage),
+    Wgsl,
+}
+
+fn language(name: &str) -> Result<Language> {
+    match name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("vert") => Ok(Language::Glsl(ShaderStage::Vertex)),
+        Some("frag") => Ok(Language::Glsl(ShaderStage::Fragment)),
+        Some("comp") => Ok(Language::Glsl(ShaderStage::Compute)),
+        Some("wgsl") => Ok(Language::Wgsl),
+        _ => Err(Error::msg(format!("{name}: unknown shader type, expected .vert, .frag, .comp or .wgsl"))),
+    }
+}
+
+pub fn is_shader_stage(name: &str) -> bool {
+    language(name).is_ok()
+}
+
+// `name` decides the language. Errors come out as file:line:column, pointing into whichever
+// included file the problem is in, followed by the offending source line
+pub fn compile(preprocessed: &Preprocessed, name: &str, defines: &[(String, String)]) -> Result<Vec<u32>> {
+    let source = &preprocessed.source;
+
+    let module = match language(name)? {
+        Language::Glsl(stage) => {
+            let options = glsl::Options {
+                stage,
+                defines: defines.iter().cloned().collect(),
+            };
+
+            glsl::Frontend::default().parse(&options, source).map_err(|errors| {
+                let messages: Vec<String> = errors.errors.iter()
+                    .map(|e| located(preprocessed, &e.kind.to_string(), e.location(source)))
+                    .collect();
+                Error::msg(messages.join("\n"))
+            })?
+        },
+        Language::Wgsl if !defines.is_empty() => {
+            return Err(Error::msg(format!("{name}: WGSL has no preprocessor, so defines can't be passed to it")));
+        },
+        Language::Wgsl => wgsl::parse_str(source)
+            .map_err(|e| Error::msg(located(preprocessed, e.message(), e.location(source))))?,
+    };
+
+    // Push constants are an optional capability in naga, Vulkan always has them
+    let info = Validator::new(ValidationFlags::all(), Capabilities::default() | Capabilities::IMMEDIATES)
+        .validate(&module)
+        .map_err(|e| {
+            let mut message = e.to_string();
+            let mut cause = e.source();
+            while let Some(inner) = cause {
+                write!(message, ": {inner}").unwrap();
+                cause = inner.source();
+            }
+            Error::msg(located(preprocessed, &message, e.location(source)))
+        })?;
+
+    // naga can't write OpSpecConstant, so constant_id and override values are baked in at their defaults.
+    // Reflection won't list them, so trying to specialize one is an error rather than silently ignored.
+    // The embedded shaders come from glslc and keep theirs
+    let (module, info) = pipeline_constants::process_overrides(&module, &info, None, &Default::default())
+        .map_err(|e| Error::msg(format!("{name}: {e}")))?;
+
+    let mut options = spv::Options::default();
+    // The shaders are written for Vulkan's clip space already, and the camera flips Y itself
+    options.flags.remove(spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+
+    spv::write_vec(&module, &info, &options, None).map_err(|e| Error::msg(format!("{name}: cannot write SPIR-V: {e}")))
+}
+
+fn located(preprocessed: &Preprocessed, message: &str, location: Option<SourceLocation>) -> String {
+    let origin = location.and_then(|l| Some((l, preprocessed.origin(l.line_number)?)));
+    let Some((location, origin)) = origin else {
+        let file = preprocessed.files.first().map_or("shader", |f| f.name.as_str());
+        return format!("{file}: error: {message}");
+    };
+
+    // naga's positions are 1-based, but an error at the very start of the source can come out as 0
+    let text = preprocessed.source.lines().nth((location.line_number as usize).saturating_sub(1)).unwrap_or("");
+    let gutter = " ".repeat(origin.line.to_string().len());
+    let marker = format!("{}{}", " ".repeat((location.line_position as usize).saturating_sub(1)), "^".repeat(location.length.max(1) as usize));
+
+    format!(
+        "{}:{}:{}: error: {message}\n{gutter} |\n{} | {text}\n{gutter} | {marker}",
+        origin.file, origin.line, location.line_position, origin.line
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_preprocessor::preprocess;
+
+    fn preprocess_files(name: &str, files: &[(&str, &str)]) -> Preprocessed {
+        preprocess(name, &mut |wanted| {
+            files.iter().find(|(name, _)| *name == wanted).map(|(_, source)| source.to_string()).ok_or_else(|| Error::msg("not found"))
+        }).unwrap()
+    }
+
+    #[test]
+    fn reports_errors_in_included_files() {
+        let preprocessed = preprocess_files("main.frag", &[
+            ("main.frag", "#version 450\n#include \"common.glsl\"\nlayout(location = 0) out vec4 colour;\nvoid main() { colour = tint(); }\n"),
+            ("common.glsl", "#pragma once\nvec4 tint() {\n    return undefined_thing;\n}\n"),
+        ]);
+
+        let error = compile(&preprocessed, "main.frag", &[]).unwrap_err().to_string();
+
+        assert!(error.starts_with("common.glsl:3:"), "{error}");
+        assert!(error.contains("return undefined_thing;"), "{error}");
+    }
+
+    #[test]
+    fn locates_errors_at_position_zero() {
+        let preprocessed = preprocess_files("main.frag", &[("main.frag", "broken\n")]);
+        let location = SourceLocation { line_number: 1, line_position: 0, offset: 0, length: 0 };
+
+        let message = located(&preprocessed, "oops", Some(location));
+
+        assert!(message.starts_with("main.frag:1:0: error: oops"), "{message}");
+    }
+}
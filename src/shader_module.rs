@@ -1,4 +1,4 @@
-use crate::LogicalDevice;
+use crate::{LogicalDevice, spirv::SpirvBinary};
 use anyhow::Result;
 
 pub struct ShaderModule {
@@ -7,12 +7,8 @@ pub struct ShaderModule {
 }
 
 impl ShaderModule {
-    pub fn new(logical_device: &LogicalDevice, shader_data: &Vec<u8>) -> Result<Self>{
-        let create_info= ash::vk::ShaderModuleCreateInfo {
-            code_size: shader_data.len(),
-            p_code: shader_data.as_ptr() as *const u32,
-            ..Default::default()
-        };
+    pub fn new(logical_device: &LogicalDevice, spirv: &SpirvBinary) -> Result<Self>{
+        let create_info= ash::vk::ShaderModuleCreateInfo::default().code(spirv.words());
 
         let shader_module = unsafe { logical_device.raw().create_shader_module(&create_info, None)? };
 
@@ -0,0 +1,74 @@
+use std::collections::{BTreeMap, HashMap, hash_map::Entry};
+
+use anyhow::Result;
+
+use crate::{LogicalDevice, shader_module::ShaderModule, shader_preprocessor::SourceFile, shaders, spirv::SpirvBinary};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct VariantKey {
+    name: String,
+    // Sorted, so the order the defines were passed in doesn't matter
+    defines: Vec<(String, String)>,
+    // Every file that went into the variant, so editing an include only invalidates the variants using it
+    sources: Vec<SourceFile>,
+}
+
+pub struct ShaderVariant {
+    spirv: SpirvBinary,
+    module: ShaderModule,
+}
+
+impl ShaderVariant {
+    // Kept around for reflection
+    pub fn spirv(&self) -> &SpirvBinary {
+        &self.spirv
+    }
+
+    pub fn module(&self) -> &ShaderModule {
+        &self.module
+    }
+}
+
+// One ShaderModule per shader and set of defines, e.g. shader.frag with HAS_NORMAL_MAP and ALPHA_TEST
+#[derive(Default)]
+pub struct ShaderPermutations {
+    variants: HashMap<VariantKey, ShaderVariant>,
+}
+
+impl ShaderPermutations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Sources are read and hashed on every call, so edits are picked up the next time a variant is asked for
+    pub fn get(&mut self, logical_device: &LogicalDevice, name: &str, defines: &[(&str, &str)]) -> Result<&ShaderVariant> {
+        let preprocessed = shaders::preprocess(name)?;
+        let defines: BTreeMap<String, String> = defines.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        let key = VariantKey {
+            name: name.to_string(),
+            defines: defines.into_iter().collect(),
+            sources: preprocessed.files.clone(),
+        };
+
+        // Anything built from older versions of the sources can never be asked for again
+        self.variants.retain(|existing, _| existing.name != key.name || existing.defines != key.defines || existing.sources == key.sources);
+
+        match self.variants.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let spirv = shaders::compile_variant(&preprocessed, name, &entry.key().defines)?;
+                let module = ShaderModule::new(logical_device, &spirv)?;
+                Ok(entry.insert(ShaderVariant { spirv, module }))
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+}
@@ -0,0 +1,237 @@
+// Expands #include so naga sees a single source, remembering where every line came from
+use std::{collections::HashSet, hash::{DefaultHasher, Hash, Hasher}};
+
+use anyhow::{Error, Result};
+
+// Includes nested deeper than this are assumed to be a cycle the guards didn't catch
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SourceFile {
+    // Relative to the shaders directory, always with forward slashes
+    pub name: String,
+    pub hash: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceLine {
+    pub file: String,
+    // 1-based, as editors count
+    pub line: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    pub source: String,
+    // One per line of `source`
+    pub lines: Vec<SourceLine>,
+    // The shader itself followed by everything it includes, in the order they were first read
+    pub files: Vec<SourceFile>,
+}
+
+impl Preprocessed {
+    // Where a 1-based line of the expanded source came from
+    pub fn origin(&self, line: u32) -> Option<&SourceLine> {
+        self.lines.get(line.checked_sub(1)? as usize)
+    }
+}
+
+struct State<'a> {
+    read: &'a mut dyn FnMut(&str) -> Result<String>,
+    output: Preprocessed,
+    // Files marked #pragma once, or whose #ifndef guard is defined, that have been included already.
+    // Including them again would expand to nothing, so they are skipped rather than called a cycle
+    once: HashSet<String>,
+    stack: Vec<String>,
+}
+
+// `read` is handed names relative to the shaders directory, e.g. "common/lighting.glsl".
+// Include paths are always relative to that directory, wherever the including file lives.
+// Both `#pragma once` and #ifndef guards work, the latter because naga's preprocessor still sees them
+pub fn preprocess(name: &str, read: &mut dyn FnMut(&str) -> Result<String>) -> Result<Preprocessed> {
+    let mut state = State {
+        read,
+        output: Preprocessed { source: String::new(), lines: vec![], files: vec![] },
+        once: HashSet::new(),
+        stack: vec![],
+    };
+
+    state.include(&normalise(name)?)?;
+
+    Ok(state.output)
+}
+
+impl State<'_> {
+    fn include(&mut self, name: &str) -> Result<()> {
+        if self.stack.iter().any(|open| open == name) || self.stack.len() >= MAX_INCLUDE_DEPTH {
+            self.stack.push(name.to_string());
+            return Err(Error::msg(format!("include cycle: {}", self.stack.join(" -> "))));
+        }
+
+        let source = (self.read)(name).map_err(|e| Error::msg(format!("Cannot read shader {name}: {e}")))?;
+
+        let file = SourceFile { name: name.to_string(), hash: hash(&source) };
+        if !self.output.files.contains(&file) {
+            self.output.files.push(file);
+        }
+
+        self.stack.push(name.to_string());
+        let guard = include_guard(&source);
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let directive = line.trim_start().strip_prefix('#').map(str::trim_start);
+            let words: Vec<&str> = directive.map(|d| d.split_whitespace().collect()).unwrap_or_default();
+
+            if words == ["pragma", "once"] {
+                self.once.insert(name.to_string());
+                // Kept as a blank line so line numbers after it still match
+                self.push_line("", name, line_number);
+                continue;
+            }
+
+            // Left in for naga, which skips the body itself when the file is included again
+            if guard.is_some_and(|guard| words == ["define", guard]) {
+                self.once.insert(name.to_string());
+            }
+
+            if let Some(target) = directive.and_then(|d| d.strip_prefix("include")) {
+                let target = target.trim();
+                let target = target.strip_prefix('"').and_then(|t| t.strip_suffix('"')).ok_or_else(|| Error::msg(format!(
+                    "{name}:{line_number}: expected #include \"file\", found #include {target}"
+                )))?;
+                let target = normalise(target).map_err(|e| Error::msg(format!("{name}:{line_number}: {e}")))?;
+
+                if self.once.contains(&target) {
+                    self.push_line("", name, line_number);
+                    continue;
+                }
+
+                self.include(&target).map_err(|e| Error::msg(format!("{name}:{line_number}: {e}")))?;
+                continue;
+            }
+
+            self.push_line(line, name, line_number);
+        }
+
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    fn push_line(&mut self, text: &str, file: &str, line: u32) {
+        self.output.source.push_str(text);
+        self.output.source.push('\n');
+        self.output.lines.push(SourceLine { file: file.to_string(), line });
+    }
+}
+
+// The X of a file wrapped in `#ifndef X`, `#define X` ... `#endif`, with only comments and blank lines outside.
+// An #undef of it later on is not noticed
+fn include_guard(source: &str) -> Option<&str> {
+    let mut directives = source.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .map(|line| line.strip_prefix('#').map(|d| d.split_whitespace().collect::<Vec<_>>()));
+
+    let guard = match directives.next()??.as_slice() {
+        ["ifndef", guard] => *guard,
+        _ => return None,
+    };
+    if directives.next()?? != ["define", guard] {
+        return None;
+    }
+    // The file's last line of code has to close the #ifndef
+    let last = directives.next_back()??;
+    (last.first() == Some(&"endif")).then_some(guard)
+}
+
+// Keeps names comparable whatever separators or ./ segments the include used, and inside the shaders directory
+fn normalise(name: &str) -> Result<String> {
+    let mut parts: Vec<&str> = vec![];
+
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {},
+            ".." => {
+                parts.pop().ok_or_else(|| Error::msg(format!("{name} is outside the shaders directory")))?;
+            },
+            part => parts.push(part),
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(Error::msg("empty include path"));
+    }
+
+    Ok(parts.join("/"))
+}
+
+fn hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn preprocess_files(name: &str, files: &[(&str, &str)]) -> Result<Preprocessed> {
+        let files: HashMap<&str, &str> = files.iter().copied().collect();
+        preprocess(name, &mut |name| files.get(name).map(|s| s.to_string()).ok_or_else(|| Error::msg("not found")))
+    }
+
+    #[test]
+    fn skips_mutual_includes_with_pragma_once() {
+        let output = preprocess_files("main.frag", &[
+            ("main.frag", "#include \"a.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "#pragma once\n#include \"b.glsl\"\nfloat a;\n"),
+            ("b.glsl", "#pragma once\n#include \"a.glsl\"\nfloat b;\n"),
+        ]).unwrap();
+
+        assert_eq!(output.source.matches("float a;").count(), 1);
+        assert_eq!(output.source.matches("float b;").count(), 1);
+    }
+
+    #[test]
+    fn skips_mutual_includes_with_guards() {
+        let output = preprocess_files("main.frag", &[
+            ("main.frag", "#include \"a.glsl\"\n#include \"b.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "// Lighting\n#ifndef A_GLSL\n#define A_GLSL\n#include \"b.glsl\"\nfloat a;\n#endif\n"),
+            ("b.glsl", "#ifndef B_GLSL\n#define B_GLSL\n#include \"a.glsl\"\nfloat b;\n#endif // B_GLSL\n"),
+        ]).unwrap();
+
+        assert_eq!(output.source.matches("float a;").count(), 1);
+        assert_eq!(output.source.matches("float b;").count(), 1);
+        assert_eq!(output.source.lines().count(), output.lines.len());
+    }
+
+    #[test]
+    fn reports_unguarded_cycles() {
+        let error = preprocess_files("main.frag", &[
+            ("main.frag", "#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#ifndef B\n#define B\n#endif\n#include \"a.glsl\"\n"),
+        ]).unwrap_err();
+
+        assert!(error.to_string().contains("include cycle: main.frag -> a.glsl -> b.glsl -> a.glsl"), "{error}");
+    }
+
+    #[test]
+    fn maps_lines_back_to_their_files() {
+        let output = preprocess_files("main.frag", &[
+            ("main.frag", "#version 450\n#include \"common.glsl\"\nvoid main() {}\n"),
+            ("common.glsl", "#pragma once\nfloat x;\n"),
+        ]).unwrap();
+
+        let origin = |line| output.origin(line).map(|o| (o.file.as_str(), o.line));
+        assert_eq!(origin(1), Some(("main.frag", 1)));
+        assert_eq!(origin(3), Some(("common.glsl", 2)));
+        assert_eq!(origin(4), Some(("main.frag", 3)));
+        assert_eq!(origin(0), None);
+        assert_eq!(origin(5), None);
+    }
+}
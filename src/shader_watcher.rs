@@ -0,0 +1,59 @@
+use std::{path::Path, sync::mpsc::{self, Receiver}};
+
+use anyhow::Result;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+// With naga the sources are compiled when a shader is loaded, so editing one is enough to reload it
+#[cfg(feature = "naga")]
+const SHADER_EXTENSIONS: [&str; 6] = ["vert", "frag", "comp", "wgsl", "glsl", "spv"];
+// Without it only the compiled modules in out/ are loaded, so a reload waits for `just compile-shaders`.
+// Reloading on a source edit would just pick up the stale module again
+#[cfg(not(feature = "naga"))]
+const SHADER_EXTENSIONS: [&str; 1] = ["spv"];
+
+// Watches a shaders directory, including the compiled modules in its out/ folder
+pub struct ShaderWatcher {
+    events: Receiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+
+        if cfg!(feature = "naga") {
+            println!("Watching {} for shader changes", dir.display());
+        } else {
+            println!("Watching {} for recompiled shaders, run `just compile-shaders` after editing one", dir.display());
+        }
+
+        Ok(Self {
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    // True if anything changed since the last call. Editors tend to fire several events per save,
+    // draining them all means one reload per save
+    pub fn poll(&self) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    changed |= event.paths.iter().any(|path| is_shader(path));
+                },
+                Ok(_) => {},
+                Err(e) => eprintln!("Shader watcher error: {e}"),
+            }
+        }
+
+        changed
+    }
+}
+
+fn is_shader(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| SHADER_EXTENSIONS.contains(&e))
+}
@@ -0,0 +1,78 @@
+use std::{env, path::{Path, PathBuf}};
+
+use anyhow::{Error, Result};
+
+use crate::spirv::SpirvBinary;
+#[cfg(feature = "naga")]
+use crate::shader_preprocessor::Preprocessed;
+
+// EMBEDDED maps each shader in shaders/ to its SPIR-V, compiled by build.rs or checked in under shaders/out.
+// EMBEDDED_SOURCES holds the text of every shader and include file, by the same names
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+// Point this at a shaders directory to load from disk instead of using the copies built into the binary
+pub const SHADER_DIR_VAR: &str = "VULKRUST_SHADER_DIR";
+
+// Set when shaders come from disk, which is also when they can be hot reloaded
+pub fn shader_dir() -> Option<PathBuf> {
+    env::var_os(SHADER_DIR_VAR).map(PathBuf::from)
+}
+
+pub fn embedded(name: &str) -> Option<&'static [u8]> {
+    EMBEDDED.iter().find(|(embedded, _)| *embedded == name).map(|(_, bytes)| *bytes)
+}
+
+// `name` is the source file name, e.g. "shader.vert"
+pub fn load_shader(name: &str) -> Result<SpirvBinary> {
+    match shader_dir() {
+        Some(dir) => load_from_disk(&dir, name),
+        None => {
+            let bytes = embedded(name).ok_or_else(|| Error::msg(format!("No shader called {name} was embedded at build time")))?;
+            SpirvBinary::from_bytes(bytes, name)
+        },
+    }
+}
+
+// Compiles the source when built with the naga feature, otherwise loads the output of `just compile-shaders`
+fn load_from_disk(dir: &Path, name: &str) -> Result<SpirvBinary> {
+    let source = dir.join(name);
+    let compiled = dir.join("out").join(format!("{name}.spv"));
+
+    #[cfg(feature = "naga")]
+    if source.exists() {
+        return compile_variant(&preprocess(name)?, name, &[]);
+    }
+
+    if !compiled.exists() {
+        return Err(Error::msg(format!(
+            "Compiled shader {} is missing. Run `just compile-shaders`, or build with `--features naga` to compile {} at runtime",
+            compiled.display(),
+            source.display()
+        )));
+    }
+
+    SpirvBinary::load(compiled)
+}
+
+// The text of a shader or include file, from disk when overridden
+pub fn read_source(name: &str) -> Result<String> {
+    match shader_dir() {
+        Some(dir) => Ok(std::fs::read_to_string(dir.join(name))?),
+        None => EMBEDDED_SOURCES.iter()
+            .find(|(embedded, _)| *embedded == name)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| Error::msg(format!("No shader source called {name} was embedded at build time"))),
+    }
+}
+
+// Expands includes, giving the source naga will see and the hashes of every file that went into it
+#[cfg(feature = "naga")]
+pub fn preprocess(name: &str) -> Result<Preprocessed> {
+    crate::shader_preprocessor::preprocess(name, &mut |name| read_source(name))
+}
+
+#[cfg(feature = "naga")]
+pub fn compile_variant(preprocessed: &Preprocessed, name: &str, defines: &[(String, String)]) -> Result<SpirvBinary> {
+    let words = crate::shader_compiler::compile(preprocessed, name, defines)?;
+    SpirvBinary::from_words(words, name)
+}
@@ -0,0 +1,204 @@
+use ash::vk;
+use anyhow::{Error, Result};
+use bytemuck::{Pod, Zeroable};
+
+use crate::reflection::{ScalarType, ShaderReflection};
+
+// VkBool32, as bool isn't Pod and a specialization constant bool is four bytes
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct SpecBool(u32);
+
+impl From<bool> for SpecBool {
+    fn from(value: bool) -> Self {
+        Self(value as u32)
+    }
+}
+
+// Types a specialization constant can have, and what reflection calls them
+pub trait SpecValue: Pod {
+    const TYPE: ScalarType;
+}
+
+impl SpecValue for SpecBool { const TYPE: ScalarType = ScalarType::Bool; }
+impl SpecValue for u32 { const TYPE: ScalarType = ScalarType::Int { width: 32, signed: false }; }
+impl SpecValue for i32 { const TYPE: ScalarType = ScalarType::Int { width: 32, signed: true }; }
+impl SpecValue for f32 { const TYPE: ScalarType = ScalarType::Float { width: 32 }; }
+impl SpecValue for u64 { const TYPE: ScalarType = ScalarType::Int { width: 64, signed: false }; }
+impl SpecValue for i64 { const TYPE: ScalarType = ScalarType::Int { width: 64, signed: true }; }
+impl SpecValue for f64 { const TYPE: ScalarType = ScalarType::Float { width: 64 }; }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpecField {
+    pub id: u32,
+    pub offset: u32,
+    pub ty: ScalarType,
+}
+
+// A #[repr(C)] Pod struct whose fields are specialization constants, normally implemented with
+// the specialization_constants! macro
+pub trait SpecializationConstants: Pod {
+    fn fields() -> Vec<SpecField>;
+}
+
+// Lets the macro name a field's type without the caller repeating it
+#[doc(hidden)]
+pub fn field_type<S, T: SpecValue>(_: fn(&S) -> &T) -> ScalarType {
+    T::TYPE
+}
+
+// specialization_constants!(Tuning { workgroup_size: 0, use_fog: 1 });
+// maps each field of Tuning to the constant_id it sets
+#[macro_export]
+macro_rules! specialization_constants {
+    ($ty:ty { $($field:ident: $id:expr),* $(,)? }) => {
+        impl $crate::specialization::SpecializationConstants for $ty {
+            fn fields() -> Vec<$crate::specialization::SpecField> {
+                vec![$($crate::specialization::SpecField {
+                    id: $id,
+                    offset: ::std::mem::offset_of!($ty, $field) as u32,
+                    ty: $crate::specialization::field_type(|s: &$ty| &s.$field),
+                }),*]
+            }
+        }
+    };
+}
+
+// The values for one shader stage, owned so pipelines can be keyed on them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Specialization {
+    fields: Vec<SpecField>,
+    data: Vec<u8>,
+}
+
+impl Specialization {
+    pub fn new<T: SpecializationConstants>(constants: &T) -> Self {
+        let mut fields = T::fields();
+        fields.sort_by_key(|field| field.id);
+
+        Self {
+            fields,
+            data: bytemuck::bytes_of(constants).to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    // Every constant set has to exist in the shader with the same type.
+    // Shaders compiled at runtime by naga have theirs baked in, so they have none to set
+    pub fn check(&self, reflection: &ShaderReflection, module: &str) -> Result<()> {
+        for field in &self.fields {
+            let constant = reflection.spec_constants.iter().find(|c| c.id == field.id).ok_or_else(|| Error::msg(format!(
+                "{module} has no specialization constant with constant_id {}. naga bakes them in at compile time, \
+                 so specializing needs the embedded SPIR-V or glslc's output in VULKRUST_SHADER_DIR/out", field.id
+            )))?;
+
+            if constant.ty != field.ty {
+                return Err(Error::msg(format!(
+                    "Specialization constant {} ({}) in {module} is {:?}, but is being set as {:?}",
+                    field.id, constant.name.as_deref().unwrap_or("unnamed"), constant.ty, field.ty
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn map_entries(&self) -> Vec<vk::SpecializationMapEntry> {
+        self.fields.iter().map(|field| vk::SpecializationMapEntry {
+            constant_id: field.id,
+            offset: field.offset,
+            size: scalar_size(field.ty),
+        }).collect()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+fn scalar_size(ty: ScalarType) -> usize {
+    match ty {
+        ScalarType::Bool => 4,
+        ScalarType::Int { width, .. } | ScalarType::Float { width } => width as usize / 8,
+    }
+}
+
+// What a graphics pipeline is specialized with, one set per stage
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PipelineSpecialization {
+    pub vertex: Specialization,
+    pub fragment: Specialization,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{shaders::embedded, spirv::SpirvBinary};
+
+    const SHADER: &str = "tests/specialised.comp";
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Scale {
+        scale: u32,
+    }
+    specialization_constants!(Scale { scale: 0 });
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct FloatScale {
+        scale: f32,
+    }
+    specialization_constants!(FloatScale { scale: 0 });
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Missing {
+        value: u32,
+    }
+    specialization_constants!(Missing { value: 7 });
+
+    fn reflect_embedded() -> ShaderReflection {
+        let bytes = embedded(SHADER).expect("the test shader is embedded");
+        ShaderReflection::reflect(&SpirvBinary::from_bytes(bytes, SHADER).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn embedded_shaders_keep_spec_constants() {
+        let reflection = reflect_embedded();
+
+        assert_eq!(reflection.spec_constants.len(), 1);
+        let constant = &reflection.spec_constants[0];
+        assert_eq!(constant.id, 0);
+        assert_eq!(constant.name.as_deref(), Some("SCALE"));
+        assert_eq!(constant.ty, ScalarType::Int { width: 32, signed: false });
+        assert_eq!(constant.default, 2);
+    }
+
+    #[test]
+    fn specialises_an_embedded_shader() {
+        let reflection = reflect_embedded();
+        let specialization = Specialization::new(&Scale { scale: 3 });
+
+        specialization.check(&reflection, SHADER).unwrap();
+
+        let entries = specialization.map_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].constant_id, entries[0].offset, entries[0].size), (0, 0, 4));
+        assert_eq!(specialization.data(), 3u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_mismatched_constants() {
+        let reflection = reflect_embedded();
+
+        let error = Specialization::new(&FloatScale { scale: 3.0 }).check(&reflection, SHADER).unwrap_err();
+        assert!(error.to_string().contains("SCALE"), "{error}");
+
+        let error = Specialization::new(&Missing { value: 1 }).check(&reflection, SHADER).unwrap_err();
+        assert!(error.to_string().contains("constant_id 7"), "{error}");
+    }
+}
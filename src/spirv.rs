@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use anyhow::{Error, Result};
+
+use crate::utils::read_file;
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+// A SPIR-V module whose header has been checked, stored as native endian words
+#[derive(Clone, Debug)]
+pub struct SpirvBinary {
+    name: String,
+    words: Vec<u32>,
+}
+
+impl SpirvBinary {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = read_file(&path.to_string_lossy())
+            .map_err(|e| Error::msg(format!("Cannot read shader {}: {e}", path.display())))?;
+
+        Self::from_bytes(&bytes, &path.display().to_string())
+    }
+
+    // `name` is used in error messages, normally the file the bytes came from
+    pub fn from_bytes(bytes: &[u8], name: &str) -> Result<Self> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err(Error::msg(format!("{name}: SPIR-V is {} bytes long, which is not a whole number of 32-bit words", bytes.len())));
+        }
+
+        // Copying into a Vec<u32> sorts out the alignment, a Vec<u8> is only guaranteed to be byte aligned
+        let words = bytes.chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        Self::from_words(words, name)
+    }
+
+    pub fn from_words(mut words: Vec<u32>, name: &str) -> Result<Self> {
+        if words.len() < HEADER_WORDS {
+            return Err(Error::msg(format!("{name}: SPIR-V is {} words long, too short to hold the {HEADER_WORDS} word header", words.len())));
+        }
+
+        // Modules may be stored in either endianness, the magic number tells us which
+        if words[0] == MAGIC.swap_bytes() {
+            words.iter_mut().for_each(|w| *w = w.swap_bytes());
+        }
+        if words[0] != MAGIC {
+            return Err(Error::msg(format!("{name}: bad SPIR-V magic number {:#010x}, expected {MAGIC:#010x}", words[0])));
+        }
+
+        let binary = Self { name: name.to_string(), words };
+
+        let (major, minor) = binary.version();
+        if major != 1 || minor > 6 {
+            return Err(Error::msg(format!("{name}: unsupported SPIR-V version {major}.{minor}")));
+        }
+        if binary.bound() == 0 {
+            return Err(Error::msg(format!("{name}: SPIR-V id bound is 0")));
+        }
+        if binary.words[4] != 0 {
+            return Err(Error::msg(format!("{name}: reserved SPIR-V header word is {:#x}, expected 0", binary.words[4])));
+        }
+
+        Ok(binary)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    pub fn version(&self) -> (u8, u8) {
+        let version = self.words[1];
+        ((version >> 16) as u8, (version >> 8) as u8)
+    }
+
+    // Registered tool id in the top 16 bits, tool version in the bottom
+    pub fn generator(&self) -> u32 {
+        self.words[2]
+    }
+
+    // Every id in the module is less than this
+    pub fn bound(&self) -> u32 {
+        self.words[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The header of a SPIR-V 1.0 module with ids below 8, followed by an OpCapability Shader
+    fn header() -> Vec<u32> {
+        vec![MAGIC, 0x0001_0000, 0, 8, 0, (2 << 16) | 17, 1]
+    }
+
+    fn le_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn error(words: Vec<u32>) -> String {
+        SpirvBinary::from_words(words, "test.spv").unwrap_err().to_string()
+    }
+
+    #[test]
+    fn loads_either_endianness() {
+        let little = SpirvBinary::from_bytes(&le_bytes(&header()), "little.spv").unwrap();
+        let big_bytes: Vec<u8> = header().iter().flat_map(|w| w.to_be_bytes()).collect();
+        let big = SpirvBinary::from_bytes(&big_bytes, "big.spv").unwrap();
+
+        assert_eq!(big.words(), little.words());
+        assert_eq!(big.version(), (1, 0));
+        assert_eq!(big.bound(), 8);
+    }
+
+    #[test]
+    fn rejects_partial_words() {
+        let mut bytes = le_bytes(&header());
+        bytes.pop();
+        let error = SpirvBinary::from_bytes(&bytes, "test.spv").unwrap_err();
+
+        assert!(error.to_string().contains("not a whole number of 32-bit words"), "{error}");
+    }
+
+    #[test]
+    fn rejects_short_modules() {
+        assert!(error(header()[..4].to_vec()).contains("too short to hold the 5 word header"));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut words = header();
+        words[0] = 0xdead_beef;
+
+        assert!(error(words).contains("bad SPIR-V magic number 0xdeadbeef"));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut words = header();
+        words[1] = 0x0001_0700;
+        assert!(error(words).contains("unsupported SPIR-V version 1.7"));
+
+        let mut words = header();
+        words[1] = 0x0002_0000;
+        assert!(error(words).contains("unsupported SPIR-V version 2.0"));
+
+        let mut words = header();
+        words[1] = 0x0001_0600;
+        SpirvBinary::from_words(words, "test.spv").unwrap();
+    }
+
+    #[test]
+    fn rejects_a_zero_bound() {
+        let mut words = header();
+        words[3] = 0;
+
+        assert!(error(words).contains("id bound is 0"));
+    }
+
+    #[test]
+    fn rejects_a_reserved_word() {
+        let mut words = header();
+        words[4] = 1;
+
+        assert!(error(words).contains("reserved SPIR-V header word is 0x1"));
+    }
+}
@@ -28,7 +28,7 @@ impl SurfaceCapabilities {
             }
         }
 
-        return Some(&self.physical_device_surface_formats[0])
+        Some(&self.physical_device_surface_formats[0])
     }
 
     pub fn find_best_present_mode(&self) -> Option<PresentModeKHR> {
@@ -44,7 +44,7 @@ impl SurfaceCapabilities {
             }
         }
 
-        return Some(PresentModeKHR::FIFO)
+        Some(PresentModeKHR::FIFO)
     }
 
     pub fn find_swap_extent(&self, width: u32, height: u32) -> Extent2D {
@@ -1,4 +1,4 @@
-use ash::vk::{CompositeAlphaFlagsKHR, Extent2D, Format, Image, ImageUsageFlags, PhysicalDevice, SharingMode, SurfaceFormatKHR, SwapchainKHR};
+use ash::vk::{self, CompositeAlphaFlagsKHR, Extent2D, Format, Image, ImageUsageFlags, PhysicalDevice, SharingMode, SwapchainKHR};
 use crate::{LogicalDevice, Surface, instance::Instance, logical_device::find_queue_families};
 use anyhow::Result;
 
@@ -11,7 +11,7 @@ pub struct SwapChain {
 }
 
 impl SwapChain {
-    pub fn new(instance: &Instance, physical_device: &PhysicalDevice, logical_device: &LogicalDevice, surface: &Surface, width: u32, height: u32) -> Result<Self>{
+    pub fn new(instance: &Instance, physical_device: &PhysicalDevice, logical_device: &LogicalDevice, surface: &Surface, width: u32, height: u32, old_swap_chain: Option<&SwapChain>) -> Result<Self>{
         let surface_capabilities = surface.query_surface_capabilities(*physical_device)?;
 
         let best_surface_format = surface_capabilities.find_best_format().expect("Could not find a good surface");
@@ -50,7 +50,8 @@ impl SwapChain {
         create_info.composite_alpha = CompositeAlphaFlagsKHR::OPAQUE;
         create_info.present_mode = best_present_mode;
         create_info.clipped = 1;
-        create_info.old_swapchain = SwapchainKHR::null();
+        // Handing over the old swap chain lets the driver reuse its resources when resizing
+        create_info.old_swapchain = old_swap_chain.map_or(SwapchainKHR::null(), |s| s.swapchain);
 
         let swapchain_loader = ash::khr::swapchain::Device::new(instance.raw(), logical_device.raw());
         let swapchain = unsafe {
@@ -69,6 +70,25 @@ impl SwapChain {
         })
     }
 
+    // Returns the image index, and whether the swap chain no longer matches the surface exactly
+    pub fn acquire_next_image(&self, signal: vk::Semaphore) -> Result<(u32, bool), vk::Result> {
+        unsafe { self.swapchain_loader.acquire_next_image(self.swapchain, u64::MAX, signal, vk::Fence::null()) }
+    }
+
+    // Returns true if the swap chain is suboptimal and should be recreated
+    pub fn present(&self, queue: vk::Queue, image_index: u32, wait: vk::Semaphore) -> Result<bool, vk::Result> {
+        let present_info = vk::PresentInfoKHR {
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &wait,
+            swapchain_count: 1,
+            p_swapchains: &self.swapchain,
+            p_image_indices: &image_index,
+            ..Default::default()
+        };
+
+        unsafe { self.swapchain_loader.queue_present(queue, &present_info) }
+    }
+
     pub fn images(&self) -> &Vec<Image> {
         &self.images
     }
@@ -0,0 +1,76 @@
+use ash::vk::{self, FenceCreateFlags};
+use anyhow::Result;
+
+use crate::LogicalDevice;
+
+pub struct Semaphore {
+    raw: vk::Semaphore,
+    device: ash::Device
+}
+
+impl Semaphore {
+    pub fn new(logical_device: &LogicalDevice) -> Result<Self> {
+        let create_info = vk::SemaphoreCreateInfo::default();
+        let semaphore = unsafe { logical_device.raw().create_semaphore(&create_info, None)? };
+
+        Ok(Self {
+            raw: semaphore,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn raw(&self) -> &vk::Semaphore {
+        &self.raw
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_semaphore(self.raw, None);
+        }
+    }
+}
+
+pub struct Fence {
+    raw: vk::Fence,
+    device: ash::Device
+}
+
+impl Fence {
+    // Start signalled so the first wait of a frame doesn't block forever
+    pub fn new(logical_device: &LogicalDevice, signalled: bool) -> Result<Self> {
+        let create_info = vk::FenceCreateInfo {
+            flags: if signalled { FenceCreateFlags::SIGNALED } else { FenceCreateFlags::empty() },
+            ..Default::default()
+        };
+        let fence = unsafe { logical_device.raw().create_fence(&create_info, None)? };
+
+        Ok(Self {
+            raw: fence,
+            device: logical_device.raw().clone()
+        })
+    }
+
+    pub fn wait(&self) -> Result<()> {
+        unsafe { self.device.wait_for_fences(&[self.raw], true, u64::MAX)? };
+        Ok(())
+    }
+
+    pub fn reset(&self) -> Result<()> {
+        unsafe { self.device.reset_fences(&[self.raw])? };
+        Ok(())
+    }
+
+    pub fn raw(&self) -> &vk::Fence {
+        &self.raw
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_fence(self.raw, None);
+        }
+    }
+}
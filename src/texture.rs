@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use ash::vk::{self, Format, FormatFeatureFlags, ImageUsageFlags, ImageViewType};
+use anyhow::{Error, Result};
+
+use crate::{LogicalDevice, command_pool::CommandPool, image::Image, image_view::ImageView, mipmaps::mip_level_count, sampler::{Sampler, SamplerOptions}, texture_container::TextureContainer};
+
+// How the shader will read the texels decides how they are stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureUsage {
+    // Albedo, emissive and anything else authored by eye, stored in sRGB and linearised when sampled
+    Colour,
+    // Normal, roughness, metalness and similar maps, where the numbers must come through untouched
+    Data,
+}
+
+impl TextureUsage {
+    pub fn format(&self) -> Format {
+        match self {
+            TextureUsage::Colour => Format::R8G8B8A8_SRGB,
+            TextureUsage::Data => Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+pub struct Texture {
+    sampler: Sampler,
+    view: ImageView,
+    image: Image,
+    width: u32,
+    height: u32,
+}
+
+impl Texture {
+    // Decodes a PNG or JPEG file
+    pub fn load<P: AsRef<Path>>(logical_device: &LogicalDevice, command_pool: &CommandPool, path: P, usage: TextureUsage, sampler_options: &SamplerOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let decoded = image::open(path)
+            .map_err(|e| Error::msg(format!("Cannot load texture {}: {e}", path.display())))?
+            .into_rgba8();
+
+        Self::from_rgba8(logical_device, command_pool, decoded.width(), decoded.height(), decoded.as_raw(), usage, sampler_options)
+    }
+
+    // Decodes an in-memory PNG or JPEG, guessing the format from its contents
+    pub fn from_bytes(logical_device: &LogicalDevice, command_pool: &CommandPool, bytes: &[u8], usage: TextureUsage, sampler_options: &SamplerOptions) -> Result<Self> {
+        let decoded = image::load_from_memory(bytes)
+            .map_err(|e| Error::msg(format!("Cannot decode texture: {e}")))?
+            .into_rgba8();
+
+        Self::from_rgba8(logical_device, command_pool, decoded.width(), decoded.height(), decoded.as_raw(), usage, sampler_options)
+    }
+
+    pub fn from_rgba8(logical_device: &LogicalDevice, command_pool: &CommandPool, width: u32, height: u32, pixels: &[u8], usage: TextureUsage, sampler_options: &SamplerOptions) -> Result<Self> {
+        let expected = (width as usize).checked_mul(height as usize).and_then(|texels| texels.checked_mul(4))
+            .ok_or_else(|| Error::msg(format!("A {width}x{height} RGBA texture is too large")))?;
+        if pixels.len() != expected {
+            return Err(Error::msg(format!("Expected {expected} bytes for a {width}x{height} RGBA texture, got {}", pixels.len())));
+        }
+
+        // Without a full mip chain textures shimmer when minified
+        let format = usage.format();
+        let image = Image::from_pixels(logical_device, command_pool, width, height, format, pixels, mip_level_count(width, height))?;
+        // Only sampled, the image may also have storage usage that its format can't do
+        let view = ImageView::with_usage(logical_device, image.raw(), &format, ImageViewType::TYPE_2D, image.full_range(), ImageUsageFlags::SAMPLED)?;
+        let sampler = Sampler::new(logical_device, sampler_options, image.mip_levels())?;
+
+        Ok(Self {
+            sampler,
+            view,
+            image,
+            width,
+            height,
+        })
+    }
+
+    // Loads a KTX2 or DDS file, keeping its format, mip levels, layers and cube faces
+    pub fn load_container<P: AsRef<Path>>(logical_device: &LogicalDevice, command_pool: &CommandPool, path: P, sampler_options: &SamplerOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let container = TextureContainer::load(path)?;
+
+        Self::from_container(logical_device, command_pool, &container, sampler_options)
+            .map_err(|e| Error::msg(format!("Cannot load texture {}: {e}", path.display())))
+    }
+
+    pub fn from_container(logical_device: &LogicalDevice, command_pool: &CommandPool, container: &TextureContainer, sampler_options: &SamplerOptions) -> Result<Self> {
+        check_container_support(logical_device, container)?;
+
+        let image = Image::from_container(logical_device, command_pool, container)?;
+        let view_type = match (container.cube, container.array_layers > 1) {
+            (false, false) => ImageViewType::TYPE_2D,
+            (false, true) => ImageViewType::TYPE_2D_ARRAY,
+            (true, false) => ImageViewType::CUBE,
+            (true, true) => ImageViewType::CUBE_ARRAY,
+        };
+        let view = ImageView::with_range(logical_device, image.raw(), &container.format, view_type, image.full_range())?;
+        let sampler = Sampler::new(logical_device, sampler_options, image.mip_levels())?;
+
+        Ok(Self {
+            sampler,
+            view,
+            image,
+            width: container.width,
+            height: container.height,
+        })
+    }
+
+    pub fn view(&self) -> &ImageView {
+        &self.view
+    }
+
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    pub fn format(&self) -> Format {
+        *self.image.format()
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.image.mip_levels()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+// Catch what the GPU can't sample before creating anything, the validation layers would only complain afterwards
+fn check_container_support(logical_device: &LogicalDevice, container: &TextureContainer) -> Result<()> {
+    let features = logical_device.enabled_features();
+
+    let needs_bc = (vk::Format::BC1_RGB_UNORM_BLOCK.as_raw()..=vk::Format::BC7_SRGB_BLOCK.as_raw()).contains(&container.format.as_raw());
+    if needs_bc && features.texture_compression_bc == vk::FALSE {
+        return Err(Error::msg(format!(
+            "{:?} needs the textureCompressionBC device feature, which this GPU does not support", container.format
+        )));
+    }
+
+    if container.cube && container.array_layers > 1 && features.image_cube_array == vk::FALSE {
+        return Err(Error::msg("Cube map arrays need the imageCubeArray device feature, which this GPU does not support"));
+    }
+
+    let supported = logical_device.format_properties(container.format).optimal_tiling_features;
+    if !supported.contains(FormatFeatureFlags::SAMPLED_IMAGE | FormatFeatureFlags::TRANSFER_DST) {
+        return Err(Error::msg(format!("{:?} cannot be sampled with optimal tiling on this GPU", container.format)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,480 @@
+use std::{fs, path::Path};
+
+use ash::vk::Format;
+use anyhow::{Error, Result};
+
+use crate::mipmaps::mip_level_count;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+// Where one mip level of one layer (or cube face) lives in `TextureContainer::data`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subresource {
+    pub level: u32,
+    // Cube faces count as layers, in +X, -X, +Y, -Y, +Z, -Z order
+    pub layer: u32,
+    pub offset: usize,
+    pub size: usize,
+}
+
+// A pre-built texture, possibly block compressed, with its mip levels, array layers and cube faces
+#[derive(Clone, Debug)]
+pub struct TextureContainer {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub cube: bool,
+    pub subresources: Vec<Subresource>,
+    pub data: Vec<u8>,
+}
+
+impl TextureContainer {
+    // Picks the parser from the file's magic number rather than its extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+
+        Self::parse(&bytes).map_err(|e| Error::msg(format!("{}: {e}", path.display())))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            parse_ktx2(bytes)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            parse_dds(bytes)
+        } else {
+            Err(Error::msg("not a KTX2 or DDS file"))
+        }
+    }
+
+    // Layers including cube faces, which is what the VkImage needs
+    pub fn image_layers(&self) -> u32 {
+        self.array_layers * if self.cube { 6 } else { 1 }
+    }
+
+    pub fn is_block_compressed(&self) -> bool {
+        format_block(self.format).is_some_and(|b| b.width > 1)
+    }
+}
+
+// Checked before the subresources are built, so a bad header can't make them overflow
+fn check_extents(width: u32, height: u32, mip_levels: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(Error::msg(format!("image is {width}x{height}, both sides need to be at least 1")));
+    }
+    if mip_levels > mip_level_count(width, height) {
+        return Err(Error::msg(format!("{mip_levels} mip levels is more than a {width}x{height} image can have")));
+    }
+
+    Ok(())
+}
+
+// Checked as each subresource is found, so the list can never grow bigger than the file allows
+fn check_subresource(format: Format, width: u32, height: u32, data_len: usize, subresource: &Subresource) -> Result<()> {
+    let expected = level_size(format, width, height, subresource.level)?;
+
+    if subresource.size != expected {
+        return Err(Error::msg(format!(
+            "level {} layer {} is {} bytes, expected {expected}", subresource.level, subresource.layer, subresource.size
+        )));
+    }
+    if subresource.offset.checked_add(subresource.size).is_none_or(|end| end > data_len) {
+        return Err(Error::msg(format!("level {} layer {} runs past the end of the file", subresource.level, subresource.layer)));
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatBlock {
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u32,
+}
+
+// Size of the smallest addressable unit of a format. Uncompressed formats have 1x1 blocks
+pub fn format_block(format: Format) -> Option<FormatBlock> {
+    let block = |width, height, bytes| Some(FormatBlock { width, height, bytes });
+
+    match format {
+        Format::R8_UNORM | Format::R8_SNORM | Format::R8_UINT | Format::R8_SRGB => block(1, 1, 1),
+        Format::R8G8_UNORM | Format::R8G8_SNORM | Format::R8G8_UINT | Format::R16_SFLOAT | Format::R16_UNORM => block(1, 1, 2),
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB | Format::R8G8B8A8_SNORM | Format::R8G8B8A8_UINT
+        | Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB | Format::A2B10G10R10_UNORM_PACK32
+        | Format::R16G16_SFLOAT | Format::R32_SFLOAT | Format::B10G11R11_UFLOAT_PACK32 | Format::E5B9G9R9_UFLOAT_PACK32 => block(1, 1, 4),
+        Format::R16G16B16A16_SFLOAT | Format::R16G16B16A16_UNORM | Format::R32G32_SFLOAT => block(1, 1, 8),
+        Format::R32G32B32A32_SFLOAT => block(1, 1, 16),
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_UNORM_BLOCK | Format::BC1_RGBA_SRGB_BLOCK
+        | Format::BC4_UNORM_BLOCK | Format::BC4_SNORM_BLOCK => block(4, 4, 8),
+        Format::BC2_UNORM_BLOCK | Format::BC2_SRGB_BLOCK | Format::BC3_UNORM_BLOCK | Format::BC3_SRGB_BLOCK
+        | Format::BC5_UNORM_BLOCK | Format::BC5_SNORM_BLOCK | Format::BC6H_UFLOAT_BLOCK | Format::BC6H_SFLOAT_BLOCK
+        | Format::BC7_UNORM_BLOCK | Format::BC7_SRGB_BLOCK => block(4, 4, 16),
+        _ => None,
+    }
+}
+
+// Expects `level` to be within the image's mip chain
+fn level_size(format: Format, width: u32, height: u32, level: u32) -> Result<usize> {
+    let block = format_block(format).ok_or_else(|| Error::msg(format!("unsupported format {:?}", format)))?;
+    let width = (width >> level).max(1).div_ceil(block.width) as usize;
+    let height = (height >> level).max(1).div_ceil(block.height) as usize;
+
+    width.checked_mul(height)
+        .and_then(|blocks| blocks.checked_mul(block.bytes as usize))
+        .ok_or_else(|| Error::msg(format!("level {level} is too big to address")))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    offset.checked_add(4)
+        .and_then(|end| bytes.get(offset..end))
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| Error::msg(format!("file truncated at byte {offset}")))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+// Offsets and lengths in the file have to fit in memory to mean anything
+fn read_usize(bytes: &[u8], offset: usize) -> Result<usize> {
+    let value = read_u64(bytes, offset)?;
+    usize::try_from(value).map_err(|_| Error::msg(format!("{value} at byte {offset} is too big to address")))
+}
+
+// https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+fn parse_ktx2(bytes: &[u8]) -> Result<TextureContainer> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?;
+    let supercompression = read_u32(bytes, 44)?;
+
+    if vk_format == 0 {
+        return Err(Error::msg("Basis Universal textures need transcoding, which is not supported"));
+    }
+    if supercompression != 0 {
+        return Err(Error::msg(format!("supercompression scheme {supercompression} is not supported")));
+    }
+    if depth > 1 {
+        return Err(Error::msg("3D textures are not supported"));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(Error::msg(format!("face count must be 1 or 6, found {face_count}")));
+    }
+
+    let format = Format::from_raw(vk_format as i32);
+    if format_block(format).is_none() {
+        return Err(Error::msg(format!("unsupported format {:?}", format)));
+    }
+
+    // Zero levels asks the loader to generate mips, we just use the base level
+    let mip_levels = level_count.max(1);
+    // Zero layers means it is not an array texture
+    let array_layers = layer_count.max(1);
+    let image_layers = array_layers.checked_mul(face_count)
+        .ok_or_else(|| Error::msg(format!("{array_layers} layers of {face_count} faces is too many")))?;
+    // Zero height means a 1D texture
+    let height = height.max(1);
+    check_extents(width, height, mip_levels)?;
+
+    let mut subresources = vec![];
+    for level in 0..mip_levels {
+        // The level index starts after the 48 byte header and 32 bytes of section offsets
+        let entry = 80 + level as usize * 24;
+        let level_offset = read_usize(bytes, entry)?;
+        let level_length = read_usize(bytes, entry + 8)?;
+
+        // Each level holds every layer, and each layer every face
+        let size = level_length / image_layers as usize;
+        for layer in 0..image_layers {
+            let subresource = Subresource {
+                level,
+                layer,
+                // layer * size is at most level_length, only the sum can overflow
+                offset: level_offset.saturating_add(layer as usize * size),
+                size,
+            };
+            check_subresource(format, width, height, bytes.len(), &subresource)?;
+            subresources.push(subresource);
+        }
+    }
+
+    Ok(TextureContainer {
+        format,
+        width,
+        height,
+        mip_levels,
+        array_layers,
+        cube: face_count == 6,
+        subresources,
+        data: bytes.to_vec(),
+    })
+}
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+fn dxgi_format(dxgi: u32) -> Option<Format> {
+    Some(match dxgi {
+        2 => Format::R32G32B32A32_SFLOAT,
+        10 => Format::R16G16B16A16_SFLOAT,
+        11 => Format::R16G16B16A16_UNORM,
+        16 => Format::R32G32_SFLOAT,
+        24 => Format::A2B10G10R10_UNORM_PACK32,
+        26 => Format::B10G11R11_UFLOAT_PACK32,
+        28 => Format::R8G8B8A8_UNORM,
+        29 => Format::R8G8B8A8_SRGB,
+        30 => Format::R8G8B8A8_UINT,
+        31 => Format::R8G8B8A8_SNORM,
+        34 => Format::R16G16_SFLOAT,
+        41 => Format::R32_SFLOAT,
+        49 => Format::R8G8_UNORM,
+        51 => Format::R8G8_SNORM,
+        54 => Format::R16_SFLOAT,
+        56 => Format::R16_UNORM,
+        61 => Format::R8_UNORM,
+        63 => Format::R8_SNORM,
+        67 => Format::E5B9G9R9_UFLOAT_PACK32,
+        71 => Format::BC1_RGBA_UNORM_BLOCK,
+        72 => Format::BC1_RGBA_SRGB_BLOCK,
+        74 => Format::BC2_UNORM_BLOCK,
+        75 => Format::BC2_SRGB_BLOCK,
+        77 => Format::BC3_UNORM_BLOCK,
+        78 => Format::BC3_SRGB_BLOCK,
+        80 => Format::BC4_UNORM_BLOCK,
+        81 => Format::BC4_SNORM_BLOCK,
+        83 => Format::BC5_UNORM_BLOCK,
+        84 => Format::BC5_SNORM_BLOCK,
+        87 => Format::B8G8R8A8_UNORM,
+        91 => Format::B8G8R8A8_SRGB,
+        95 => Format::BC6H_UFLOAT_BLOCK,
+        96 => Format::BC6H_SFLOAT_BLOCK,
+        98 => Format::BC7_UNORM_BLOCK,
+        99 => Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+// Formats from before the DX10 header existed
+fn legacy_dds_format(bytes: &[u8]) -> Result<Format> {
+    let flags = read_u32(bytes, 80)?;
+    let code = read_u32(bytes, 84)?;
+    let bit_count = read_u32(bytes, 88)?;
+    let masks = [read_u32(bytes, 92)?, read_u32(bytes, 96)?, read_u32(bytes, 100)?, read_u32(bytes, 104)?];
+
+    if flags & DDPF_FOURCC != 0 {
+        return match &code.to_le_bytes() {
+            b"DXT1" => Ok(Format::BC1_RGBA_UNORM_BLOCK),
+            b"DXT2" | b"DXT3" => Ok(Format::BC2_UNORM_BLOCK),
+            b"DXT4" | b"DXT5" => Ok(Format::BC3_UNORM_BLOCK),
+            b"ATI1" | b"BC4U" => Ok(Format::BC4_UNORM_BLOCK),
+            b"BC4S" => Ok(Format::BC4_SNORM_BLOCK),
+            b"ATI2" | b"BC5U" => Ok(Format::BC5_UNORM_BLOCK),
+            b"BC5S" => Ok(Format::BC5_SNORM_BLOCK),
+            other => Err(Error::msg(format!("unsupported FourCC '{}'", String::from_utf8_lossy(other)))),
+        };
+    }
+
+    if flags & DDPF_RGB != 0 && bit_count == 32 {
+        let has_alpha = flags & DDPF_ALPHAPIXELS != 0;
+        match masks {
+            [0x000000FF, 0x0000FF00, 0x00FF0000, alpha] if !has_alpha || alpha == 0xFF000000 => return Ok(Format::R8G8B8A8_UNORM),
+            [0x00FF0000, 0x0000FF00, 0x000000FF, alpha] if !has_alpha || alpha == 0xFF000000 => return Ok(Format::B8G8R8A8_UNORM),
+            _ => {},
+        }
+    }
+
+    Err(Error::msg(format!("unsupported pixel format with flags {flags:#x}, {bit_count} bits and masks {masks:x?}")))
+}
+
+// https://learn.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide
+fn parse_dds(bytes: &[u8]) -> Result<TextureContainer> {
+    // Offsets include the 4 byte magic
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let mip_count = read_u32(bytes, 28)?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 { mip_count.max(1) } else { 1 };
+    let mut cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+    let mut array_layers = 1;
+    let mut data_offset = 128;
+
+    let format = if read_u32(bytes, 84)? == four_cc(b"DX10") {
+        let dxgi = read_u32(bytes, 128)?;
+        let dimension = read_u32(bytes, 132)?;
+        let misc_flags = read_u32(bytes, 136)?;
+        array_layers = read_u32(bytes, 140)?.max(1);
+        data_offset += 20;
+
+        if dimension != DDS_DIMENSION_TEXTURE2D {
+            return Err(Error::msg(format!("only 2D textures are supported, resource dimension is {dimension}")));
+        }
+        cube = misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+
+        dxgi_format(dxgi).ok_or_else(|| Error::msg(format!("unsupported DXGI format {dxgi}")))?
+    } else {
+        legacy_dds_format(bytes)?
+    };
+
+    let image_layers = array_layers.checked_mul(if cube { 6 } else { 1 })
+        .ok_or_else(|| Error::msg(format!("{array_layers} cube maps is too many")))?;
+    check_extents(width, height, mip_levels)?;
+
+    // Unlike KTX2, each layer holds its whole mip chain before the next layer starts
+    let mut subresources = vec![];
+    let mut offset = data_offset;
+    for layer in 0..image_layers {
+        for level in 0..mip_levels {
+            let size = level_size(format, width, height, level)?;
+            let subresource = Subresource { level, layer, offset, size };
+            check_subresource(format, width, height, bytes.len(), &subresource)?;
+            subresources.push(subresource);
+            // Fits, the subresource ends inside the file
+            offset += size;
+        }
+    }
+
+    Ok(TextureContainer {
+        format,
+        width,
+        height,
+        mip_levels,
+        array_layers,
+        cube,
+        subresources,
+        data: bytes.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGBA8_UNORM: u32 = 37;
+    const BC1_RGBA_UNORM: u32 = 133;
+
+    // A KTX2 header and level index for one layer of `levels`, each given as (offset, length)
+    fn ktx2(format: u32, width: u32, height: u32, levels: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for field in [format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            bytes.extend(field.to_le_bytes());
+        }
+        // Section offsets, none of which are read
+        bytes.resize(80, 0);
+        for (offset, length) in levels {
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+        }
+        bytes
+    }
+
+    // A DX10 DDS header without any data after it
+    fn dds(dxgi: u32, width: u32, height: u32, mip_count: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 148];
+        let mut put = |offset: usize, value: u32| bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        put(0, four_cc(&DDS_MAGIC));
+        put(8, DDSD_MIPMAPCOUNT);
+        put(12, height);
+        put(16, width);
+        put(28, mip_count);
+        put(84, four_cc(b"DX10"));
+        put(128, dxgi);
+        put(132, DDS_DIMENSION_TEXTURE2D);
+        put(140, 1);
+        bytes
+    }
+
+    #[test]
+    fn parses_ktx2_levels() {
+        // 4x4 and 2x2 rgba8, right after the level index
+        let mut bytes = ktx2(RGBA8_UNORM, 4, 4, &[(128, 64), (192, 16)]);
+        bytes.resize(208, 0xFF);
+
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert_eq!(container.format, Format::R8G8B8A8_UNORM);
+        assert_eq!(container.mip_levels, 2);
+        assert_eq!(container.subresources, [
+            Subresource { level: 0, layer: 0, offset: 128, size: 64 },
+            Subresource { level: 1, layer: 0, offset: 192, size: 16 },
+        ]);
+    }
+
+    #[test]
+    fn parses_dds_blocks() {
+        // 8x8 BC1 is 2x2 blocks of 8 bytes, 4x4 and smaller are one block each
+        let mut bytes = dds(71, 8, 8, 4);
+        bytes.resize(148 + 32 + 8 * 3, 0);
+
+        let container = TextureContainer::parse(&bytes).unwrap();
+
+        assert!(container.is_block_compressed());
+        let sizes: Vec<usize> = container.subresources.iter().map(|s| s.size).collect();
+        assert_eq!(sizes, [32, 8, 8, 8]);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let bytes = ktx2(RGBA8_UNORM, 4, 4, &[(128, 64)]);
+        let error = TextureContainer::parse(&bytes[..30]).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{error}");
+
+        let bytes = dds(28, 4, 4, 1);
+        let error = TextureContainer::parse(&bytes[..100]).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{error}");
+    }
+
+    #[test]
+    fn rejects_data_past_the_end() {
+        let bytes = ktx2(RGBA8_UNORM, 4, 4, &[(128, 64)]);
+        let error = TextureContainer::parse(&bytes).unwrap_err();
+        assert!(error.to_string().contains("past the end"), "{error}");
+
+        // An offset that overflows when the length is added
+        let bytes = ktx2(RGBA8_UNORM, 4, 4, &[(u64::MAX - 8, 64)]);
+        assert!(TextureContainer::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        // Each level would take more bytes than there are addresses
+        let bytes = ktx2(RGBA8_UNORM, u32::MAX, u32::MAX, &[(128, 64)]);
+        assert!(TextureContainer::parse(&bytes).is_err());
+
+        let bytes = dds(2, u32::MAX, u32::MAX, 1);
+        assert!(TextureContainer::parse(&bytes).is_err());
+
+        // More levels than a 4x4 image has
+        let bytes = dds(28, 4, 4, 40);
+        let error = TextureContainer::parse(&bytes).unwrap_err();
+        assert!(error.to_string().contains("mip levels"), "{error}");
+    }
+
+    #[test]
+    fn rejects_zero_extents() {
+        let bytes = ktx2(BC1_RGBA_UNORM, 0, 4, &[(128, 8)]);
+        let error = TextureContainer::parse(&bytes).unwrap_err();
+        assert!(error.to_string().contains("0x4"), "{error}");
+
+        let bytes = dds(28, 4, 0, 1);
+        assert!(TextureContainer::parse(&bytes).is_err());
+    }
+}
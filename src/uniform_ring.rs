@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use ash::vk::{self, BufferUsageFlags, DescriptorSetLayoutBinding, DescriptorType, MemoryPropertyFlags, ShaderStageFlags};
+use anyhow::{Error, Result};
+use bytemuck::Pod;
+
+use crate::{LogicalDevice, buffer::Buffer, descriptors::{DescriptorAllocator, DescriptorSetLayout, DescriptorWriter}};
+
+// What each dynamic offset can see. The smallest maxUniformBufferRange Vulkan allows, so it fits everywhere
+const MAX_UNIFORM_SIZE: vk::DeviceSize = 16384;
+
+const INITIAL_CAPACITY: vk::DeviceSize = 64 * 1024;
+
+// The only binding in the set a pipeline gives to the uniform ring, see GraphicsPipelineBuilder::uniform_ring.
+// Pipelines and the ring create their own layouts from this, which Vulkan treats as compatible as they're identical
+pub fn uniform_ring_binding() -> DescriptorSetLayoutBinding<'static> {
+    DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+        .descriptor_count(1)
+        .stage_flags(ShaderStageFlags::ALL_GRAPHICS)
+}
+
+// Where a pushed value ended up: bind set with offset as its dynamic offset
+#[derive(Clone, Copy, Debug)]
+pub struct UniformAllocation {
+    pub set: vk::DescriptorSet,
+    pub offset: u32,
+}
+
+// Per-frame uniform data, e.g. the camera and each object's transform, packed into one buffer
+// and told apart with dynamic offsets. There's one ring per frame in flight, each only reset once
+// its frame's fence has signalled, so nothing the GPU may still read is written over
+pub struct UniformRing {
+    buffer: Buffer,
+    mapped: *mut u8,
+    set: vk::DescriptorSet,
+    head: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    // Outgrown part way through the frame, but still bound by commands recorded before that
+    retired: Vec<Buffer>,
+    descriptors: DescriptorAllocator,
+    layout: DescriptorSetLayout,
+    logical_device: Arc<LogicalDevice>,
+}
+
+impl UniformRing {
+    pub fn new(logical_device: &Arc<LogicalDevice>) -> Result<Self> {
+        let layout = DescriptorSetLayout::new(logical_device, &[uniform_ring_binding()])?;
+        let mut descriptors = DescriptorAllocator::with_ratios(logical_device, 4, &[(DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0)]);
+        let (buffer, mapped, set) = Self::create_buffer(logical_device, &layout, &mut descriptors, INITIAL_CAPACITY)?;
+
+        Ok(Self {
+            buffer,
+            mapped,
+            set,
+            head: 0,
+            alignment: logical_device.properties().limits.min_uniform_buffer_offset_alignment.max(1),
+            retired: vec![],
+            descriptors,
+            layout,
+            logical_device: logical_device.clone(),
+        })
+    }
+
+    // The buffer is longer than its capacity by the descriptor's range, as every offset handed out
+    // has to have the full range after it
+    fn create_buffer(logical_device: &LogicalDevice, layout: &DescriptorSetLayout, descriptors: &mut DescriptorAllocator, capacity: vk::DeviceSize) -> Result<(Buffer, *mut u8, vk::DescriptorSet)> {
+        let buffer = Buffer::new(
+            logical_device,
+            capacity + MAX_UNIFORM_SIZE,
+            BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT
+        )?;
+
+        let set = descriptors.allocate(layout)?;
+        DescriptorWriter::new()
+            .dynamic_uniform_buffer(0, &buffer, MAX_UNIFORM_SIZE)
+            .update(logical_device, layout, set)?;
+        let mapped = buffer.map()?;
+
+        Ok((buffer, mapped, set))
+    }
+
+    fn capacity(&self) -> vk::DeviceSize {
+        self.buffer.size() - MAX_UNIFORM_SIZE
+    }
+
+    pub fn push<T: Pod>(&mut self, value: &T) -> Result<UniformAllocation> {
+        let bytes = bytemuck::bytes_of(value);
+        let size = bytes.len() as vk::DeviceSize;
+        let placement = place(self.head, size, self.alignment, self.capacity())
+            .map_err(|e| Error::msg(format!("{}: {e}", std::any::type_name::<T>())))?;
+
+        if let Some(capacity) = placement.grow_to {
+            self.grow(capacity)?;
+        }
+        let offset = u32::try_from(placement.offset)
+            .map_err(|_| Error::msg(format!("uniform ring offset {} doesn't fit a dynamic offset", placement.offset)))?;
+
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.mapped.add(offset as usize), bytes.len()) };
+        self.head = placement.offset + size;
+
+        Ok(UniformAllocation {
+            set: self.set,
+            offset,
+        })
+    }
+
+    // Moves to a new buffer, which place makes twice the size. The old one is kept until reset, as draws
+    // recorded earlier in the frame point into it
+    fn grow(&mut self, capacity: vk::DeviceSize) -> Result<()> {
+        let (buffer, mapped, set) = Self::create_buffer(&self.logical_device, &self.layout, &mut self.descriptors, capacity)?;
+        println!("Growing uniform ring to {capacity} bytes");
+
+        self.buffer.unmap();
+        self.retired.push(std::mem::replace(&mut self.buffer, buffer));
+        self.mapped = mapped;
+        self.set = set;
+        self.head = 0;
+
+        Ok(())
+    }
+
+    // Only once the GPU has finished the frame this ring was last used for
+    pub fn reset(&mut self) -> Result<()> {
+        self.head = 0;
+
+        // The sets for outgrown buffers go with them, so the current one needs a new set too
+        if !self.retired.is_empty() {
+            self.retired.clear();
+            self.descriptors.reset()?;
+            self.set = self.descriptors.allocate(&self.layout)?;
+            DescriptorWriter::new()
+                .dynamic_uniform_buffer(0, &self.buffer, MAX_UNIFORM_SIZE)
+                .update(&self.logical_device, &self.layout, self.set)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Where push puts an entry of `size` bytes, given the ring's head and capacity
+#[derive(Debug, PartialEq)]
+struct Placement {
+    offset: vk::DeviceSize,
+    // Move to a buffer of this capacity first, the offset is then into that
+    grow_to: Option<vk::DeviceSize>,
+}
+
+// Any aligned offset up to the capacity will do, even the capacity itself, as the buffer has
+// MAX_UNIFORM_SIZE spare after it for the descriptor's range and so for the entry
+fn place(head: vk::DeviceSize, size: vk::DeviceSize, alignment: vk::DeviceSize, capacity: vk::DeviceSize) -> Result<Placement> {
+    if size > MAX_UNIFORM_SIZE {
+        return Err(Error::msg(format!("{size} bytes is more than the {MAX_UNIFORM_SIZE} a uniform ring entry can be")));
+    }
+
+    let offset = head.next_multiple_of(alignment);
+    if offset <= capacity {
+        return Ok(Placement { offset, grow_to: None });
+    }
+
+    // The new buffer starts empty, so doubling always leaves room for the entry
+    let grown = capacity.checked_mul(2).ok_or_else(|| Error::msg("uniform ring can't grow any further"))?;
+    Ok(Placement { offset: 0, grow_to: Some(grown) })
+}
+
+impl Drop for UniformRing {
+    fn drop(&mut self) {
+        self.buffer.unmap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_offsets_up_to_the_alignment() {
+        assert_eq!(place(0, 64, 256, INITIAL_CAPACITY).unwrap().offset, 0);
+        assert_eq!(place(1, 64, 256, INITIAL_CAPACITY).unwrap().offset, 256);
+        assert_eq!(place(256, 64, 256, INITIAL_CAPACITY).unwrap().offset, 256);
+        assert_eq!(place(257, 64, 64, INITIAL_CAPACITY).unwrap().offset, 320);
+    }
+
+    #[test]
+    fn fills_up_to_the_capacity_before_growing() {
+        // Rounds up to exactly the capacity, which still has the full range after it
+        assert_eq!(place(1000, 64, 256, 1024).unwrap(), Placement { offset: 1024, grow_to: None });
+        assert_eq!(place(1025, 64, 256, 1024).unwrap(), Placement { offset: 0, grow_to: Some(2048) });
+    }
+
+    #[test]
+    fn grows_as_often_as_needed() {
+        let alignment = 256;
+        let mut capacity = INITIAL_CAPACITY;
+        let mut head = 0;
+        let mut capacities = vec![];
+
+        for _ in 0..20 {
+            let placement = place(head, MAX_UNIFORM_SIZE, alignment, capacity).unwrap();
+            if let Some(grown) = placement.grow_to {
+                capacity = grown;
+                capacities.push(capacity);
+            }
+
+            // The descriptor's range, and so the entry, has to fit in the buffer create_buffer makes
+            let buffer_size = capacity + MAX_UNIFORM_SIZE;
+            assert!(placement.offset.is_multiple_of(alignment));
+            assert!(placement.offset + MAX_UNIFORM_SIZE <= buffer_size);
+            head = placement.offset + MAX_UNIFORM_SIZE;
+        }
+
+        // 5 entries fit in 64KiB, 9 in 128KiB, then 256KiB has room for the rest
+        assert_eq!(capacities, [2 * INITIAL_CAPACITY, 4 * INITIAL_CAPACITY]);
+    }
+
+    #[test]
+    fn rejects_oversized_entries() {
+        place(0, MAX_UNIFORM_SIZE, 256, INITIAL_CAPACITY).unwrap();
+
+        let error = place(0, MAX_UNIFORM_SIZE + 1, 256, INITIAL_CAPACITY).unwrap_err();
+        assert!(error.to_string().contains("more than the 16384 a uniform ring entry can be"), "{error}");
+    }
+}